@@ -0,0 +1,156 @@
+//! Compares the two backends of `ringchannel`, the mutex protected `Backend::Queues` and the
+//! lock-free `Backend::Ring`, with one sender and a varying number of receivers each running on
+//! its own thread.
+//!
+//! Two scenarios are measured: in "burst" the sender publishes as fast as it can (receivers will
+//! be lapped and drop messages), in "paced" it publishes one message every `PACED_PERIOD`, which
+//! is closer to what a high rate sensor does.
+//!
+//! Run with `cargo bench --bench ringchannel`.
+
+use std::{
+    hint::black_box,
+    num::NonZero,
+    thread,
+    time::{Duration, Instant},
+};
+
+use quadcopter::utils::{
+    capacity::Capacity,
+    ringchannel::{self, Backend, ChannelError},
+};
+
+const NUM_MESSAGES: usize = 1_000_000;
+const NUM_MESSAGES_PACED: usize = 100_000;
+const PACED_PERIOD: Duration = Duration::from_micros(5);
+const CAPACITY: usize = 64;
+
+/// Roughly the size of an IMU sample: timestamp, accelerometer, gyroscope and magnetometer
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, Default)]
+struct ImuSample {
+    timestamp: i64,
+    data: [f32; 9],
+}
+
+struct BenchResult {
+    send_time: Duration,
+    sent: usize,
+    received: usize,
+}
+
+/// Sends `num_messages` through `send`, waiting `period` between each of them, and returns the
+/// total time spent inside `send`
+fn send_all(num_messages: usize, period: Option<Duration>, send: impl Fn(ImuSample)) -> Duration {
+    let mut send_time = Duration::ZERO;
+    let start = Instant::now();
+
+    for i in 0..num_messages {
+        if let Some(period) = period {
+            let deadline = start + period * i as u32;
+            while Instant::now() < deadline {
+                std::hint::spin_loop();
+            }
+        }
+
+        let t = Instant::now();
+        send(ImuSample {
+            timestamp: i as i64,
+            ..Default::default()
+        });
+        send_time += t.elapsed();
+    }
+
+    send_time
+}
+
+fn run(
+    backend: Backend,
+    num_receivers: usize,
+    num_messages: usize,
+    period: Option<Duration>,
+) -> BenchResult {
+    let (s, r) = ringchannel::channel_with::<ImuSample>(
+        backend,
+        Capacity::Bounded(NonZero::new(CAPACITY).unwrap()),
+    );
+
+    let handles: Vec<_> = (0..num_receivers)
+        .map(|_| {
+            let r = r.clone();
+            thread::spawn(move || {
+                let mut received = 0usize;
+                loop {
+                    match r.recv() {
+                        Ok(v) => {
+                            black_box(v);
+                            received += 1;
+                        }
+                        Err(ChannelError::Closed) => break received,
+                        Err(ChannelError::Empty) => unreachable!(),
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(r);
+
+    let send_time = send_all(num_messages, period, |v| s.send(v));
+    drop(s);
+
+    BenchResult {
+        send_time,
+        sent: num_messages,
+        received: handles.into_iter().map(|h| h.join().unwrap()).sum(),
+    }
+}
+
+fn report(name: &str, scenario: &str, num_receivers: usize, res: BenchResult) {
+    let expected = res.sent * num_receivers;
+    println!(
+        "{:<6} {:<6} receivers={} send={:>8.1} ns/msg delivered={:>5.1}%",
+        name,
+        scenario,
+        num_receivers,
+        res.send_time.as_nanos() as f64 / res.sent as f64,
+        if expected > 0 {
+            res.received as f64 / expected as f64 * 100.0
+        } else {
+            100.0
+        },
+    );
+}
+
+fn main() {
+    let backends = [
+        ("mutex", Backend::Queues),
+        ("ring", Backend::Ring(NonZero::new(CAPACITY).unwrap())),
+    ];
+
+    for num_receivers in [0, 1, 2, 4] {
+        for (name, backend) in backends {
+            report(
+                name,
+                "burst",
+                num_receivers,
+                run(backend, num_receivers, NUM_MESSAGES, None),
+            );
+        }
+    }
+
+    for num_receivers in [1, 2, 4] {
+        for (name, backend) in backends {
+            report(
+                name,
+                "paced",
+                num_receivers,
+                run(
+                    backend,
+                    num_receivers,
+                    NUM_MESSAGES_PACED,
+                    Some(PACED_PERIOD),
+                ),
+            );
+        }
+    }
+}
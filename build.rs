@@ -3,7 +3,12 @@ fn main() -> Result<()> {
     prost_reflect_build::Builder::new()
         .descriptor_pool("crate::DESCRIPTOR_POOL")
         .compile_protos(
-            &["proto/basic.proto", "proto/sensors.proto", "proto/examples.proto"],
+            &[
+                "proto/basic.proto",
+                "proto/sensors.proto",
+                "proto/examples.proto",
+                "proto/parameters.proto",
+            ],
             &["./"],
         )?;
         
@@ -0,0 +1,291 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use anyhow::Result;
+use chrono::TimeDelta;
+use nalgebra::Vector3;
+
+use crate::{
+    core::time::Clock,
+    crater::sim::{
+        atmosphere::{Atmosphere, AtmosphereIsa},
+        rocket::Rocket,
+    },
+    crater_messages::sensors::{FlightEvent, Position, Velocity},
+    nodes::{FtlOrderedExecutor, Node, NodeConfig, NodeContext, NodeManager, StepResult},
+    parameters::ParameterService,
+    telemetry::{TelemetryDispatcher, TelemetryReceiver, TelemetryService, Timestamped},
+    utils::capacity::Capacity,
+};
+
+/// Outcome of a flight of the crater rocket, extracted from its telemetry, in the NED frame of
+/// the launch site
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FlightSummary {
+    pub apogee: f64,
+    pub t_apogee: f64,
+
+    /// North and east coordinates of the point where the rocket crosses the ground on the way
+    /// down, or of the last position if it has not landed
+    pub landing: [f64; 2],
+    pub t_landing: f64,
+    pub landed: bool,
+
+    pub max_mach: f64,
+
+    /// Time of the last telemetry received
+    pub t_end: f64,
+}
+
+impl FlightSummary {
+    /// Names of the columns written by `csv_fields()`
+    pub const CSV_HEADER: [&'static str; 7] = [
+        "apogee_m",
+        "t_apogee_s",
+        "landing_n_m",
+        "landing_e_m",
+        "t_landing_s",
+        "landed",
+        "max_mach",
+    ];
+
+    pub fn csv_fields(&self) -> Vec<String> {
+        vec![
+            self.apogee.to_string(),
+            self.t_apogee.to_string(),
+            self.landing[0].to_string(),
+            self.landing[1].to_string(),
+            self.t_landing.to_string(),
+            self.landed.to_string(),
+            self.max_mach.to_string(),
+        ]
+    }
+}
+
+/// Early-termination condition, checked at every step on the summary of the flight so far. The
+/// simulation stops as soon as it returns true.
+pub type StopCondition = Arc<dyn Fn(&FlightSummary) -> bool + Send + Sync>;
+
+/// Runs one headless simulation of the crater rocket with `params`, until it lands, reaches
+/// `/sim/max_t` or meets the `stop` condition
+pub fn simulate(params: &ParameterService, stop: Option<StopCondition>) -> Result<FlightSummary> {
+    let ts = TelemetryService::default();
+    let mut nm = NodeManager::new(
+        ts,
+        params.clone(),
+        HashMap::from([
+            ("rocket".to_string(), NodeConfig::default()),
+            ("recorder".to_string(), NodeConfig::default()),
+        ]),
+    );
+
+    let summary = Arc::new(Mutex::new(FlightSummary::default()));
+
+    // The recorder steps after the rocket, so it sees the telemetry of the current step
+    nm.add_node("rocket", |ctx| Ok(Box::new(Rocket::new("crater", ctx)?)))?;
+    nm.add_node("recorder", |ctx| {
+        Ok(Box::new(FlightRecorder::new(ctx, summary.clone(), stop)?))
+    })?;
+
+    let dt = (params.get_f64("/sim/dt")? * 1000000.0) as i64;
+    FtlOrderedExecutor::run_blocking(nm, TimeDelta::microseconds(dt))?;
+
+    let summary = *summary.lock().unwrap();
+    Ok(summary)
+}
+
+/// Runs `f` on every job, on `threads` threads, returning the results in the order of the jobs
+pub fn run_parallel<J: Sync, R: Send>(
+    jobs: &[J],
+    threads: usize,
+    f: impl Fn(usize, &J) -> Result<R> + Sync,
+) -> Result<Vec<R>> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(jobs.len()));
+
+    thread::scope(|s| -> Result<()> {
+        let workers: Vec<_> = (0..threads.clamp(1, jobs.len().max(1)))
+            .map(|_| {
+                s.spawn(|| -> Result<()> {
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(job) = jobs.get(i) else {
+                            return Ok(());
+                        };
+
+                        let res = f(i, job)?;
+                        results.lock().unwrap().push((i, res));
+                    }
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .try_for_each(|w| w.join().expect("Batch worker panicked"))
+    })?;
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _)| *i);
+
+    Ok(results.into_iter().map(|(_, r)| r).collect())
+}
+
+/// Receives the trajectory of the rocket and keeps its summary up to date
+struct FlightRecorder {
+    rcv_pos: TelemetryReceiver<Position>,
+    rcv_vel: TelemetryReceiver<Velocity>,
+    rcv_events: TelemetryReceiver<FlightEvent>,
+    atmosphere: AtmosphereIsa,
+
+    summary: Arc<Mutex<FlightSummary>>,
+    last: Option<(f64, Vector3<f64>)>,
+    stop: Option<StopCondition>,
+}
+
+impl FlightRecorder {
+    fn new(
+        ctx: NodeContext,
+        summary: Arc<Mutex<FlightSummary>>,
+        stop: Option<StopCondition>,
+    ) -> Result<Self> {
+        *summary.lock().unwrap() = FlightSummary {
+            apogee: f64::NEG_INFINITY,
+            ..Default::default()
+        };
+
+        Ok(FlightRecorder {
+            rcv_pos: ctx
+                .telemetry()
+                .subscribe("/rocket/position", Capacity::Unbounded)?,
+            rcv_vel: ctx
+                .telemetry()
+                .subscribe("/rocket/velocity_ned", Capacity::Unbounded)?,
+            rcv_events: ctx
+                .telemetry()
+                .subscribe("/rocket/events", Capacity::Unbounded)?,
+            atmosphere: AtmosphereIsa::default(),
+            summary,
+            last: None,
+            stop,
+        })
+    }
+
+    fn record(
+        &mut self,
+        summary: &mut FlightSummary,
+        t: f64,
+        pos_n: Vector3<f64>,
+        vel_n: Vector3<f64>,
+    ) {
+        let h = -pos_n[2];
+
+        if h > summary.apogee {
+            summary.apogee = h;
+            summary.t_apogee = t;
+        }
+
+        let mach = vel_n.norm() / self.atmosphere.speed_of_sound(h);
+        summary.max_mach = summary.max_mach.max(mach);
+
+        if !summary.landed {
+            summary.landing = [pos_n[0], pos_n[1]];
+            summary.t_landing = t;
+
+            // Crossing the ground on the way down, interpolated between the two steps
+            if let Some((t0, p0)) = self.last.filter(|(_, p0)| p0[2] < 0.0 && pos_n[2] >= 0.0) {
+                let k = -p0[2] / (pos_n[2] - p0[2]);
+                let p = p0 + (pos_n - p0) * k;
+
+                summary.landing = [p[0], p[1]];
+                summary.t_landing = t0 + (t - t0) * k;
+                summary.landed = true;
+            }
+        }
+
+        summary.t_end = t;
+        self.last = Some((t, pos_n));
+    }
+
+    /// Events are located exactly by the rocket, so they replace the values found from the
+    /// trajectory, which are only known at each step
+    fn record_event(summary: &mut FlightSummary, event: &FlightEvent) {
+        let t = event.timestamp as f64 / 1e9;
+        let pos_n: Vector3<f64> = event.pos.into();
+
+        match event.name.as_str() {
+            "apogee" => {
+                summary.apogee = -pos_n[2];
+                summary.t_apogee = t;
+            }
+            "impact" => {
+                summary.landing = [pos_n[0], pos_n[1]];
+                summary.t_landing = t;
+                summary.landed = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Node for FlightRecorder {
+    fn step(&mut self, _: usize, _: TimeDelta, _: &dyn Clock) -> Result<StepResult> {
+        let summary = self.summary.clone();
+        let mut summary = summary.lock().unwrap();
+
+        // Positions and velocities are sent together at every step
+        while let (Ok(Timestamped(_, pos)), Ok(Timestamped(_, vel))) =
+            (self.rcv_pos.try_recv(), self.rcv_vel.try_recv())
+        {
+            let t = pos.timestamp as f64 / 1e9;
+            self.record(&mut summary, t, pos.pos.into(), vel.vel.into());
+        }
+
+        while let Ok(Timestamped(_, event)) = self.rcv_events.try_recv() {
+            Self::record_event(&mut summary, &event);
+        }
+
+        match &self.stop {
+            Some(stop) if stop(&summary) => Ok(StepResult::Stop),
+            _ => Ok(StepResult::Continue),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_early_stop() -> Result<()> {
+        let mut params = ParameterService::from_toml_files(&["config/crater/params.toml"])?;
+        params.apply_overrides(&["/sim/dt=0.02"])?;
+
+        let full = simulate(&params, None)?;
+        assert!(full.landed);
+        assert!(full.apogee > 0.0 && full.t_apogee < full.t_landing);
+        assert!(full.max_mach > 0.0);
+
+        // The impact is located within the last step, which integrates from t_end to t_end + dt
+        assert!(full.t_landing > full.t_end && full.t_landing <= full.t_end + 0.02);
+
+        // Only up to the apogee
+        let stop: StopCondition = Arc::new(|s| s.t_end > s.t_apogee + 1.0);
+        let partial = simulate(&params, Some(stop))?;
+
+        assert!(!partial.landed);
+        assert_eq!(partial.apogee, full.apogee);
+        assert!(partial.t_end < full.t_end);
+
+        let squares = run_parallel(&[1, 2, 3, 4, 5], 3, |i, j| Ok((i, j * j)))?;
+        assert_eq!(squares, vec![(0, 1), (1, 4), (2, 9), (3, 16), (4, 25)]);
+
+        Ok(())
+    }
+}
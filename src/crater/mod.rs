@@ -1 +1,4 @@
-pub mod sim;
\ No newline at end of file
+pub mod batch;
+pub mod montecarlo;
+pub mod sim;
+pub mod sweep;
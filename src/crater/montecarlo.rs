@@ -0,0 +1,227 @@
+use std::{collections::BTreeMap, fmt::Display, io::Write};
+
+use anyhow::{Context, Result};
+
+use super::batch::{run_parallel, simulate, FlightSummary};
+use crate::parameters::{Parameter, ParameterSamples, ParameterService};
+
+/// Headless batch of simulations of the crater rocket, each with the parameters sampled from
+/// their distributions with a different seed
+#[derive(Debug, Clone)]
+pub struct MonteCarlo {
+    pub runs: usize,
+
+    /// Run `i` is sampled with seed `seed + i`, so any run can be reproduced on its own
+    pub seed: u64,
+
+    /// Number of simulations running in parallel
+    pub threads: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunSummary {
+    pub run: usize,
+    pub samples: ParameterSamples,
+    pub flight: FlightSummary,
+}
+
+impl MonteCarlo {
+    /// Runs the batch with copies of `params`, returning the summaries sorted by run
+    pub fn run(&self, params: &ParameterService) -> Result<Vec<RunSummary>> {
+        let runs: Vec<_> = (0..self.runs).collect();
+
+        run_parallel(&runs, self.threads, |_, &run| {
+            let seed = self.seed.wrapping_add(run as u64);
+            run_single(params, run, seed).with_context(|| format!("Run {run} (seed {seed}) failed"))
+        })
+    }
+}
+
+/// Runs one simulation with a copy of the parameters, sampled with `seed`
+pub fn run_single(params: &ParameterService, run: usize, seed: u64) -> Result<RunSummary> {
+    let mut params = params.deep_copy();
+    let samples = params.sample(seed)?;
+
+    Ok(RunSummary {
+        run,
+        samples,
+        flight: simulate(&params, None)?,
+    })
+}
+
+/// Writes one line per run: the summary, then the sampled parameters. Lists are written as one
+/// column per element, named `path[i]`.
+pub fn write_csv(mut out: impl Write, summaries: &[RunSummary]) -> Result<()> {
+    let mut columns = BTreeMap::new();
+    for summary in summaries {
+        for (path, value) in &summary.samples.values {
+            let len = value.as_list().map(|l| l.len());
+            columns.entry(path.clone()).or_insert(len);
+        }
+    }
+
+    let mut header: Vec<_> = ["run", "seed"]
+        .into_iter()
+        .chain(FlightSummary::CSV_HEADER)
+        .map(String::from)
+        .collect();
+
+    for (path, len) in &columns {
+        match len {
+            Some(len) => header.extend((0..*len).map(|i| format!("{path}[{i}]"))),
+            None => header.push(path.clone()),
+        }
+    }
+    writeln!(out, "{}", header.join(","))?;
+
+    for s in summaries {
+        let mut row = vec![s.run.to_string(), s.samples.seed.to_string()];
+        row.extend(s.flight.csv_fields());
+
+        for (path, len) in &columns {
+            let value = s.samples.values.get(path);
+            match (value, len) {
+                (Some(Parameter::List(l)), Some(len)) => {
+                    row.extend((0..*len).map(|i| l.get(i).map(csv_value).unwrap_or_default()))
+                }
+                (Some(p), None) => row.push(csv_value(p)),
+                (_, len) => row.extend((0..len.unwrap_or(1)).map(|_| String::new())),
+            }
+        }
+
+        writeln!(out, "{}", row.join(","))?;
+    }
+
+    Ok(())
+}
+
+fn csv_value(param: &Parameter) -> String {
+    match param {
+        Parameter::F64(v) => v.to_string(),
+        Parameter::F32(v) => v.to_string(),
+        p => format!("\"{}\"", p.to_string().replace('"', "\"\"")),
+    }
+}
+
+/// Mean and standard deviation of the apogee and landing point over a batch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchStatistics {
+    pub runs: usize,
+    pub apogee: (f64, f64),
+    pub t_apogee: (f64, f64),
+    pub landing_n: (f64, f64),
+    pub landing_e: (f64, f64),
+    pub max_mach: (f64, f64),
+}
+
+impl BatchStatistics {
+    /// `None` for less than two runs, whose standard deviation is not defined
+    pub fn new(summaries: &[RunSummary]) -> Option<Self> {
+        if summaries.len() < 2 {
+            return None;
+        }
+
+        let stat = |f: fn(&RunSummary) -> f64| {
+            let n = summaries.len() as f64;
+            let mean = summaries.iter().map(f).sum::<f64>() / n;
+            let var = summaries.iter().map(|s| (f(s) - mean).powi(2)).sum::<f64>() / (n - 1.0);
+
+            (mean, var.sqrt())
+        };
+
+        Some(BatchStatistics {
+            runs: summaries.len(),
+            apogee: stat(|s| s.flight.apogee),
+            t_apogee: stat(|s| s.flight.t_apogee),
+            landing_n: stat(|s| s.flight.landing[0]),
+            landing_e: stat(|s| s.flight.landing[1]),
+            max_mach: stat(|s| s.flight.max_mach),
+        })
+    }
+}
+
+impl Display for BatchStatistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} runs, mean ± standard deviation:", self.runs)?;
+
+        for (name, (mean, std), unit) in [
+            ("apogee", self.apogee, "m"),
+            ("time of apogee", self.t_apogee, "s"),
+            ("landing north", self.landing_n, "m"),
+            ("landing east", self.landing_e, "m"),
+            ("max mach", self.max_mach, ""),
+        ] {
+            writeln!(f, "  {name:<15} {mean:>10.2} ± {std:.2} {unit}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_monte_carlo() -> Result<()> {
+        let mut params = ParameterService::from_toml_files(&["config/crater/params.toml"])?;
+        params.apply_overrides(&["/sim/dt=0.02"])?;
+
+        // Dispersed launch direction
+        let mut azimuth = params.schema("/sim/rocket/crater/init/azimuth")?.unwrap();
+        azimuth.dist = Some(crate::parameters::Distribution::Uniform {
+            low: 0.0,
+            high: 360.0,
+        });
+        params.set_schema("/sim/rocket/crater/init/azimuth", azimuth)?;
+
+        let mc = MonteCarlo {
+            runs: 4,
+            seed: 10,
+            threads: 2,
+        };
+
+        let summaries = mc.run(&params)?;
+        assert_eq!(
+            summaries.iter().map(|s| s.run).collect::<Vec<_>>(),
+            [0, 1, 2, 3]
+        );
+        assert_eq!(summaries[1].samples.seed, 11);
+
+        for s in summaries.iter().map(|s| &s.flight) {
+            assert!(s.landed && s.apogee > 0.0);
+            assert!(s.t_apogee > 0.0 && s.t_apogee < s.t_landing);
+            assert!(s.max_mach > 0.0);
+        }
+
+        // Reproducible run by run, whatever the thread that ran them
+        assert_eq!(run_single(&params, 2, 12)?, summaries[2]);
+
+        // The launch direction changes the landing point, but not the apogee
+        let (first, second) = (&summaries[0].flight, &summaries[1].flight);
+        assert_ne!(first.landing, second.landing);
+        assert!((first.apogee - second.apogee).abs() < 1.0);
+
+        let mut csv = vec![];
+        write_csv(&mut csv, &summaries)?;
+        let csv = String::from_utf8(csv)?;
+        let lines: Vec<_> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[0],
+            "run,seed,apogee_m,t_apogee_s,landing_n_m,landing_e_m,t_landing_s,landed,max_mach,\
+            /sim/rocket/crater/init/azimuth"
+        );
+        assert!(lines[1].starts_with("0,10,"));
+
+        let stats = BatchStatistics::new(&summaries).unwrap();
+        assert_eq!(stats.runs, 4);
+        assert!(stats.landing_n.1 > 0.0);
+
+        assert_eq!(BatchStatistics::new(&summaries[..1]), None);
+        assert_eq!(BatchStatistics::new(&[]), None);
+
+        Ok(())
+    }
+}
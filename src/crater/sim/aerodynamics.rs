@@ -2,12 +2,17 @@ use core::f64;
 use anyhow::Result;
 use nalgebra::{vector, Vector3};
 use num_traits::Pow;
+use serde::Deserialize;
 
-use crate::parameters::ParameterService;
+use crate::{
+    math::rigid_body::{ForceContributor, RigidBody, RigidBodyState, Wrench},
+    parameters::ParameterService,
+};
 
 use super::atmosphere::Atmosphere;
 
 #[allow(nonstandard_style)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Coefficients {
     cA_0: f64,
     cA_a: f64,
@@ -26,21 +31,7 @@ pub struct Coefficients {
 
 impl Coefficients {
     pub fn from_params(basepath: &str, params: &ParameterService) -> Result<Self> {
-        Ok(Self {
-            cA_0: params.get_f64(format!("{basepath}/aero/cA_0").as_str())?,
-            cA_a: params.get_f64(format!("{basepath}/aero/cA_a").as_str())?,
-            cA_b: params.get_f64(format!("{basepath}/aero/cA_b").as_str())?,
-            cY_b: params.get_f64(format!("{basepath}/aero/cY_b").as_str())?,
-            cY_r: params.get_f64(format!("{basepath}/aero/cY_r").as_str())?,
-            cN_a: params.get_f64(format!("{basepath}/aero/cN_a").as_str())?,
-            cN_q: params.get_f64(format!("{basepath}/aero/cN_q").as_str())?,
-            cl_0: params.get_f64(format!("{basepath}/aero/cl_0").as_str())?,
-            cl_p: params.get_f64(format!("{basepath}/aero/cl_p").as_str())?,
-            cm_a: params.get_f64(format!("{basepath}/aero/cm_a").as_str())?,
-            cm_q: params.get_f64(format!("{basepath}/aero/cm_q").as_str())?,
-            cn_b: params.get_f64(format!("{basepath}/aero/cn_b").as_str())?,
-            cn_r: params.get_f64(format!("{basepath}/aero/cn_r").as_str())?,
-        })
+        Ok(params.get_struct(format!("{basepath}/aero").as_str())?)
     }
 }
 
@@ -65,6 +56,16 @@ impl AeroState {
             h,
         }
     }
+
+    /// State of a body in still air, at its altitude
+    pub fn still_air(state: &RigidBodyState) -> AeroState {
+        AeroState::new(
+            state.vel_b(),
+            Vector3::zeros(),
+            state.angvel_b().clone_owned(),
+            -state.pos_n()[2],
+        )
+    }
 }
 
 pub struct AerodynamicsResult {
@@ -186,3 +187,16 @@ impl Aerodynamics {
         (f, t)
     }
 }
+
+/// Aerodynamic actions in still air, at the altitude of the body
+impl ForceContributor for Aerodynamics {
+    fn wrench(&self, _: f64, state: &RigidBodyState, _: &RigidBody) -> Wrench {
+        let aero = self.calc(&AeroState::still_air(state));
+
+        Wrench {
+            force_b: aero.forces,
+            moment_b: aero.moments,
+            ..Default::default()
+        }
+    }
+}
@@ -2,8 +2,12 @@ pub trait Atmosphere {
     fn pressure(&self, h: f64) -> f64;
     fn density(&self, h: f64) -> f64;
     fn temperature(&self, h: f64) -> f64;
+    fn speed_of_sound(&self, h: f64) -> f64;
 }
 
+/// Ratio of specific heats of air
+const GAMMA_AIR: f64 = 1.4;
+
 pub struct AtmosphereIsa {
     pressure_0: f64,
     temperature_0: f64,
@@ -66,6 +70,10 @@ impl Atmosphere for AtmosphereIsa {
         let t = self.temperature(h);
         (t / self.temperature_0).powf(exponent) * self.density_0
     }
+
+    fn speed_of_sound(&self, h: f64) -> f64 {
+        (GAMMA_AIR * self.specific_gas_constant * self.temperature(h)).sqrt()
+    }
 }
 
 #[cfg(test)]
@@ -106,4 +114,12 @@ mod tests {
         assert_relative_eq!(isa.density(4572.0), 0.7708, epsilon = 0.0001);
         assert_relative_eq!(isa.density(10668.0), 0.3796, epsilon = 0.0001);
     }
+
+    #[test]
+    fn test_default_isa_speed_of_sound() {
+        let isa = AtmosphereIsa::default();
+
+        assert_relative_eq!(isa.speed_of_sound(0.0), 340.29, epsilon = 0.01);
+        assert_relative_eq!(isa.speed_of_sound(10668.0), 296.54, epsilon = 0.01);
+    }
 }
@@ -1,8 +1,25 @@
 use nalgebra::Vector3;
 
+use crate::math::rigid_body::{ForceContributor, RigidBody, RigidBodyState, Wrench};
+
 pub trait RocketEngine {
     /// Thrust of the rocket at time tburn, in the body frame
     fn thrust_b(&self, t: f64) -> Vector3<f64>;
+
+    /// Time at which the engine stops producing thrust
+    fn burnout_time(&self) -> f64;
+}
+
+/// Thrust of an engine as a force contributor, applied along the body axes
+pub struct EngineThrust<'a>(pub &'a dyn RocketEngine);
+
+impl ForceContributor for EngineThrust<'_> {
+    fn wrench(&self, t: f64, _: &RigidBodyState, _: &RigidBody) -> Wrench {
+        Wrench {
+            force_b: self.0.thrust_b(t),
+            ..Default::default()
+        }
+    }
 }
 
 pub struct SimpleRocketEngine {
@@ -31,4 +48,8 @@ impl RocketEngine for SimpleRocketEngine {
             Vector3::zeros()
         }
     }
+
+    fn burnout_time(&self) -> f64 {
+        self.duration
+    }
 }
@@ -1,26 +1,30 @@
 use core::f64;
 
 use crate::{
-    core::time::{Clock, Timestamp, TD},
-    crater::sim::engine::SimpleRocketEngine,
+    core::{
+        path::Path,
+        time::{Clock, Timestamp, TD},
+    },
+    crater::sim::engine::{EngineThrust, SimpleRocketEngine},
     crater_messages::{
         basic::Vec3,
         sensors::{
-            AeroAngles, AeroForces, AngularVelocity, EulerAngles, OrientationQuat, Position,
-            Thrust, Velocity,
+            AeroAngles, AeroForces, AngularVelocity, EulerAngles, FlightEvent, OrientationQuat,
+            Position, Thrust, Velocity,
         },
     },
-    math::ode::{OdeProblem, OdeSolver, RungeKutta4},
+    math::{
+        ode::{Crossing, EventHit, OdeEvent, OdeEvents, OdeProblem, OdeSolver, RungeKutta4},
+        rigid_body::{ForceContributor, Gravity, RigidBody, RigidBodyState},
+    },
     nodes::{Node, NodeContext, NodeTelemetry, StepResult},
-    parameters::ParameterService,
+    parameters::{Parameter, ParameterService},
     telemetry::{TelemetryDispatcher, TelemetrySender},
 };
 use anyhow::{anyhow, Result};
 use chrono::TimeDelta;
-use nalgebra::{
-    vector, Matrix3, Quaternion, SVector, UnitQuaternion, Vector3, Vector4, VectorView,
-    VectorViewMut, U1, U13, U3, U4,
-};
+use nalgebra::{vector, Matrix3, SVector, UnitQuaternion, Vector3};
+use serde::Deserialize;
 
 use super::{
     aerodynamics::{AeroState, Aerodynamics, Coefficients},
@@ -28,22 +32,56 @@ use super::{
     engine::RocketEngine,
 };
 
+/// Events located during the integration, published on `/rocket/events` with their name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RocketEvent {
+    RailExit,
+    Burnout,
+    Apogee,
+    Impact,
+}
+
+/// Events of the rocket, in the order of `OdeEvents::events()`
+const EVENTS: [RocketEvent; 4] = [
+    RocketEvent::RailExit,
+    RocketEvent::Burnout,
+    RocketEvent::Apogee,
+    RocketEvent::Impact,
+];
+
+impl RocketEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            RocketEvent::RailExit => "rail_exit",
+            RocketEvent::Burnout => "burnout",
+            RocketEvent::Apogee => "apogee",
+            RocketEvent::Impact => "impact",
+        }
+    }
+
+    fn descriptor(&self) -> OdeEvent {
+        OdeEvent {
+            crossing: Crossing::Rising,
+            terminal: *self == RocketEvent::Impact,
+        }
+    }
+}
+
 pub struct Rocket {
     engine: Box<dyn RocketEngine + Send>,
     params: Params,
     aerodynamics: Aerodynamics,
-    state: State,
+    state: RigidBodyState,
     senders: Senders,
 }
 
 struct Params {
-    mass: f64,
-    inertia: Matrix3<f64>,
-    inv_inertia: Matrix3<f64>,
+    body: RigidBody,
     p0_n: Vector3<f64>,
     v0_b: Vector3<f64>,
     w0_b: Vector3<f64>,
-    g_n: Vector3<f64>,
+    rail_length: f64,
+    gravity: Gravity,
     diameter: f64,
     surface: f64,
     max_t: f64,
@@ -51,115 +89,79 @@ struct Params {
     elevation: f64,
 }
 
+/// Rocket parameters as written in the parameter files
+#[derive(Debug, Deserialize)]
+struct RawParams {
+    mass: f64,
+    diameter: f64,
+    g_n: [f64; 3],
+    init: RawInitParams,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawInitParams {
+    p0_n: [f64; 3],
+    v0_b: [f64; 3],
+    rail_length: f64,
+}
+
 impl Params {
     fn from_service(path: &str, param_service: &ParameterService) -> Result<Self> {
-        let inertia = param_service.get_vec_f64(format!("{path}/inertia").as_str())?;
-
-        let inertia = Matrix3::from_diagonal(&Vector3::from_column_slice(&inertia));
-        let inv_inertia = inertia
-            .try_inverse()
+        let raw: RawParams = param_service.get_struct(path)?;
+
+        // Either the 3 principal moments of inertia or the full tensor, as 3 rows or 9 values
+        let inertia_path = format!("{path}/inertia");
+        let is_diagonal = param_service
+            .get(&Path::from_str(&inertia_path)?)
+            .and_then(|p| p.as_list().cloned())
+            .is_some_and(|l| l.len() == 3 && !l.iter().any(Parameter::is_list));
+        let inertia = if is_diagonal {
+            Matrix3::from_diagonal(&param_service.get_vector3(&inertia_path)?)
+        } else {
+            param_service.get_matrix3(&inertia_path)?
+        };
+        let body = RigidBody::new(raw.mass, inertia)
             .ok_or(anyhow!("The intertia matrix is not invertible"))?;
 
-        let diameter = param_service.get_f64(format!("{path}/diameter").as_str())?;
-        let surface = f64::consts::PI * (diameter / 2.0).powf(2.0);
+        let surface = f64::consts::PI * (raw.diameter / 2.0).powf(2.0);
 
-        let p0_n = param_service.get_vec_f64(format!("{path}/init/p0_n").as_str())?;
-        let p0_n = Vector3::from_column_slice(&p0_n);
-
-        let v0_b = param_service.get_vec_f64(format!("{path}/init/v0_b").as_str())?;
-        let v0_b = Vector3::from_column_slice(&v0_b);
-
-        let mut w0_b = param_service.get_vec_f64(format!("{path}/init/w0_b_deg").as_str())?;
-        w0_b.iter_mut().for_each(|w| *w = w.to_radians());
-        let w0_b = Vector3::from_column_slice(&w0_b);
-
-        let g_n = param_service.get_vec_f64(format!("{path}/g_n").as_str())?;
-        let g_n = Vector3::from_column_slice(&g_n);
+        // Angles are converted from the unit they are written in
+        let init_path = format!("{path}/init");
 
         Ok(Params {
-            mass: param_service.get_f64(format!("{path}/mass").as_str())?,
-            inertia,
-            inv_inertia,
-            p0_n,
-            v0_b,
-            w0_b,
-            g_n,
-            diameter,
+            body,
+            p0_n: Vector3::from(raw.init.p0_n),
+            v0_b: Vector3::from(raw.init.v0_b),
+            w0_b: param_service.get_vector3_in(&format!("{init_path}/w0_b_deg"), "rad/s")?,
+            rail_length: raw.init.rail_length,
+            gravity: Gravity {
+                g_n: Vector3::from(raw.g_n),
+            },
+            diameter: raw.diameter,
             surface,
-            max_t: param_service.get_f64(format!("/sim/max_t").as_str())?,
-            azimuth: param_service
-                .get_f64(format!("{path}/init/azimuth").as_str())?
-                .to_radians(),
-            elevation: param_service
-                .get_f64(format!("{path}/init/elevation").as_str())?
-                .to_radians(),
+            max_t: param_service.get_f64("/sim/max_t")?,
+            azimuth: param_service.get_f64_in(&format!("{init_path}/azimuth"), "rad")?,
+            elevation: param_service.get_f64_in(&format!("{init_path}/elevation"), "rad")?,
         })
     }
-}
-
-#[derive(Debug, Default)]
-struct State(SVector<f64, 13>);
-
-impl State {
-    fn from_params(params: &Params) -> Self {
-        let q_nb = UnitQuaternion::from_euler_angles(0.0, params.elevation, params.azimuth);
-
-        let mut state: SVector<f64, 13> = SVector::zeros();
-
-        let mut p_view = state.fixed_rows_mut::<3>(0);
-        p_view.set_column(0, &params.p0_n);
-
-        let mut v_view = state.fixed_rows_mut::<3>(3);
-        v_view.set_column(0, &q_nb.transform_vector(&params.v0_b));
-
-        let mut q_view = state.fixed_rows_mut::<4>(6);
-        q_view.set_column(0, q_nb.as_vector());
 
-        let mut w_view = state.fixed_rows_mut::<3>(10);
-        w_view.set_column(0, &params.w0_b);
-
-        Self(state)
-    }
-
-    fn pos_n(&self) -> VectorView<'_, f64, U3, U1, U13> {
-        self.0.fixed_rows::<3>(0)
-    }
-
-    fn vel_n(&self) -> VectorView<'_, f64, U3, U1, U13> {
-        self.0.fixed_rows::<3>(3)
-    }
-
-    fn quat_nb_vec(&self) -> VectorView<'_, f64, U4, U1, U13> {
-        self.0.fixed_rows::<4>(6)
-    }
-
-    fn angvel_b(&self) -> VectorView<'_, f64, U3, U1, U13> {
-        self.0.fixed_rows::<3>(10)
-    }
-
-    fn pos_n_mut(&mut self) -> VectorViewMut<'_, f64, U3, U1, U13> {
-        self.0.fixed_rows_mut::<3>(0)
-    }
-    fn vel_n_mut(&mut self) -> VectorViewMut<'_, f64, U3, U1, U13> {
-        self.0.fixed_rows_mut::<3>(3)
-    }
-
-    fn quat_nb_vec_mut(&mut self) -> VectorViewMut<'_, f64, U4, U1, U13> {
-        self.0.fixed_rows_mut::<4>(6)
-    }
-
-    fn angvel_b_mut(&mut self) -> VectorViewMut<'_, f64, U3, U1, U13> {
-        self.0.fixed_rows_mut::<3>(10)
+    /// Distance traveled from the launch point beyond the end of the rail. The rail does not
+    /// constrain the motion, it only marks when the rocket leaves it.
+    fn past_rail(&self, state: &RigidBodyState) -> f64 {
+        (state.pos_n() - self.p0_n).norm() - self.rail_length
     }
+}
 
-    fn quat_nb(&self) -> UnitQuaternion<f64> {
-        UnitQuaternion::from_quaternion(Quaternion::from_vector(self.quat_nb_vec().clone_owned()))
-    }
+/// Initial state of the rocket, launched from the ramp
+fn initial_state(params: &Params) -> RigidBodyState {
+    let q_nb = UnitQuaternion::from_euler_angles(0.0, params.elevation, params.azimuth);
 
-    fn normalize_quat(&mut self) {
-        let n = self.quat_nb_vec().normalize();
-        self.quat_nb_vec_mut().set_column(0, &n);
-    }
+    RigidBodyState::new(
+        &params.p0_n,
+        &q_nb.transform_vector(&params.v0_b),
+        &q_nb,
+        &params.w0_b,
+    )
 }
 
 impl Rocket {
@@ -184,7 +186,7 @@ impl Rocket {
 
         let params = Params::from_service(&param_path, &ctx.parameters())?;
         let senders = Senders::new(ctx.telemetry())?;
-        let state = State::from_params(&params);
+        let state = initial_state(&params);
 
         let coefficients = Coefficients::from_params(&param_path, &ctx.parameters())?;
         let atmosphere = Box::new(AtmosphereIsa::default());
@@ -203,40 +205,35 @@ impl Rocket {
 
 impl OdeProblem<f64, 13> for Rocket {
     fn odefun(&self, t: f64, y: SVector<f64, 13>) -> SVector<f64, 13> {
-        let state = State(y);
-        let mut dstate = State::default();
+        let thrust = EngineThrust(&*self.engine);
 
-        let q_nb = state.quat_nb();
-        let vel_b = q_nb.inverse_transform_vector(&state.vel_n().clone_owned());
-        let w_b = state.angvel_b();
-
-        let aero = self.aerodynamics.calc(&AeroState::new(
-            vel_b,
-            Vector3::zeros(),
-            w_b.clone_owned(),
-            -state.pos_n()[2],
-        ));
-
-        let f_n =
-            q_nb.transform_vector(&(self.engine.thrust_b(t) + &aero.forces));
-        let m_b = aero.moments;
-
-        let acc_n = f_n / self.params.mass  + self.params.g_n;
-
-        let qw =
-            Quaternion::from_vector(Vector4::new(w_b[0] / 2.0, w_b[1] / 2.0, w_b[2] / 2.0, 0.0));
-        let qdot = q_nb.into_inner() * qw;
+        self.params
+            .body
+            .derivative(
+                t,
+                &RigidBodyState(y),
+                &[&self.params.gravity, &thrust, &self.aerodynamics],
+            )
+            .0
+    }
+}
 
-        let w_dot = self.params.inv_inertia * (m_b + (self.params.inertia * w_b).cross(&w_b));
+impl OdeEvents<f64, 13> for Rocket {
+    fn events(&self) -> Vec<OdeEvent> {
+        EVENTS.iter().map(RocketEvent::descriptor).collect()
+    }
 
-        dstate
-            .pos_n_mut()
-            .set_column(0, &state.vel_n().clone_owned());
-        dstate.vel_n_mut().set_column(0, &acc_n);
-        dstate.quat_nb_vec_mut().set_column(0, qdot.as_vector());
-        dstate.angvel_b_mut().set_column(0, &w_dot);
+    fn event(&self, index: usize, t: f64, y: &SVector<f64, 13>) -> f64 {
+        let state = RigidBodyState(*y);
 
-        dstate.0
+        match EVENTS[index] {
+            RocketEvent::RailExit => self.params.past_rail(&state),
+            RocketEvent::Burnout => t - self.engine.burnout_time(),
+            // Vertical speed, positive down
+            RocketEvent::Apogee => state.vel_n()[2],
+            // Down position, starting from the ground
+            RocketEvent::Impact => state.pos_n()[2],
+        }
     }
 }
 
@@ -256,16 +253,20 @@ impl Node for Rocket {
             return Ok(StepResult::Continue);
         }
 
-        let next = RungeKutta4.solve(
+        let next = RungeKutta4.solve_with_events(
             self,
             t.monotonic.elapsed_seconds_f64(),
             TD(dt).seconds(),
             self.state.0,
         );
 
-        self.state.0 = next;
+        self.state.0 = next.y;
         self.state.normalize_quat();
 
+        for event in &next.events {
+            self.senders.send_event(t, event);
+        }
+
         self.senders.send(
             t,
             &self.state,
@@ -275,9 +276,7 @@ impl Node for Rocket {
         );
 
         // Stop conditions
-        if (self.state.pos_n()[2] > 0.0 && t.monotonic.elapsed_seconds_f64() > 1.0)
-            || t.monotonic.elapsed_seconds_f64() > self.params.max_t
-        {
+        if next.terminated || t.monotonic.elapsed_seconds_f64() > self.params.max_t {
             Ok(StepResult::Stop)
         } else {
             Ok(StepResult::Continue)
@@ -295,6 +294,7 @@ struct Senders {
     snd_thrust: TelemetrySender<Thrust>,
     snd_aeroangles: TelemetrySender<AeroAngles>,
     snd_aeroforces: TelemetrySender<AeroForces>,
+    snd_events: TelemetrySender<FlightEvent>,
 }
 
 impl Senders {
@@ -309,15 +309,27 @@ impl Senders {
             snd_thrust: telemetry.publish("/rocket/thrust")?,
             snd_aeroangles: telemetry.publish("/rocket/aero/angles")?,
             snd_aeroforces: telemetry.publish("/rocket/aero/actions")?,
+            snd_events: telemetry.publish("/rocket/events")?,
         })
     }
 
+    fn send_event(&self, t: Timestamp, event: &EventHit<f64, 13>) {
+        self.snd_events.send(
+            t,
+            FlightEvent {
+                timestamp: (event.t * 1e9) as i64,
+                name: EVENTS[event.index].name().to_string(),
+                pos: Vec3::from(RigidBodyState(event.y).pos_n()),
+            },
+        );
+    }
+
     fn send(
         &self,
         t: Timestamp,
-        state: &State,
+        state: &RigidBodyState,
         engine: &dyn RocketEngine,
-        _: &Params,
+        params: &Params,
         aerodynamics: &Aerodynamics,
     ) {
         let ts = t.monotonic.elapsed().num_nanoseconds().unwrap();
@@ -391,27 +403,24 @@ impl Senders {
             },
         );
 
-        let aero = aerodynamics.calc(&AeroState::new(
-            vel_b,
-            Vector3::zeros(),
-            state.angvel_b().clone_owned(),
-            state.pos_n()[2],
-        ));
+        // Same state and actions as integrated by the simulation
+        let aero_state = AeroState::still_air(state);
         self.snd_aeroangles.send(
             t,
             AeroAngles {
                 timestamp: ts,
-                alpha: aero.alpha.to_degrees(),
-                beta: aero.beta.to_degrees(),
+                alpha: aerodynamics.alpha(&aero_state).to_degrees(),
+                beta: aerodynamics.beta(&aero_state).to_degrees(),
             },
         );
 
+        let wrench = aerodynamics.wrench(t.monotonic.elapsed_seconds_f64(), state, &params.body);
         self.snd_aeroforces.send(
             t,
             AeroForces {
                 timestamp: ts,
-                force: Vec3::from(aero.forces),
-                torque: Vec3::from(aero.moments),
+                force: Vec3::from(wrench.force_b),
+                torque: Vec3::from(wrench.moment_b),
             },
         );
     }
@@ -431,6 +440,63 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_inertia() -> Result<()> {
+        let mut ps = ParameterService::from_toml_files(&["config/crater/params.toml"])?;
+        let path = "/sim/rocket/crater";
+        let mut set_inertia = |values: Vec<Parameter>| -> Result<Result<Matrix3<f64>>> {
+            ps.set(
+                &"/sim/rocket/crater/inertia".into(),
+                Parameter::List(values),
+            )?;
+            Ok(Params::from_service(path, &ps).map(|p| *p.body.inertia()))
+        };
+        let f64s = |v: &[f64]| v.iter().map(|v| Parameter::F64(*v)).collect::<Vec<_>>();
+
+        let expected = Matrix3::from_diagonal(&vector![1.0, 2.0, 3.0]);
+        assert_eq!(set_inertia(f64s(&[1.0, 2.0, 3.0]))??, expected);
+        assert_eq!(
+            set_inertia(f64s(&[1.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 3.0]))??,
+            expected
+        );
+        let rows = [[1.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 3.0]];
+        assert_eq!(
+            set_inertia(rows.iter().map(|r| Parameter::List(f64s(r))).collect())??,
+            expected
+        );
+
+        // The error about the diagonal is not hidden by the one about the full tensor
+        let err = set_inertia(vec![Parameter::String("a".to_string()); 3])?.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(crate::parameters::Error::TypeMismatch(..))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rail_exit() -> Result<()> {
+        let mut ps = ParameterService::from_toml_files(&["config/crater/params.toml"])?;
+        ps.set(
+            &"/sim/rocket/crater/init/p0_n".into(),
+            Parameter::List(vec![Parameter::F64(1.0); 3]),
+        )?;
+        ps.set(
+            &"/sim/rocket/crater/init/rail_length".into(),
+            Parameter::F64(2.0),
+        )?;
+        let params = Params::from_service("/sim/rocket/crater", &ps)?;
+
+        let mut state = initial_state(&params);
+        assert_eq!(params.past_rail(&state), -2.0);
+
+        state.pos_n_mut().copy_from(&vector![1.0, 1.0, -2.0]);
+        assert!((params.past_rail(&state) - 1.0).abs() < 1e-12);
+
+        Ok(())
+    }
+
     #[test]
     fn test_quaternion() {
         let (yaw, pitch, roll) = (45.0f64, 45.0f64, 0.0f64);
@@ -0,0 +1,338 @@
+use std::io::Write;
+
+use anyhow::{anyhow, Context, Result};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use super::batch::{run_parallel, simulate, FlightSummary, StopCondition};
+use crate::{
+    core::path::Path,
+    parameters::{Parameter, ParameterService},
+};
+
+/// A parameter swept between two values, in the unit it is written in the parameter files
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepAxis {
+    pub path: String,
+    pub low: f64,
+    pub high: f64,
+}
+
+/// How the points of a sweep are chosen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SweepDesign {
+    /// Every combination of `steps` evenly spaced values per axis, bounds included
+    Grid { steps: usize },
+
+    /// `samples` points, such that the projection on each axis has one point in each of
+    /// `samples` equal intervals
+    LatinHypercube { samples: usize, seed: u64 },
+}
+
+/// Headless runs of the crater rocket over a set of points of the parameter space
+pub struct Sweep {
+    pub axes: Vec<SweepAxis>,
+    pub design: SweepDesign,
+
+    /// Number of simulations running in parallel
+    pub threads: usize,
+
+    /// Checked at every step of every run, see `StopCondition`
+    pub stop: Option<StopCondition>,
+}
+
+/// Result table of a sweep, one row per point
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepResults {
+    /// Swept paths, in the order of the values of each row
+    pub paths: Vec<String>,
+
+    /// Swept values of each point, as set to the parameters, and the summary of its run
+    pub rows: Vec<(Vec<f64>, FlightSummary)>,
+}
+
+impl Sweep {
+    /// The points of the sweep, each with one value per axis. Grids vary the last axis first.
+    pub fn points(&self) -> Vec<Vec<f64>> {
+        match self.design {
+            SweepDesign::Grid { steps } => self.axes.iter().fold(vec![vec![]], |points, axis| {
+                let values = linspace(axis.low, axis.high, steps);
+
+                points
+                    .iter()
+                    .flat_map(|p| {
+                        values.iter().map(|v| {
+                            let mut p = p.clone();
+                            p.push(*v);
+                            p
+                        })
+                    })
+                    .collect()
+            }),
+            SweepDesign::LatinHypercube { samples, seed } => {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let mut points = vec![Vec::with_capacity(self.axes.len()); samples];
+
+                for axis in &self.axes {
+                    let mut strata: Vec<_> = (0..samples).collect();
+                    strata.shuffle(&mut rng);
+
+                    for (point, stratum) in points.iter_mut().zip(strata) {
+                        let u = (stratum as f64 + rng.gen::<f64>()) / samples as f64;
+                        point.push(axis.low + (axis.high - axis.low) * u);
+                    }
+                }
+
+                points
+            }
+        }
+    }
+
+    /// Runs a simulation for each point, with copies of `params` where the swept parameters are
+    /// set to the values of the point. Values are converted to the type of each parameter, and
+    /// integer parameters are set to the nearest integer. All the points are converted before
+    /// the first run, so values that do not fit a parameter fail the sweep right away.
+    pub fn run(&self, params: &ParameterService) -> Result<SweepResults> {
+        let paths = self
+            .axes
+            .iter()
+            .map(|a| Path::from_str(&a.path))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let points = self
+            .points()
+            .iter()
+            .map(|point| {
+                paths
+                    .iter()
+                    .zip(point)
+                    .map(|(path, v)| typed_value(params, path, *v))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let values: Vec<Vec<f64>> = points
+            .iter()
+            .map(|point| point.iter().map(|(_, v)| *v).collect())
+            .collect();
+
+        let summaries = run_parallel(&points, self.threads, |i, point| {
+            let mut params = params.deep_copy();
+            for (path, (v, _)) in paths.iter().zip(point) {
+                params.set(path, v.clone())?;
+            }
+
+            simulate(&params, self.stop.clone())
+                .with_context(|| format!("Sweep run at {:?} failed", values[i]))
+        })?;
+
+        Ok(SweepResults {
+            paths: self.axes.iter().map(|a| a.path.clone()).collect(),
+            rows: values.into_iter().zip(summaries).collect(),
+        })
+    }
+}
+
+/// `v` as a parameter of the same type as the one at `path`, rounded to the nearest integer for
+/// integer types, along with the value it stands for in the results
+fn typed_value(params: &ParameterService, path: &Path, v: f64) -> Result<(Parameter, f64)> {
+    let current = params
+        .get(path)
+        .ok_or_else(|| anyhow!("Swept parameter '{path}' not found"))?;
+
+    let r = v.round();
+    let int = |min: f64, max: f64| (min..=max).contains(&r).then_some(r);
+
+    let typed = match current {
+        Parameter::F64(_) => Some(Parameter::F64(v)),
+        Parameter::F32(_) if v.abs() <= f32::MAX as f64 => Some(Parameter::F32(v as f32)),
+        Parameter::U8(_) => int(0.0, u8::MAX as f64).map(|r| Parameter::U8(r as u8)),
+        Parameter::U16(_) => int(0.0, u16::MAX as f64).map(|r| Parameter::U16(r as u16)),
+        Parameter::U32(_) => int(0.0, u32::MAX as f64).map(|r| Parameter::U32(r as u32)),
+        Parameter::U64(_) => int(0.0, u64::MAX as f64).map(|r| Parameter::U64(r as u64)),
+        Parameter::I8(_) => int(i8::MIN as f64, i8::MAX as f64).map(|r| Parameter::I8(r as i8)),
+        Parameter::I16(_) => {
+            int(i16::MIN as f64, i16::MAX as f64).map(|r| Parameter::I16(r as i16))
+        }
+        Parameter::I32(_) => {
+            int(i32::MIN as f64, i32::MAX as f64).map(|r| Parameter::I32(r as i32))
+        }
+        Parameter::I64(_) => {
+            int(i64::MIN as f64, i64::MAX as f64).map(|r| Parameter::I64(r as i64))
+        }
+        _ => None,
+    };
+
+    let typed =
+        typed.ok_or_else(|| anyhow!("Cannot set '{path}' ({}) to {v}", current.type_string()))?;
+
+    // Integers are rounded, floats are reported as swept, whatever their precision
+    let value = match typed {
+        Parameter::F32(_) | Parameter::F64(_) => v,
+        _ => r,
+    };
+
+    Ok((typed, value))
+}
+
+impl SweepResults {
+    /// Summary of the run with exactly these swept values
+    pub fn get(&self, values: &[f64]) -> Option<&FlightSummary> {
+        self.rows
+            .iter()
+            .find(|(v, _)| v.as_slice() == values)
+            .map(|(_, s)| s)
+    }
+
+    /// Writes one line per point: the swept values, then the summary of the run
+    pub fn write_csv(&self, mut out: impl Write) -> Result<()> {
+        let header: Vec<_> = self
+            .paths
+            .iter()
+            .map(String::as_str)
+            .chain(FlightSummary::CSV_HEADER)
+            .collect();
+        writeln!(out, "{}", header.join(","))?;
+
+        for (values, summary) in &self.rows {
+            let row: Vec<_> = values
+                .iter()
+                .map(f64::to_string)
+                .chain(summary.csv_fields())
+                .collect();
+            writeln!(out, "{}", row.join(","))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `n` evenly spaced values from `low` to `high`, or just `low` if `n` is 1
+fn linspace(low: f64, high: f64, n: usize) -> Vec<f64> {
+    match n {
+        0 => vec![],
+        1 => vec![low],
+        n => (0..n)
+            .map(|i| low + (high - low) * i as f64 / (n - 1) as f64)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn sweep(design: SweepDesign) -> Sweep {
+        Sweep {
+            axes: vec![
+                SweepAxis {
+                    path: "/sim/rocket/crater/init/elevation".to_string(),
+                    low: 60.0,
+                    high: 90.0,
+                },
+                SweepAxis {
+                    path: "/sim/rocket/crater/engine/simple/total_impulse".to_string(),
+                    low: 300.0,
+                    high: 340.0,
+                },
+            ],
+            design,
+            threads: 3,
+            stop: None,
+        }
+    }
+
+    #[test]
+    fn test_points() {
+        assert_eq!(
+            sweep(SweepDesign::Grid { steps: 3 }).points(),
+            vec![
+                vec![60.0, 300.0],
+                vec![60.0, 320.0],
+                vec![60.0, 340.0],
+                vec![75.0, 300.0],
+                vec![75.0, 320.0],
+                vec![75.0, 340.0],
+                vec![90.0, 300.0],
+                vec![90.0, 320.0],
+                vec![90.0, 340.0],
+            ]
+        );
+
+        let lhs = sweep(SweepDesign::LatinHypercube {
+            samples: 10,
+            seed: 1,
+        });
+        let points = lhs.points();
+        assert_eq!(points.len(), 10);
+
+        // One point per interval on each axis
+        for (i, axis) in lhs.axes.iter().enumerate() {
+            let mut strata: Vec<_> = points
+                .iter()
+                .map(|p| ((p[i] - axis.low) / (axis.high - axis.low) * 10.0) as usize)
+                .collect();
+            strata.sort();
+            assert_eq!(strata, (0..10).collect::<Vec<_>>());
+        }
+
+        assert_eq!(lhs.points(), points);
+    }
+
+    #[test]
+    fn test_typed_value() -> Result<()> {
+        let params = ParameterService::from_toml(
+            "gain = {val=0.5, dtype=\"f32\"}
+            steps = {val=3, dtype=\"u8\"}
+            name = {val=\"crater\", dtype=\"string\"}
+            ",
+        )?;
+
+        assert_eq!(
+            typed_value(&params, &"/gain".into(), 0.15000000000000002)?,
+            (Parameter::F32(0.15), 0.15000000000000002)
+        );
+        assert_eq!(
+            typed_value(&params, &"/steps".into(), 3.4)?,
+            (Parameter::U8(3), 3.0)
+        );
+        assert!(typed_value(&params, &"/steps".into(), -1.0).is_err());
+        assert!(typed_value(&params, &"/steps".into(), 300.0).is_err());
+        assert!(typed_value(&params, &"/name".into(), 1.0).is_err());
+        assert!(typed_value(&params, &"/missing".into(), 1.0).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sweep() -> Result<()> {
+        let mut params = ParameterService::from_toml_files(&["config/crater/params.toml"])?;
+        params.apply_overrides(&["/sim/dt=0.02"])?;
+
+        // Only the apogee is of interest
+        let mut sweep = sweep(SweepDesign::Grid { steps: 2 });
+        sweep.stop = Some(Arc::new(|s| s.t_end > s.t_apogee + 0.5));
+
+        let results = sweep.run(&params)?;
+        assert_eq!(results.rows.len(), 4);
+
+        let low = results.get(&[60.0, 300.0]).unwrap();
+        let high = results.get(&[90.0, 340.0]).unwrap();
+        assert!(!low.landed && !high.landed);
+        assert!(high.apogee > low.apogee);
+
+        let mut csv = vec![];
+        results.write_csv(&mut csv)?;
+        let csv = String::from_utf8(csv)?;
+
+        assert!(csv.starts_with(
+            "/sim/rocket/crater/init/elevation,/sim/rocket/crater/engine/simple/total_impulse,\
+            apogee_m,"
+        ));
+        assert_eq!(csv.lines().count(), 5);
+        assert!(csv.lines().nth(4).unwrap().starts_with("90,340,"));
+
+        Ok(())
+    }
+}
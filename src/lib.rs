@@ -57,6 +57,10 @@ pub mod crater_messages {
     pub mod sensors {
         include!(concat!(env!("OUT_DIR"), "/crater.sensors.rs"));
     }
+
+    pub mod parameters {
+        include!(concat!(env!("OUT_DIR"), "/crater.parameters.rs"));
+    }
 }
 
 pub static DESCRIPTOR_POOL: Lazy<DescriptorPool> = Lazy::new(|| {
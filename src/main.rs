@@ -1,31 +1,89 @@
 use std::{
     collections::HashMap,
-    fs,
+    env,
+    fs::File,
+    io::BufWriter,
+    str::FromStr,
     sync::{atomic::AtomicBool, mpsc::channel, Arc, Mutex},
     thread,
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use chrono::TimeDelta;
 use quadcopter::{
-    crater::sim::rocket::Rocket,
+    crater::{
+        montecarlo::{write_csv, BatchStatistics, MonteCarlo},
+        sim::rocket::Rocket,
+        sweep::{Sweep, SweepAxis, SweepDesign},
+    },
     crater_messages::sensors::{
         AeroAngles, AeroForces, AngularVelocity, EulerAngles, OrientationQuat, Position, Thrust,
         Velocity,
     },
-    nodes::{FtlOrderedExecutor, NodeConfig, NodeManager},
+    nodes::{FtlOrderedExecutor, NodeConfig, NodeManager, ParameterServer},
     parameters::ParameterService,
-    plot::localplotter::LocalPlotter,
+    plot::{localplotter::LocalPlotter, parameditor::ParameterEditor},
     telemetry::TelemetryService,
 };
 use rust_data_inspector::{DataInspector, PlotSignals};
 
+/// Environment variables starting with this prefix override parameters, see
+/// `ParameterService::apply_env_overrides()`
+const PARAM_ENV_PREFIX: &str = "QUADCOPTER_PARAM";
+
+/// Default file written by the "Save as TOML" action of the parameter editor
+const EDITED_PARAMS_FILE: &str = "params_edited.toml";
+
+/// Default file written by batch runs
+const BATCH_OUTPUT_FILE: &str = "montecarlo.csv";
+
+/// Default file written by sweeps
+const SWEEP_OUTPUT_FILE: &str = "sweep.csv";
+
 #[derive(Debug, Default, Clone)]
 struct SimState {
     running: bool,
+
+    /// Parameters of the current (or last) run
+    params: Option<ParameterService>,
+
+    /// Parameters for the next run, instead of loading them from the files
+    next_params: Option<ParameterService>,
+}
+
+/// Command line arguments
+#[derive(Debug, Default)]
+struct Args {
+    /// Parameter files layered on top of the base parameters
+    files: Vec<String>,
+
+    /// `--set PATH=VALUE` overrides
+    overrides: Vec<String>,
+
+    /// `--batch N`: runs N simulations without the GUI
+    batch: Option<usize>,
+
+    /// `--sweep PATH=LOW:HIGH`, with `--grid N` or `--lhs N`: runs a sweep without the GUI
+    sweep: Vec<String>,
+    grid: Option<usize>,
+    lhs: Option<usize>,
+
+    seed: u64,
+    threads: Option<usize>,
+    out: Option<String>,
 }
 
 fn main() -> Result<()> {
+    let args = parse_args(env::args().skip(1))?;
+
+    if let Some(runs) = args.batch {
+        return run_batch(runs, &args);
+    }
+
+    if !args.sweep.is_empty() {
+        return run_sweep(&args);
+    }
+
     let mut signals = PlotSignals::default();
     let local_plotter = Arc::new(Mutex::new(LocalPlotter::new()));
 
@@ -42,6 +100,9 @@ fn main() -> Result<()> {
         local_plotter.plot_channel::<AeroForces>(&mut signals, "/rocket/aero/actions")?;
     }
 
+    let param_files = param_files(&args);
+    let overrides = args.overrides;
+
     let (runsim_sender, runsim_receiver) = channel::<bool>();
     let simstate = Arc::new(Mutex::new(SimState::default()));
 
@@ -52,18 +113,29 @@ fn main() -> Result<()> {
         thread::spawn(move || -> Result<()> {
             loop {
                 let plot_handle = {
-                    simstate.lock().unwrap().running = true;
+                    let next_params = {
+                        let mut simstate = simstate.lock().unwrap();
+                        simstate.running = true;
+                        simstate.next_params.take()
+                    };
 
                     let ts = TelemetryService::default();
-                    let params_toml = fs::read_to_string("config/crater/params.toml")?;
-                    let params = ParameterService::from_toml(&params_toml)?;
+                    let params = match next_params {
+                        Some(params) => params,
+                        None => load_params(&param_files, &overrides)?,
+                    };
+                    simstate.lock().unwrap().params = Some(params.clone());
 
                     let mut nm = NodeManager::new(
                         ts.clone(),
                         params.clone(),
-                        HashMap::from([("rocket".to_string(), NodeConfig::default())]),
+                        HashMap::from([
+                            ("rocket".to_string(), NodeConfig::default()),
+                            ("parameters".to_string(), NodeConfig::default()),
+                        ]),
                     );
 
+                    nm.add_node("parameters", |ctx| Ok(Box::new(ParameterServer::new(ctx)?)))?;
                     nm.add_node("rocket", |ctx| Ok(Box::new(Rocket::new("crater", ctx)?)))?;
 
                     let plot_handle = local_plotter.lock().unwrap().run(&ts)?;
@@ -95,12 +167,19 @@ fn main() -> Result<()> {
 
     {
         let runsim_sender = runsim_sender.clone();
+        // Behind a mutex, as the panel callback only gets shared access to its state
+        let editor = Mutex::new(ParameterEditor::new(EDITED_PARAMS_FILE));
+
         DataInspector::run_native(
             "plotter",
             signals,
             Some(
                 move |ui: &mut egui::Ui, api: &mut rust_data_inspector::DataInspectorAPI| {
-                    let enabled = !simstate.lock().unwrap().running;
+                    let (enabled, current) = {
+                        let simstate = simstate.lock().unwrap();
+                        (!simstate.running, simstate.params.clone())
+                    };
+
                     if ui
                         .add_enabled(enabled, egui::Button::new("↻ Restart"))
                         .clicked()
@@ -108,6 +187,16 @@ fn main() -> Result<()> {
                         api.clear_timeseries();
                         runsim_sender.send(true).unwrap();
                     }
+
+                    ui.separator();
+
+                    let restart_with = editor.lock().unwrap().ui(ui, current.as_ref(), enabled);
+
+                    if let Some(params) = restart_with {
+                        simstate.lock().unwrap().next_params = Some(params);
+                        api.clear_timeseries();
+                        runsim_sender.send(true).unwrap();
+                    }
                 },
             ),
         )
@@ -119,3 +208,146 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Runs `runs` simulations in parallel, with the parameter distributions sampled with a
+/// different seed each time, and writes their summaries to a CSV file
+fn run_batch(runs: usize, args: &Args) -> Result<()> {
+    let params = load_params(&param_files(args), &args.overrides)?;
+
+    let threads = threads(args);
+    let out = args.out.as_deref().unwrap_or(BATCH_OUTPUT_FILE);
+
+    let mc = MonteCarlo {
+        runs,
+        seed: args.seed,
+        threads,
+    };
+
+    println!("Running {runs} simulations on {threads} threads");
+    let summaries = mc.run(&params)?;
+
+    write_csv(BufWriter::new(File::create(out)?), &summaries)?;
+    println!("Run summaries written to {out}");
+
+    if let Some(stats) = BatchStatistics::new(&summaries) {
+        print!("{stats}");
+    }
+
+    Ok(())
+}
+
+/// Runs a simulation for each point of a grid or Latin-hypercube sweep over the parameters
+/// given with `--sweep`, and writes the result table to a CSV file
+fn run_sweep(args: &Args) -> Result<()> {
+    let params = load_params(&param_files(args), &args.overrides)?;
+
+    let axes = args
+        .sweep
+        .iter()
+        .map(|s| {
+            let (path, low, high) = s
+                .split_once('=')
+                .and_then(|(path, range)| range.split_once(':').map(|(l, h)| (path, l, h)))
+                .ok_or_else(|| anyhow!("Invalid sweep '{s}', expected 'PATH=LOW:HIGH'"))?;
+
+            Ok(SweepAxis {
+                path: path.to_string(),
+                low: parse_number("--sweep", low)?,
+                high: parse_number("--sweep", high)?,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    let design = match (args.grid, args.lhs) {
+        (Some(steps), None) => SweepDesign::Grid { steps },
+        (None, Some(samples)) => SweepDesign::LatinHypercube {
+            samples,
+            seed: args.seed,
+        },
+        _ => bail!("Sweeps require exactly one of --grid N or --lhs N"),
+    };
+
+    let sweep = Sweep {
+        axes,
+        design,
+        threads: threads(args),
+        stop: None,
+    };
+    let out = args.out.as_deref().unwrap_or(SWEEP_OUTPUT_FILE);
+
+    println!("Running {} simulations", sweep.points().len());
+    let results = sweep.run(&params)?;
+
+    results.write_csv(BufWriter::new(File::create(out)?))?;
+    println!("Sweep results written to {out}");
+
+    Ok(())
+}
+
+fn threads(args: &Args) -> usize {
+    args.threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+/// Scenario files passed on the command line are layered on top of the base parameters
+fn param_files(args: &Args) -> Vec<String> {
+    ["config/crater/params.toml".to_string()]
+        .into_iter()
+        .chain(args.files.iter().cloned())
+        .collect()
+}
+
+fn load_params(files: &[String], overrides: &[String]) -> Result<ParameterService> {
+    // Command line overrides take precedence over the environment
+    let mut params = ParameterService::from_toml_files(files)?;
+    params.apply_env_overrides(PARAM_ENV_PREFIX)?;
+    params.apply_overrides(overrides)?;
+
+    Ok(params)
+}
+
+/// Parses the command line: parameter files, `--set PATH=VALUE` overrides, and the headless
+/// mode options `--batch N`, `--sweep PATH=LOW:HIGH`, `--grid N`, `--lhs N`, `--seed S`,
+/// `--threads T` and `--out FILE`
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut parsed = Args::default();
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            parsed.files.push(arg);
+            continue;
+        }
+
+        // Options are written either as `--name value` or `--name=value`
+        let (name, mut inline) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        let mut value = || {
+            inline
+                .take()
+                .or_else(|| args.next())
+                .ok_or_else(|| anyhow!("Missing value after {name}"))
+        };
+
+        match name {
+            "--set" => parsed.overrides.push(value()?),
+            "--batch" => parsed.batch = Some(parse_number(name, &value()?)?),
+            "--sweep" => parsed.sweep.push(value()?),
+            "--grid" => parsed.grid = Some(parse_number(name, &value()?)?),
+            "--lhs" => parsed.lhs = Some(parse_number(name, &value()?)?),
+            "--seed" => parsed.seed = parse_number(name, &value()?)?,
+            "--threads" => parsed.threads = Some(parse_number(name, &value()?)?),
+            "--out" => parsed.out = Some(value()?),
+            _ => bail!("Unknown option '{}'", name),
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn parse_number<T: FromStr>(option: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("Invalid value '{value}' for {option}"))
+}
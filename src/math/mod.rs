@@ -1 +1,2 @@
-pub mod ode;
\ No newline at end of file
+pub mod ode;
+pub mod rigid_body;
\ No newline at end of file
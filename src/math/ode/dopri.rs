@@ -0,0 +1,458 @@
+use std::cell::Cell;
+
+use nalgebra::{allocator::Allocator, DefaultAllocator, Dim, OVector, RealField, SVector};
+
+use super::{OdeFun, OdeProblem, OdeStep};
+
+// Dormand-Prince 5(4) tableau. The 5th order solution is propagated, and the last stage is
+// evaluated at the new state, so it is reused as the first stage of the next step.
+const C: [f64; 7] = [0.0, 1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0, 1.0];
+
+const A: [[f64; 6]; 7] = [
+    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+    [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+    [
+        19372.0 / 6561.0,
+        -25360.0 / 2187.0,
+        64448.0 / 6561.0,
+        -212.0 / 729.0,
+        0.0,
+        0.0,
+    ],
+    [
+        9017.0 / 3168.0,
+        -355.0 / 33.0,
+        46732.0 / 5247.0,
+        49.0 / 176.0,
+        -5103.0 / 18656.0,
+        0.0,
+    ],
+    [
+        35.0 / 384.0,
+        0.0,
+        500.0 / 1113.0,
+        125.0 / 192.0,
+        -2187.0 / 6784.0,
+        11.0 / 84.0,
+    ],
+];
+
+/// Difference between the 5th and 4th order weights, giving the local error estimate
+const E: [f64; 7] = [
+    71.0 / 57600.0,
+    0.0,
+    -71.0 / 16695.0,
+    71.0 / 1920.0,
+    -17253.0 / 339200.0,
+    22.0 / 525.0,
+    -1.0 / 40.0,
+];
+
+/// Weights of the 4th order continuous extension (Hairer, Nørsett & Wanner)
+const D: [f64; 7] = [
+    -12715105075.0 / 11282082432.0,
+    0.0,
+    87487479700.0 / 32700410799.0,
+    -10690763975.0 / 1880347072.0,
+    701980252875.0 / 199316789632.0,
+    -1453857185.0 / 822651844.0,
+    69997945.0 / 29380423.0,
+];
+
+const SAFETY: f64 = 0.9;
+const MIN_FACTOR: f64 = 0.2;
+const MAX_FACTOR: f64 = 5.0;
+
+/// Number of steps taken by an adaptive solver
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StepStats {
+    pub accepted: usize,
+    pub rejected: usize,
+}
+
+/// Adaptive Dormand-Prince 5(4) solver.
+///
+/// Steps are accepted if the estimated local error of each component is below
+/// `atol + rtol * |y|`, and the step size is adjusted after each step to keep it so. As an
+/// `OdeSolver`, `solve()` takes as many steps as needed to cover `dt`, starting from the step
+/// size that was reached during the previous call.
+pub struct DormandPrince45<T> {
+    pub atol: T,
+    pub rtol: T,
+
+    /// Steps are never smaller than `h_min`: if the error is still too large, the step is
+    /// accepted anyway
+    pub h_min: T,
+    pub h_max: Option<T>,
+
+    h: Cell<Option<T>>,
+    stats: Cell<StepStats>,
+}
+
+/// Solution of an adaptive solver over an interval, which can be evaluated at any time inside
+/// it with a 4th order interpolation
+#[derive(Debug, Clone)]
+pub struct DenseOutput<T: RealField, const S: usize> {
+    steps: Vec<DenseStep<T, S>>,
+}
+
+/// Coefficients of the interpolating polynomial of a step
+#[derive(Debug, Clone)]
+struct DenseStep<T: RealField, const S: usize> {
+    t: T,
+    h: T,
+    r: [SVector<T, S>; 5],
+}
+
+impl<T: RealField + From<f64> + Copy> DormandPrince45<T> {
+    pub fn new(atol: T, rtol: T) -> Self {
+        DormandPrince45 {
+            atol,
+            rtol,
+            h_min: T::from(1e-10),
+            h_max: None,
+            h: Cell::new(None),
+            stats: Cell::new(StepStats::default()),
+        }
+    }
+
+    pub fn with_step_limits(mut self, h_min: T, h_max: Option<T>) -> Self {
+        self.h_min = h_min;
+        self.h_max = h_max;
+        self
+    }
+
+    /// Steps taken since the solver was created, or since the last `reset()`
+    pub fn stats(&self) -> StepStats {
+        self.stats.get()
+    }
+
+    /// Clears the stats and forgets the step size of the previous calls
+    pub fn reset(&self) {
+        self.stats.set(StepStats::default());
+        self.h.set(None);
+    }
+
+    /// Integrates from `t0` to `t1`, with `t1 >= t0`, keeping every step for dense output
+    pub fn integrate<const S: usize>(
+        &self,
+        problem: &dyn OdeProblem<T, S>,
+        t0: T,
+        t1: T,
+        y0: SVector<T, S>,
+    ) -> DenseOutput<T, S> {
+        let mut steps = vec![];
+
+        let f = |t, y: &SVector<T, S>| problem.odefun(t, *y);
+        self.advance(&f, t0, t1, y0, |t, h, y0, y1, k| {
+            let h_k = |i: usize| k[i] * h;
+            let ydiff = y1 - y0;
+            let bspl = h_k(0) - ydiff;
+
+            let mut r4 = SVector::zeros();
+            for (d, k) in D.iter().zip(k) {
+                r4 += k * T::from(*d);
+            }
+
+            steps.push(DenseStep {
+                t,
+                h,
+                r: [*y0, ydiff, bspl, ydiff - h_k(6) - bspl, r4 * h],
+            });
+        });
+
+        DenseOutput { steps }
+    }
+
+    /// Takes adaptive steps from `t0` to `t1`, calling `on_step(t, h, y0, y1, stages)` after each
+    /// accepted step. Returns the state at `t1`.
+    fn advance<D: Dim>(
+        &self,
+        f: OdeFun<T, D>,
+        t0: T,
+        t1: T,
+        y0: OVector<T, D>,
+        mut on_step: impl FnMut(T, T, &OVector<T, D>, &OVector<T, D>, &[OVector<T, D>; 7]),
+    ) -> OVector<T, D>
+    where
+        DefaultAllocator: Allocator<D>,
+    {
+        let mut stats = self.stats.get();
+
+        let mut t = t0;
+        let mut y = y0;
+        let mut fy = f(t, &y);
+        let mut h = match self.h.get() {
+            Some(h) => h,
+            None => self.initial_step(f, t, &y, &fy),
+        };
+
+        while t < t1 {
+            // Shortened to end exactly at t1, without changing the size of the next steps
+            let remaining = t1 - t;
+            let h_max = self.h_max.map_or(remaining, |h_max| h_max.min(remaining));
+            h = h.max(self.h_min);
+            let step = h.min(h_max);
+
+            let mut k: [_; 7] = std::array::from_fn(|_| fy.clone());
+            for i in 1..7 {
+                let mut yi = y.clone();
+                for (a, kj) in A[i].iter().zip(&k[..i]) {
+                    yi += kj * (T::from(*a) * step);
+                }
+                k[i] = f(t + T::from(C[i]) * step, &yi);
+            }
+
+            // The last stage is evaluated at the 5th order solution
+            let mut y_new = y.clone();
+            for (a, kj) in A[6].iter().zip(&k[..6]) {
+                y_new += kj * (T::from(*a) * step);
+            }
+
+            let err = self.error_norm(&y, &y_new, &k, step);
+            let factor = if err == T::zero() {
+                T::from(MAX_FACTOR)
+            } else {
+                (T::from(SAFETY) * err.powf(T::from(-0.2)))
+                    .max(T::from(MIN_FACTOR))
+                    .min(T::from(MAX_FACTOR))
+            };
+
+            if err <= T::one() || step <= self.h_min {
+                stats.accepted += 1;
+                on_step(t, step, &y, &y_new, &k);
+
+                t = if step == remaining { t1 } else { t + step };
+                y = y_new;
+                let [.., last] = k;
+                fy = last;
+
+                if step < h {
+                    h = h.min(step * factor);
+                } else {
+                    h = step * factor;
+                }
+            } else {
+                stats.rejected += 1;
+                h = step * factor.min(T::one());
+            }
+        }
+
+        self.stats.set(stats);
+        self.h.set(Some(h));
+
+        y
+    }
+
+    /// Root mean square of the local error of each component, relative to its tolerance
+    fn error_norm<D: Dim>(
+        &self,
+        y0: &OVector<T, D>,
+        y1: &OVector<T, D>,
+        k: &[OVector<T, D>; 7],
+        h: T,
+    ) -> T
+    where
+        DefaultAllocator: Allocator<D>,
+    {
+        let mut err = y0.map(|_| T::zero());
+        for (e, k) in E.iter().zip(k) {
+            err += k * (T::from(*e) * h);
+        }
+
+        let scaled = err.zip_zip_map(y0, y1, |e, a, b| {
+            e / (self.atol + self.rtol * a.abs().max(b.abs()))
+        });
+
+        (scaled.norm_squared() / T::from(y0.len().max(1) as f64)).sqrt()
+    }
+
+    /// Initial step size guess, from the magnitude of the state and of its first two
+    /// derivatives (Hairer, Nørsett & Wanner)
+    fn initial_step<D: Dim>(
+        &self,
+        f: OdeFun<T, D>,
+        t: T,
+        y: &OVector<T, D>,
+        fy: &OVector<T, D>,
+    ) -> T
+    where
+        DefaultAllocator: Allocator<D>,
+    {
+        let n = T::from(y.len().max(1) as f64);
+        let scale = y.map(|v| self.atol + self.rtol * v.abs());
+        let norm = |v: &OVector<T, D>| (v.component_div(&scale).norm_squared() / n).sqrt();
+
+        let (d0, d1) = (norm(y), norm(fy));
+        let h0 = if d0 < T::from(1e-5) || d1 < T::from(1e-5) {
+            T::from(1e-6)
+        } else {
+            T::from(0.01) * d0 / d1
+        };
+
+        let f1 = f(t + h0, &(y + fy * h0));
+        let d2 = norm(&(f1 - fy)) / h0;
+
+        let h1 = if d1.max(d2) <= T::from(1e-15) {
+            (h0 * T::from(1e-3)).max(T::from(1e-6))
+        } else {
+            (T::from(0.01) / d1.max(d2)).powf(T::from(0.2))
+        };
+
+        (h0 * T::from(100.0)).min(h1)
+    }
+}
+
+impl<T: RealField + From<f64> + Copy> OdeStep<T> for DormandPrince45<T> {
+    fn step<D: Dim>(&self, f: OdeFun<T, D>, t0: T, dt: T, y0: OVector<T, D>) -> OVector<T, D>
+    where
+        DefaultAllocator: Allocator<D> + Allocator<D, D>,
+    {
+        self.advance(f, t0, t0 + dt, y0, |_, _, _, _, _| ())
+    }
+}
+
+impl<T: RealField + From<f64> + Copy, const S: usize> DenseOutput<T, S> {
+    pub fn t0(&self) -> Option<T> {
+        self.steps.first().map(|s| s.t)
+    }
+
+    pub fn t1(&self) -> Option<T> {
+        self.steps.last().map(|s| s.t + s.h)
+    }
+
+    /// Times of the accepted steps, including the final time
+    pub fn times(&self) -> Vec<T> {
+        self.steps.iter().map(|s| s.t).chain(self.t1()).collect()
+    }
+
+    /// Interpolated state at `t`, which is clamped to the integration interval. `None` if no
+    /// step was taken.
+    pub fn eval(&self, t: T) -> Option<SVector<T, S>> {
+        let i = self.steps.partition_point(|s| s.t + s.h < t);
+        let step = self.steps.get(i).or(self.steps.last())?;
+
+        let theta = ((t - step.t) / step.h).max(T::zero()).min(T::one());
+        let theta1 = T::one() - theta;
+        let [r0, r1, r2, r3, r4] = &step.r;
+
+        Some(r0 + (r1 + (r2 + (r3 + r4 * theta1) * theta) * theta1) * theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{vector, Vector2};
+
+    use super::*;
+    use crate::math::ode::{OdeSolver, RungeKutta4};
+
+    /// Harmonic oscillator, y = [cos(t), -sin(t)]
+    struct Oscillator;
+
+    impl OdeProblem<f64, 2> for Oscillator {
+        fn odefun(&self, _: f64, y: Vector2<f64>) -> Vector2<f64> {
+            vector![y[1], -y[0]]
+        }
+    }
+
+    /// Falling mass with a thrust pulse during the first second, like a short boost phase
+    struct Boost;
+
+    impl OdeProblem<f64, 2> for Boost {
+        fn odefun(&self, t: f64, y: Vector2<f64>) -> Vector2<f64> {
+            let thrust = if t < 1.0 { 50.0 } else { 0.0 };
+            vector![y[1], thrust - 9.81]
+        }
+    }
+
+    #[test]
+    fn test_accuracy() {
+        let exact = |t: f64| vector![t.cos(), -t.sin()];
+
+        for tol in [1e-4, 1e-8] {
+            let solver = DormandPrince45::new(tol, tol);
+            let y = solver.solve(&Oscillator, 0.0, 10.0, exact(0.0));
+
+            assert!((y - exact(10.0)).norm() < tol * 100.0);
+        }
+
+        // Tighter tolerances take more steps
+        let loose = DormandPrince45::new(1e-4, 1e-4);
+        let tight = DormandPrince45::new(1e-8, 1e-8);
+        loose.solve(&Oscillator, 0.0, 10.0, exact(0.0));
+        tight.solve(&Oscillator, 0.0, 10.0, exact(0.0));
+        assert!(tight.stats().accepted > 2 * loose.stats().accepted);
+
+        // Much fewer evaluations than a fixed step solver for the same accuracy
+        let rk4_steps = 1000;
+        let mut y = exact(0.0);
+        for i in 0..rk4_steps {
+            y = RungeKutta4.solve(&Oscillator, i as f64 * 0.01, 0.01, y);
+        }
+        assert!((y - exact(10.0)).norm() < 1e-8);
+        assert!(tight.stats().accepted * 6 < rk4_steps * 4);
+    }
+
+    #[test]
+    fn test_dense_output() {
+        let exact = |t: f64| vector![t.cos(), -t.sin()];
+        let solver = DormandPrince45::new(1e-9, 1e-9);
+        let sol = solver.integrate(&Oscillator, 0.0, 5.0, exact(0.0));
+
+        assert_eq!(sol.t0(), Some(0.0));
+        assert_eq!(sol.t1(), Some(5.0));
+        assert_eq!(sol.times().len(), solver.stats().accepted + 1);
+
+        for i in 0..=500 {
+            let t = i as f64 * 0.01;
+            assert!((sol.eval(t).unwrap() - exact(t)).norm() < 1e-7, "t = {t}");
+        }
+
+        // Clamped outside of the interval
+        assert_eq!(sol.eval(-1.0), sol.eval(0.0));
+        assert_eq!(sol.eval(6.0), sol.eval(5.0));
+    }
+
+    #[test]
+    fn test_step_control() {
+        let solver = DormandPrince45::new(1e-6, 1e-6);
+        let sol = solver.integrate(&Boost, 0.0, 10.0, Vector2::zeros());
+
+        // The discontinuity at the end of the boost causes rejections, and small steps around it
+        let stats = solver.stats();
+        assert!(stats.rejected > 0);
+
+        let times = sol.times();
+        let sizes: Vec<_> = times.windows(2).map(|w| (w[0], w[1] - w[0])).collect();
+        let around = sizes.iter().find(|(t, _)| (t - 1.0).abs() < 0.1).unwrap().1;
+        let coast = sizes.last().unwrap().1.max(sizes[sizes.len() - 2].1);
+        assert!(coast > 10.0 * around);
+
+        // The error estimate is not reliable across the discontinuity, but the free fall after
+        // the boost is integrated exactly
+        let v1 = 50.0 - 9.81;
+        let x1 = v1 / 2.0;
+        let (x, v) = (x1 + v1 * 9.0 - 9.81 * 81.0 / 2.0, v1 - 9.81 * 9.0);
+        let y = sol.eval(10.0).unwrap();
+        assert!((y - vector![x, v]).norm() < 0.1);
+
+        let y2 = sol.eval(2.0).unwrap();
+        assert!((y[1] - y2[1] + 9.81 * 8.0).abs() < 1e-9);
+
+        // Step sizes are kept between calls to solve()
+        solver.reset();
+        let mut y_solve = Vector2::zeros();
+        for i in 0..100 {
+            y_solve = solver.solve(&Boost, i as f64 * 0.1, 0.1, y_solve);
+        }
+        assert!((y_solve - y).norm() < 0.1);
+        assert!(solver.stats().accepted < 200);
+
+        let limited = DormandPrince45::new(1e-6, 1e-6).with_step_limits(1e-10, Some(0.5));
+        let sol = limited.integrate(&Boost, 0.0, 10.0, Vector2::zeros());
+        assert!(sol.times().windows(2).all(|w| w[1] - w[0] <= 0.5 + 1e-12));
+    }
+}
@@ -0,0 +1,359 @@
+use nalgebra::{RealField, SVector};
+
+use super::{OdeEvents, OdeSolver};
+
+/// Relative to the step size, width of the interval where event times are searched
+const EVENT_TOLERANCE: f64 = 1e-10;
+const EVENT_MAX_ITER: usize = 100;
+
+/// Sign changes of an event function that trigger the event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crossing {
+    /// From negative to zero or positive
+    Rising,
+
+    /// From positive to zero or negative
+    Falling,
+    Both,
+}
+
+/// Description of an event of an `OdeProblem`, whose function is `OdeEvents::event()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OdeEvent {
+    pub crossing: Crossing,
+
+    /// Terminal events stop the integration at the time they occur
+    pub terminal: bool,
+}
+
+/// An event located during a step
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventHit<T: RealField, const S: usize> {
+    /// Index of the event in `OdeEvents::events()`
+    pub index: usize,
+    pub t: T,
+    pub y: SVector<T, S>,
+}
+
+/// Result of `OdeSolver::solve_with_events()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventStep<T: RealField, const S: usize> {
+    /// End of the step, or time of the terminal event
+    pub t: T,
+    pub y: SVector<T, S>,
+
+    /// Events that occurred during the step, sorted by time
+    pub events: Vec<EventHit<T, S>>,
+
+    /// Whether the step was stopped by a terminal event, the last of `events`
+    pub terminated: bool,
+}
+
+impl Crossing {
+    fn triggered<T: RealField>(&self, g0: &T, g1: &T) -> bool {
+        let rising = g0.is_negative() && !g1.is_negative();
+        let falling = g0.is_positive() && !g1.is_positive();
+
+        match self {
+            Crossing::Rising => rising,
+            Crossing::Falling => falling,
+            Crossing::Both => rising || falling,
+        }
+    }
+}
+
+/// Takes a step with `solver`, then locates every event whose function changed sign during the
+/// step. An event function that crosses zero twice within a step is not detected.
+///
+/// Events are located on the cubic Hermite interpolant of the step, built from the states and
+/// derivatives at both of its ends, rather than by integrating again up to the event: the
+/// solver is only called once, so adaptive solvers keep their step size and statistics.
+pub(super) fn solve_with_events<T, const S: usize, O>(
+    solver: &O,
+    problem: &dyn OdeEvents<T, S>,
+    t0: T,
+    dt: T,
+    y0: SVector<T, S>,
+) -> EventStep<T, S>
+where
+    T: RealField + From<f64> + Copy,
+    O: OdeSolver<T, S> + ?Sized,
+{
+    let t1 = t0 + dt;
+    let y1 = solver.solve(problem, t0, dt, y0);
+    let events = problem.events();
+
+    let triggered: Vec<_> = events
+        .iter()
+        .enumerate()
+        .filter_map(|(i, event)| {
+            let g0 = problem.event(i, t0, &y0);
+            let g1 = problem.event(i, t1, &y1);
+
+            event.crossing.triggered(&g0, &g1).then_some((i, g0, g1))
+        })
+        .collect();
+
+    let mut hits = vec![];
+    if !triggered.is_empty() {
+        let interpolant = Hermite {
+            t0,
+            dt,
+            y0,
+            y1,
+            f0: problem.odefun(t0, y0),
+            f1: problem.odefun(t1, y1),
+        };
+
+        for (i, g0, g1) in triggered {
+            let (t, y) = locate(problem, &interpolant, i, g0, g1);
+            hits.push(EventHit { index: i, t, y });
+        }
+    }
+
+    hits.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap_or(std::cmp::Ordering::Equal));
+
+    match hits.iter().position(|h| events[h.index].terminal) {
+        Some(terminal) => {
+            hits.truncate(terminal + 1);
+            EventStep {
+                t: hits[terminal].t,
+                y: hits[terminal].y,
+                events: hits,
+                terminated: true,
+            }
+        }
+        None => EventStep {
+            t: t1,
+            y: y1,
+            events: hits,
+            terminated: false,
+        },
+    }
+}
+
+/// Cubic Hermite interpolant of a step, third order accurate
+struct Hermite<T: RealField, const S: usize> {
+    t0: T,
+    dt: T,
+    y0: SVector<T, S>,
+    y1: SVector<T, S>,
+    f0: SVector<T, S>,
+    f1: SVector<T, S>,
+}
+
+impl<T: RealField + From<f64> + Copy, const S: usize> Hermite<T, S> {
+    fn eval(&self, t: T) -> SVector<T, S> {
+        let (one, two, three) = (T::one(), T::from(2.0), T::from(3.0));
+        let s = (t - self.t0) / self.dt;
+        let (s2, s3) = (s * s, s * s * s);
+
+        self.y0 * (two * s3 - three * s2 + one)
+            + self.f0 * ((s3 - two * s2 + s) * self.dt)
+            + self.y1 * (three * s2 - two * s3)
+            + self.f1 * ((s3 - s2) * self.dt)
+    }
+}
+
+/// Finds the zero of the function of event `i` on the interpolant of the step with the
+/// Illinois method. Returns the first point found after the crossing, so the event has occurred
+/// at the returned state.
+fn locate<T, const S: usize>(
+    problem: &dyn OdeEvents<T, S>,
+    interpolant: &Hermite<T, S>,
+    i: usize,
+    g0: T,
+    g1: T,
+) -> (T, SVector<T, S>)
+where
+    T: RealField + From<f64> + Copy,
+{
+    let (t0, t1) = (interpolant.t0, interpolant.t0 + interpolant.dt);
+    let tol = interpolant.dt * T::from(EVENT_TOLERANCE);
+
+    let (mut lo, mut g_lo) = (t0, g0);
+    let (mut hi, mut y_hi, mut g_hi) = (t1, interpolant.y1, g1);
+
+    // Side of the last update, to halve the other end if it is kept twice in a row
+    let mut last_lo = None;
+
+    for _ in 0..EVENT_MAX_ITER {
+        if hi - lo <= tol || g_hi.is_zero() {
+            break;
+        }
+
+        let mut t = (lo * g_hi - hi * g_lo) / (g_hi - g_lo);
+        if t <= lo || t >= hi {
+            t = (lo + hi) / T::from(2.0);
+        }
+
+        let y = interpolant.eval(t);
+        let g = problem.event(i, t, &y);
+
+        if g.is_positive() == g_lo.is_positive() && !g.is_zero() {
+            (lo, g_lo) = (t, g);
+            if last_lo == Some(true) {
+                g_hi /= T::from(2.0);
+            }
+            last_lo = Some(true);
+        } else {
+            (hi, y_hi, g_hi) = (t, y, g);
+            if last_lo == Some(false) {
+                g_lo /= T::from(2.0);
+            }
+            last_lo = Some(false);
+        }
+    }
+
+    (hi, y_hi)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{vector, Vector2};
+
+    use super::*;
+    use crate::math::ode::{DormandPrince45, OdeProblem, RungeKutta4};
+
+    /// Ball thrown upwards at 10 m/s, y = [altitude, vertical speed]
+    struct Ball {
+        terminal_impact: bool,
+    }
+
+    const G: f64 = 9.81;
+
+    impl OdeProblem<f64, 2> for Ball {
+        fn odefun(&self, _: f64, y: Vector2<f64>) -> Vector2<f64> {
+            vector![y[1], -G]
+        }
+    }
+
+    impl OdeEvents<f64, 2> for Ball {
+        fn events(&self) -> Vec<OdeEvent> {
+            vec![
+                // Apogee
+                OdeEvent {
+                    crossing: Crossing::Falling,
+                    terminal: false,
+                },
+                // Impact
+                OdeEvent {
+                    crossing: Crossing::Falling,
+                    terminal: self.terminal_impact,
+                },
+                // Passing 2 m, in either direction
+                OdeEvent {
+                    crossing: Crossing::Both,
+                    terminal: false,
+                },
+            ]
+        }
+
+        fn event(&self, i: usize, _: f64, y: &Vector2<f64>) -> f64 {
+            match i {
+                0 => y[1],
+                1 => y[0],
+                _ => y[0] - 2.0,
+            }
+        }
+    }
+
+    fn simulate(
+        solver: &dyn OdeSolver<f64, 2>,
+        problem: &Ball,
+        dt: f64,
+    ) -> (Vec<(usize, f64)>, EventStep<f64, 2>) {
+        let mut hits = vec![];
+        let mut step = EventStep {
+            t: 0.0,
+            y: vector![0.0, 10.0],
+            events: vec![],
+            terminated: false,
+        };
+
+        while !step.terminated && step.t < 3.0 {
+            step = solver.solve_with_events(problem, step.t, dt, step.y);
+            hits.extend(step.events.iter().map(|h| (h.index, h.t)));
+        }
+
+        (hits, step)
+    }
+
+    #[test]
+    fn test_events() {
+        let t_apogee = 10.0 / G;
+        let t_impact = 20.0 / G;
+        let t_2m = |sign: f64| (10.0 + sign * (100.0f64 - 4.0 * G).sqrt()) / G;
+
+        let ball = Ball {
+            terminal_impact: true,
+        };
+
+        // Located well within the step, the accuracy is the one of the solver
+        let (hits, last) = simulate(&RungeKutta4, &ball, 0.1);
+        let indices: Vec<_> = hits.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, vec![2, 0, 2, 1]);
+
+        let expected = [t_2m(-1.0), t_apogee, t_2m(1.0), t_impact];
+        for ((_, t), expected) in hits.iter().zip(expected) {
+            assert!((t - expected).abs() < 1e-9, "{t} != {expected}");
+        }
+
+        // Stopped at the impact, on the ground side
+        assert!(last.terminated);
+        assert_eq!(last.t, hits[3].1);
+        assert!(last.y[0] <= 0.0 && last.y[0] > -1e-9);
+        assert_eq!(last.events.last().map(|h| h.index), Some(1));
+
+        // Non terminal, the integration continues after the impact
+        let ball = Ball {
+            terminal_impact: false,
+        };
+        let (hits, last) = simulate(&DormandPrince45::new(1e-10, 1e-10), &ball, 0.25);
+        assert_eq!(hits.len(), 4);
+        assert!((hits[3].1 - t_impact).abs() < 1e-8);
+        assert!(!last.terminated && last.t >= 3.0);
+        assert!(last.y[0] < 0.0);
+    }
+
+    #[test]
+    fn test_event_at_start() {
+        // Starting on the ground is not an impact
+        let ball = Ball {
+            terminal_impact: true,
+        };
+        let step = RungeKutta4.solve_with_events(&ball, 0.0, 0.1, vector![0.0, 10.0]);
+        assert!(step.events.is_empty() && !step.terminated);
+        assert_eq!(step.t, 0.1);
+
+        // Without events, the same as solve()
+        assert_eq!(
+            step.y,
+            RungeKutta4.solve(&ball, 0.0, 0.1, vector![0.0, 10.0])
+        );
+    }
+
+    #[test]
+    fn test_adaptive_solver_state() {
+        let ball = Ball {
+            terminal_impact: false,
+        };
+        let y0 = vector![0.0, 10.0];
+
+        // The events in the step do not cost any additional step of the solver
+        let with_events = DormandPrince45::new(1e-10, 1e-10);
+        let plain = DormandPrince45::new(1e-10, 1e-10);
+
+        let step = with_events.solve_with_events(&ball, 0.0, 1.5, y0);
+        assert_eq!(step.events.len(), 2);
+        assert_eq!(step.y, plain.solve(&ball, 0.0, 1.5, y0));
+        assert_eq!(with_events.stats(), plain.stats());
+
+        // And the next step starts from the same step size
+        assert_eq!(
+            with_events.solve(&ball, 1.5, 0.5, step.y),
+            plain.solve(&ball, 1.5, 0.5, step.y)
+        );
+        assert_eq!(with_events.stats(), plain.stats());
+    }
+}
@@ -0,0 +1,274 @@
+use nalgebra::{allocator::Allocator, DefaultAllocator, Dim, OMatrix, OVector, RealField};
+
+use super::{linearize::numerical_jacobian, OdeFun, OdeStep};
+
+/// Newton iterations stop when the correction is below this, relative to the state
+const NEWTON_TOL: f64 = 1e-10;
+const NEWTON_MAX_ITER: usize = 10;
+
+/// Steps whose Newton iterations do not converge are split in two halves, at most this many
+/// times in a row
+const MAX_HALVINGS: usize = 8;
+
+/// Backward Euler method, first order and L-stable
+pub struct BackwardEuler;
+
+/// Trapezoidal rule, second order and A-stable. Very stiff components are not damped, but
+/// oscillate with slowly decreasing amplitude.
+pub struct Trapezoidal;
+
+/// Two stage, second order, L-stable singly diagonally implicit Runge-Kutta method (Alexander)
+pub struct Sdirk2;
+
+impl<T: RealField + From<f64> + Copy> OdeStep<T> for BackwardEuler {
+    fn step<D: Dim>(&self, f: OdeFun<T, D>, t0: T, dt: T, y0: OVector<T, D>) -> OVector<T, D>
+    where
+        DefaultAllocator: Allocator<D> + Allocator<D, D>,
+    {
+        step_halving(
+            &|t0, dt, y0: OVector<T, D>| solve_stage(f, t0 + dt, dt, y0.clone(), y0),
+            t0,
+            dt,
+            y0,
+            0,
+        )
+    }
+}
+
+impl<T: RealField + From<f64> + Copy> OdeStep<T> for Trapezoidal {
+    fn step<D: Dim>(&self, f: OdeFun<T, D>, t0: T, dt: T, y0: OVector<T, D>) -> OVector<T, D>
+    where
+        DefaultAllocator: Allocator<D> + Allocator<D, D>,
+    {
+        let try_step = |t0, dt: T, y0| {
+            let hdt = dt / T::from(2.0);
+            let base = f(t0, &y0) * hdt + y0;
+
+            solve_stage(f, t0 + dt, hdt, base.clone(), base)
+        };
+
+        step_halving(&try_step, t0, dt, y0, 0)
+    }
+}
+
+impl<T: RealField + From<f64> + Copy> OdeStep<T> for Sdirk2 {
+    fn step<D: Dim>(&self, f: OdeFun<T, D>, t0: T, dt: T, y0: OVector<T, D>) -> OVector<T, D>
+    where
+        DefaultAllocator: Allocator<D> + Allocator<D, D>,
+    {
+        let try_step = |t0, dt, y0: OVector<T, D>| {
+            let gamma = T::one() - T::from(0.5).sqrt();
+            let t1 = t0 + gamma * dt;
+
+            let z1 = solve_stage(f, t1, gamma * dt, y0.clone(), y0.clone())?;
+            let base = f(t1, &z1) * ((T::one() - gamma) * dt) + y0;
+
+            // The last stage is the solution
+            solve_stage(f, t0 + dt, gamma * dt, base.clone(), base)
+        };
+
+        step_halving(&try_step, t0, dt, y0, 0)
+    }
+}
+
+/// Takes a step with `try_step`, splitting it in two halves, recursively, when its Newton
+/// iterations do not converge. After `MAX_HALVINGS` splits the step fails, and the state is set
+/// to NaN, as explicit methods do when they diverge.
+fn step_halving<T: RealField + From<f64> + Copy, D: Dim>(
+    try_step: &impl Fn(T, T, OVector<T, D>) -> Option<OVector<T, D>>,
+    t0: T,
+    dt: T,
+    y0: OVector<T, D>,
+    halvings: usize,
+) -> OVector<T, D>
+where
+    DefaultAllocator: Allocator<D>,
+{
+    if let Some(y) = try_step(t0, dt, y0.clone()) {
+        return y;
+    }
+
+    if halvings == MAX_HALVINGS {
+        return y0.map(|_| T::from(f64::NAN));
+    }
+
+    let hdt = dt / T::from(2.0);
+    let y_mid = step_halving(try_step, t0, hdt, y0, halvings + 1);
+    if y_mid.iter().any(|v| !v.is_finite()) {
+        return y_mid;
+    }
+
+    step_halving(try_step, t0 + hdt, hdt, y_mid, halvings + 1)
+}
+
+/// Solves `z = base + h * f(t, z)` for the stage `z` of an implicit method, with simplified
+/// Newton iterations starting from `guess`. The Jacobian is only evaluated once, at `guess`.
+/// Returns `None` if the iteration matrix is singular, or if the iterations do not converge
+/// within `NEWTON_MAX_ITER`.
+fn solve_stage<T: RealField + From<f64> + Copy, D: Dim>(
+    f: OdeFun<T, D>,
+    t: T,
+    h: T,
+    base: OVector<T, D>,
+    guess: OVector<T, D>,
+) -> Option<OVector<T, D>>
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    let (n, _) = guess.shape_generic();
+    let identity = OMatrix::<T, D, D>::identity_generic(n, n);
+
+    let iteration = (identity - numerical_jacobian(f, t, &guess) * h).try_inverse()?;
+
+    let mut z = guess;
+    for _ in 0..NEWTON_MAX_ITER {
+        let residual = &z - &base - f(t, &z) * h;
+        let dz = &iteration * residual;
+        z -= &dz;
+
+        if dz.norm() <= T::from(NEWTON_TOL) * (T::one() + z.norm()) {
+            return Some(z);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{matrix, vector, Matrix2, SVector, Vector1, Vector2};
+
+    use super::*;
+    use crate::math::ode::{jacobian, OdeProblem, OdeSolver, RungeKutta4};
+
+    /// Stiff problem whose solution quickly converges to cos(t)
+    struct Stiff;
+
+    impl OdeProblem<f64, 1> for Stiff {
+        fn odefun(&self, t: f64, y: Vector1<f64>) -> Vector1<f64> {
+            vector![-1000.0 * (y[0] - t.cos()) - t.sin()]
+        }
+    }
+
+    /// Mass on a stiff spring with a light damper, like a ground contact
+    struct Contact;
+
+    const CONTACT: Matrix2<f64> = matrix![0.0, 1.0; -1e6, -20.0];
+
+    impl OdeProblem<f64, 2> for Contact {
+        fn odefun(&self, _: f64, y: Vector2<f64>) -> Vector2<f64> {
+            CONTACT * y
+        }
+    }
+
+    struct Decay;
+
+    impl OdeProblem<f64, 1> for Decay {
+        fn odefun(&self, _: f64, y: Vector1<f64>) -> Vector1<f64> {
+            -y
+        }
+    }
+
+    /// Strongly nonlinear decay, y(t) = 1 / sqrt(1 / y0^2 + 2 t)
+    struct Cubic;
+
+    impl OdeProblem<f64, 1> for Cubic {
+        fn odefun(&self, _: f64, y: Vector1<f64>) -> Vector1<f64> {
+            -y.map(|v| v.powi(3))
+        }
+    }
+
+    /// Derivative only defined for positive states
+    struct Sqrt;
+
+    impl OdeProblem<f64, 1> for Sqrt {
+        fn odefun(&self, _: f64, y: Vector1<f64>) -> Vector1<f64> {
+            y.map(f64::sqrt)
+        }
+    }
+
+    fn integrate<const S: usize>(
+        solver: &dyn OdeSolver<f64, S>,
+        problem: &dyn OdeProblem<f64, S>,
+        y0: SVector<f64, S>,
+        dt: f64,
+        steps: usize,
+    ) -> SVector<f64, S> {
+        (0..steps).fold(y0, |y, i| solver.solve(problem, i as f64 * dt, dt, y))
+    }
+
+    #[test]
+    fn test_jacobian() {
+        let jac = jacobian(&Contact, 0.0, &vector![0.1, -2.0]);
+        assert!((jac - CONTACT).norm() < 1e-3);
+
+        let jac = jacobian(&Stiff, 1.0, &vector![5.0]);
+        assert!((jac[0] + 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_order() {
+        let solvers: [(&dyn OdeSolver<f64, 1>, f64); 3] =
+            [(&BackwardEuler, 2.0), (&Trapezoidal, 4.0), (&Sdirk2, 4.0)];
+
+        for (solver, ratio) in solvers {
+            let err = |steps| {
+                (integrate(solver, &Decay, vector![1.0], 1.0 / steps as f64, steps)
+                    - vector![(-1.0f64).exp()])
+                .norm()
+            };
+
+            // Halving the step divides the error by 2^order
+            let (coarse, fine) = (err(50), err(100));
+            assert!(
+                (coarse / fine - ratio).abs() < 0.1 * ratio,
+                "{coarse} {fine}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_stiff() {
+        // Far outside of the stability region of RK4
+        let dt = 0.01;
+        let y = integrate(&RungeKutta4, &Stiff, vector![2.0], dt, 100);
+        assert!(y[0].is_nan() || y[0].abs() > 1e3);
+
+        let solvers: [&dyn OdeSolver<f64, 1>; 3] = [&BackwardEuler, &Trapezoidal, &Sdirk2];
+        for solver in solvers {
+            let y = integrate(solver, &Stiff, vector![2.0], dt, 100);
+            assert!((y[0] - 1.0f64.cos()).abs() < 1e-3);
+        }
+
+        // The contact oscillates at 160 Hz, much faster than the step: it is damped by the
+        // L-stable methods, but not by the trapezoidal rule
+        for solver in [&BackwardEuler as &dyn OdeSolver<f64, 2>, &Sdirk2] {
+            let y = integrate(solver, &Contact, vector![-0.01, 0.0], 0.01, 100);
+            assert!(y.norm() < 1e-20);
+        }
+
+        let y = integrate(&Trapezoidal, &Contact, vector![-0.01, 0.0], 0.01, 100);
+        assert!(y.norm() > 1.0);
+    }
+
+    #[test]
+    fn test_newton_failure() {
+        // With the Jacobian at the initial state, Newton iterations do not converge in a single
+        // step: it is split until they do
+        let f = |t, y: &Vector1<f64>| Cubic.odefun(t, *y);
+        assert!(solve_stage(&f, 1.0, 1.0, vector![5.0], vector![5.0]).is_none());
+
+        let solvers: [(&dyn OdeSolver<f64, 1>, f64); 3] =
+            [(&BackwardEuler, 0.1), (&Trapezoidal, 0.15), (&Sdirk2, 0.02)];
+        for (solver, tol) in solvers {
+            let y = solver.solve(&Cubic, 0.0, 1.0, vector![5.0]);
+            assert!((y[0] - 1.0 / 2.04f64.sqrt()).abs() < tol, "{y}");
+        }
+
+        // Iterations never converge: the failure is reported as NaN
+        for (solver, _) in solvers {
+            let y = solver.solve(&Sqrt, 0.0, 0.1, vector![-1.0]);
+            assert!(y[0].is_nan());
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use nalgebra::{RealField, SVector, UnitQuaternion, Vector3};
+
+/// ODE whose state is split into a vector `x` and an attitude `q`, rotating vectors from the
+/// body frame to the reference frame. The attitude evolves as `dq/dt = q * (0, w / 2)`, with `w`
+/// the angular velocity in the body frame.
+pub trait LieOdeProblem<T, const S: usize>
+where
+    T: RealField,
+{
+    /// Derivative of `x`, and angular velocity `w`
+    fn odefun(&self, t: T, x: &SVector<T, S>, q: &UnitQuaternion<T>)
+        -> (SVector<T, S>, Vector3<T>);
+}
+
+pub trait LieOdeSolver<T, const S: usize>
+where
+    T: RealField,
+{
+    fn solve(
+        &self,
+        problem: &dyn LieOdeProblem<T, S>,
+        t0: T,
+        dt: T,
+        x0: SVector<T, S>,
+        q0: UnitQuaternion<T>,
+    ) -> (SVector<T, S>, UnitQuaternion<T>);
+}
+
+/// Runge-Kutta-Munthe-Kaas method of order 4: the classic RK4 tableau, with the attitude
+/// written as `q0 * exp(u)` and `u` integrated in the Lie algebra. The attitude is updated with
+/// the exponential map, so it stays a unit quaternion without being renormalized.
+pub struct RungeKuttaMuntheKaas4;
+
+impl<T: RealField + From<f64> + Copy, const S: usize> LieOdeSolver<T, S> for RungeKuttaMuntheKaas4 {
+    fn solve(
+        &self,
+        problem: &dyn LieOdeProblem<T, S>,
+        t0: T,
+        dt: T,
+        x0: SVector<T, S>,
+        q0: UnitQuaternion<T>,
+    ) -> (SVector<T, S>, UnitQuaternion<T>) {
+        let hdt = dt / T::from(2.0);
+
+        // Derivatives of x and u, for a stage at x0 + dx, q0 * exp(u)
+        let stage = |t: T, dx: SVector<T, S>, u: Vector3<T>| {
+            let q = q0 * UnitQuaternion::from_scaled_axis(u);
+            let (xdot, w) = problem.odefun(t, &(x0 + dx), &q);
+
+            (xdot, dexp_inv(&u, &w))
+        };
+
+        let (kx1, ku1) = stage(t0, SVector::zeros(), Vector3::zeros());
+        let (kx2, ku2) = stage(t0 + hdt, kx1 * hdt, ku1 * hdt);
+        let (kx3, ku3) = stage(t0 + hdt, kx2 * hdt, ku2 * hdt);
+        let (kx4, ku4) = stage(t0 + dt, kx3 * dt, ku3 * dt);
+
+        let two = T::from(2.0);
+        let sixth = dt / T::from(6.0);
+        let x = x0 + (kx1 + kx2 * two + kx3 * two + kx4) * sixth;
+        let u = (ku1 + ku2 * two + ku3 * two + ku4) * sixth;
+
+        (x, q0 * UnitQuaternion::from_scaled_axis(u))
+    }
+}
+
+/// Derivative of `u` such that `exp(u)` moves with the body angular velocity `w`, truncated to
+/// the terms needed by a 4th order method
+fn dexp_inv<T: RealField + From<f64> + Copy>(u: &Vector3<T>, w: &Vector3<T>) -> Vector3<T> {
+    let uw = u.cross(w);
+    w + uw / T::from(2.0) + u.cross(&uw) / T::from(12.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{vector, Matrix3, Quaternion, SVector, Vector3, Vector4};
+
+    use super::*;
+    use crate::math::ode::{OdeProblem, OdeSolver, RungeKutta4};
+
+    /// Body spinning at a constant rate around z, with a constant acceleration along its x axis
+    struct Spinning {
+        w: f64,
+    }
+
+    impl Spinning {
+        /// Exact position, starting at rest from the origin
+        fn exact(&self, t: f64) -> Vector3<f64> {
+            vector![(self.w * t).sin(), 1.0 - (self.w * t).cos(), 0.0] / self.w
+        }
+    }
+
+    impl LieOdeProblem<f64, 3> for Spinning {
+        fn odefun(
+            &self,
+            _: f64,
+            _: &SVector<f64, 3>,
+            q: &UnitQuaternion<f64>,
+        ) -> (SVector<f64, 3>, Vector3<f64>) {
+            (q.transform_vector(&Vector3::x()), vector![0.0, 0.0, self.w])
+        }
+    }
+
+    /// The same problem for a vector solver, with the quaternion after the position
+    impl OdeProblem<f64, 7> for Spinning {
+        fn odefun(&self, t: f64, y: SVector<f64, 7>) -> SVector<f64, 7> {
+            let q = UnitQuaternion::from_quaternion(Quaternion::from_vector(
+                y.fixed_rows::<4>(3).clone_owned(),
+            ));
+            let (xdot, w) = LieOdeProblem::odefun(self, t, &y.fixed_rows::<3>(0).into(), &q);
+            let qdot = q.into_inner() * Quaternion::from_vector(Vector4::new(w.x, w.y, w.z, 0.0));
+
+            let mut ydot = SVector::<f64, 7>::zeros();
+            ydot.fixed_rows_mut::<3>(0).copy_from(&xdot);
+            ydot.fixed_rows_mut::<4>(3).copy_from(&(qdot.coords / 2.0));
+            ydot
+        }
+    }
+
+    /// Torque free body, with its angular velocity in the body frame as state
+    struct TorqueFree {
+        inertia: Matrix3<f64>,
+    }
+
+    impl LieOdeProblem<f64, 3> for TorqueFree {
+        fn odefun(
+            &self,
+            _: f64,
+            w: &SVector<f64, 3>,
+            _: &UnitQuaternion<f64>,
+        ) -> (SVector<f64, 3>, Vector3<f64>) {
+            let inv_inertia = self.inertia.try_inverse().unwrap();
+            (inv_inertia * (self.inertia * w).cross(w), *w)
+        }
+    }
+
+    #[test]
+    fn test_spinning() {
+        let problem = Spinning { w: 40.0 };
+        let dt = 0.01;
+
+        let (mut x, mut q) = (Vector3::zeros(), UnitQuaternion::identity());
+        let mut y = SVector::<f64, 7>::zeros();
+        y[6] = 1.0;
+
+        for i in 0..200 {
+            let t = i as f64 * dt;
+            (x, q) = RungeKuttaMuntheKaas4.solve(&problem, t, dt, x, q);
+
+            y = RungeKutta4.solve(&problem, t, dt, y);
+            let n = y.fixed_rows::<4>(3).normalize();
+            y.fixed_rows_mut::<4>(3).copy_from(&n);
+        }
+
+        // The attitude of a constant rotation is exact, and stays normalized
+        let exact_q = UnitQuaternion::from_scaled_axis(vector![0.0, 0.0, problem.w * 2.0]);
+        assert!(q.angle_to(&exact_q) < 1e-10);
+        assert!((q.quaternion().norm() - 1.0).abs() < 1e-12);
+
+        // More accurate than integrating the quaternion as a vector
+        let exact = problem.exact(2.0);
+        let err = (x - exact).norm();
+        let err_rk4 = (y.fixed_rows::<3>(0) - exact).norm();
+        assert!(err < 1e-6);
+        assert!(err < err_rk4 / 10.0);
+    }
+
+    #[test]
+    fn test_torque_free() {
+        let problem = TorqueFree {
+            inertia: Matrix3::from_diagonal(&vector![0.005, 0.26, 0.27]),
+        };
+        let (w0, q0) = (vector![0.2, 5.0, 0.1], UnitQuaternion::identity());
+        let momentum =
+            |w: &Vector3<f64>, q: &UnitQuaternion<f64>| q.transform_vector(&(problem.inertia * w));
+        let energy = |w: &Vector3<f64>| w.dot(&(problem.inertia * w)) / 2.0;
+
+        let (mut w, mut q) = (w0, q0);
+        for i in 0..1000 {
+            (w, q) = RungeKuttaMuntheKaas4.solve(&problem, i as f64 * 0.001, 0.001, w, q);
+        }
+
+        // Angular momentum in the reference frame and kinetic energy are conserved
+        assert!((momentum(&w, &q) - momentum(&w0, &q0)).norm() < 1e-8);
+        assert!((energy(&w) - energy(&w0)).abs() < 1e-8);
+        assert!((q.quaternion().norm() - 1.0).abs() < 1e-12);
+    }
+}
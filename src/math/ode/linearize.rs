@@ -0,0 +1,385 @@
+use std::ops::{Div, Sub};
+
+use nalgebra::{
+    allocator::Allocator, DMatrix, DVector, DefaultAllocator, Dim, OMatrix, OVector, RealField,
+    SMatrix, SVector,
+};
+use thiserror::Error;
+
+use super::{OdeFun, OdeProblem};
+
+/// ODE with an input `u`, such as actuator commands, that is constant during each step
+pub trait ControlledOdeProblem<T, const S: usize, const U: usize>
+where
+    T: RealField,
+{
+    fn odefun(&self, t: T, x: SVector<T, S>, u: SVector<T, U>) -> SVector<T, S>;
+}
+
+/// A controlled problem with a constant input, to be integrated by any `OdeSolver`
+pub struct ConstantInput<'a, T: RealField, const S: usize, const U: usize> {
+    pub problem: &'a dyn ControlledOdeProblem<T, S, U>,
+    pub u: SVector<T, U>,
+}
+
+impl<T: RealField, const S: usize, const U: usize> OdeProblem<T, S> for ConstantInput<'_, T, S, U> {
+    fn odefun(&self, t: T, y: SVector<T, S>) -> SVector<T, S> {
+        self.problem.odefun(t, y, self.u.clone())
+    }
+}
+
+/// Linear model `dx/dt = a * (x - x0) + b * (u - u0)` around the operating point `(x0, u0)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Linearization<T: RealField, const S: usize, const U: usize> {
+    pub x0: SVector<T, S>,
+    pub u0: SVector<T, U>,
+    pub a: SMatrix<T, S, S>,
+    pub b: SMatrix<T, S, U>,
+}
+
+/// Jacobian of the derivative of `problem` with respect to the state, by central differences
+pub fn jacobian<T: RealField + From<f64> + Copy, const S: usize>(
+    problem: &dyn OdeProblem<T, S>,
+    t: T,
+    y: &SVector<T, S>,
+) -> SMatrix<T, S, S> {
+    numerical_jacobian(&|t, y| problem.odefun(t, *y), t, y)
+}
+
+/// Jacobian of `f` with respect to the state, for states of any size
+pub(super) fn numerical_jacobian<T: RealField + From<f64> + Copy, D: Dim>(
+    f: OdeFun<T, D>,
+    t: T,
+    y: &OVector<T, D>,
+) -> OMatrix<T, D, D>
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    let (n, _) = y.shape_generic();
+    let mut jac = OMatrix::<T, D, D>::zeros_generic(n, n);
+
+    for j in 0..y.len() {
+        jac.set_column(
+            j,
+            &central_difference(y[j], |yj| {
+                let mut y = y.clone();
+                y[j] = yj;
+                f(t, &y)
+            }),
+        );
+    }
+
+    jac
+}
+
+/// Jacobians of the derivative of `problem` with respect to the state and the input, at
+/// `(x0, u0)`, by central differences
+pub fn linearize<T: RealField + From<f64> + Copy, const S: usize, const U: usize>(
+    problem: &dyn ControlledOdeProblem<T, S, U>,
+    t: T,
+    x0: SVector<T, S>,
+    u0: SVector<T, U>,
+) -> Linearization<T, S, U> {
+    let a = jacobian(&ConstantInput { problem, u: u0 }, t, &x0);
+
+    let mut b = SMatrix::<T, S, U>::zeros();
+    for j in 0..U {
+        b.set_column(
+            j,
+            &central_difference(u0[j], |uj| {
+                let mut u = u0;
+                u[j] = uj;
+                problem.odefun(t, x0, u)
+            }),
+        );
+    }
+
+    Linearization { x0, u0, a, b }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TrimError<T: RealField> {
+    #[error("Nothing to trim: no free variable or no derivative to cancel")]
+    Empty,
+
+    #[error("Trim did not converge after {iterations} iterations, residual {residual}")]
+    NotConverged { iterations: usize, residual: T },
+}
+
+/// Steady state search: finds the free states and inputs for which the selected derivatives
+/// are zero, with the Levenberg-Marquardt method. The other states and inputs keep the value of
+/// the initial guess.
+///
+/// For example, a hover is found by fixing the position and velocities and cancelling all
+/// derivatives, with the attitude and inputs free. A cruise at constant speed is found in the
+/// same way, without cancelling the derivative of the position.
+#[derive(Debug, Clone)]
+pub struct Trim<T, const S: usize, const U: usize> {
+    pub free_states: [bool; S],
+    pub free_inputs: [bool; U],
+    pub zero_derivatives: [bool; S],
+
+    /// Converged when the norm of the selected derivatives is below this
+    pub tol: T,
+    pub max_iter: usize,
+}
+
+/// Result of a trim
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrimPoint<T: RealField, const S: usize, const U: usize> {
+    pub x: SVector<T, S>,
+    pub u: SVector<T, U>,
+
+    /// Norm of the selected derivatives at `(x, u)`
+    pub residual: T,
+    pub iterations: usize,
+}
+
+impl<T: RealField + From<f64> + Copy, const S: usize, const U: usize> Trim<T, S, U> {
+    /// Every state and input free, every derivative cancelled
+    pub fn new() -> Self {
+        Trim {
+            free_states: [true; S],
+            free_inputs: [true; U],
+            zero_derivatives: [true; S],
+            tol: T::from(1e-9),
+            max_iter: 100,
+        }
+    }
+
+    pub fn solve(
+        &self,
+        problem: &dyn ControlledOdeProblem<T, S, U>,
+        t: T,
+        x_guess: SVector<T, S>,
+        u_guess: SVector<T, U>,
+    ) -> Result<TrimPoint<T, S, U>, TrimError<T>> {
+        // Free variables, indexed in [x, u]
+        let free: Vec<_> = self
+            .free_states
+            .iter()
+            .chain(&self.free_inputs)
+            .enumerate()
+            .filter_map(|(i, free)| free.then_some(i))
+            .collect();
+
+        if free.is_empty() || !self.zero_derivatives.contains(&true) {
+            return Err(TrimError::Empty);
+        }
+
+        let split = |z: &DVector<T>| {
+            (
+                SVector::<T, S>::from_fn(|i, _| z[i]),
+                SVector::<T, U>::from_fn(|i, _| z[S + i]),
+            )
+        };
+        let residual = |z: &DVector<T>| {
+            let (x, u) = split(z);
+            let xdot = problem.odefun(t, x, u);
+
+            DVector::from_iterator(
+                self.zero_derivatives.iter().filter(|z| **z).count(),
+                xdot.iter()
+                    .zip(&self.zero_derivatives)
+                    .filter_map(|(v, zero)| zero.then_some(*v)),
+            )
+        };
+
+        let mut z = DVector::from_iterator(S + U, x_guess.iter().chain(u_guess.iter()).copied());
+        let mut r = residual(&z);
+        let mut lambda = T::from(1e-3);
+
+        for iteration in 0..=self.max_iter {
+            if r.norm() <= self.tol {
+                let (x, u) = split(&z);
+                return Ok(TrimPoint {
+                    x,
+                    u,
+                    residual: r.norm(),
+                    iterations: iteration,
+                });
+            } else if iteration == self.max_iter {
+                break;
+            }
+
+            let mut jac = DMatrix::zeros(r.len(), free.len());
+            for (j, &i) in free.iter().enumerate() {
+                jac.set_column(
+                    j,
+                    &central_difference(z[i], |zi| {
+                        let mut z = z.clone();
+                        z[i] = zi;
+                        residual(&z)
+                    }),
+                );
+            }
+
+            let jtj = jac.transpose() * &jac;
+            let grad = jac.transpose() * &r;
+
+            // Increase the damping until the step decreases the residual
+            loop {
+                let mut lhs = jtj.clone();
+                for k in 0..free.len() {
+                    lhs[(k, k)] += lambda * jtj[(k, k)].max(T::from(1e-12));
+                }
+
+                if let Some(step) = lhs.lu().solve(&-&grad) {
+                    let mut next = z.clone();
+                    for (k, &i) in free.iter().enumerate() {
+                        next[i] += step[k];
+                    }
+
+                    let r_next = residual(&next);
+                    if r_next.norm() < r.norm() {
+                        (z, r) = (next, r_next);
+                        lambda = (lambda / T::from(10.0)).max(T::from(1e-12));
+                        break;
+                    }
+                }
+
+                lambda *= T::from(10.0);
+                if lambda > T::from(1e12) {
+                    return Err(TrimError::NotConverged {
+                        iterations: iteration,
+                        residual: r.norm(),
+                    });
+                }
+            }
+        }
+
+        Err(TrimError::NotConverged {
+            iterations: self.max_iter,
+            residual: r.norm(),
+        })
+    }
+}
+
+impl<T: RealField + From<f64> + Copy, const S: usize, const U: usize> Default for Trim<T, S, U> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Derivative of `f` at `x` by central differences, with a step relative to `x`
+fn central_difference<T, V>(x: T, f: impl Fn(T) -> V) -> V
+where
+    T: RealField + Copy,
+    V: Sub<Output = V> + Div<T, Output = V>,
+{
+    let h = T::default_epsilon().cbrt() * x.abs().max(T::one());
+    (f(x + h) - f(x - h)) / (h + h)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{vector, Vector2, Vector6};
+
+    use super::*;
+    use crate::math::ode::{OdeSolver, RungeKutta4};
+
+    const M: f64 = 1.5;
+    const L: f64 = 0.2;
+    const I: f64 = 0.02;
+    const G: f64 = 9.81;
+    const DRAG: f64 = 0.3;
+
+    /// Planar quadcopter, x = [x, z, pitch, vx, vz, pitch rate] with z up, u = motor thrusts
+    struct Planar;
+
+    impl ControlledOdeProblem<f64, 6, 2> for Planar {
+        fn odefun(&self, _: f64, x: Vector6<f64>, u: Vector2<f64>) -> Vector6<f64> {
+            let thrust = u[0] + u[1];
+
+            vector![
+                x[3],
+                x[4],
+                x[5],
+                -thrust * x[2].sin() / M - DRAG * x[3],
+                thrust * x[2].cos() / M - G - DRAG * x[4],
+                (u[1] - u[0]) * L / I
+            ]
+        }
+    }
+
+    fn hover_trim() -> Trim<f64, 6, 2> {
+        let mut trim = Trim::new();
+        trim.free_states = [false, false, true, false, false, false];
+        trim
+    }
+
+    #[test]
+    fn test_hover() {
+        let x_guess = vector![0.0, 10.0, 0.2, 0.0, 0.0, 0.0];
+        let hover = hover_trim()
+            .solve(&Planar, 0.0, x_guess, vector![5.0, 9.0])
+            .unwrap();
+
+        assert!(hover.residual <= 1e-9);
+        assert!(hover.x[2].abs() < 1e-9);
+        assert!((hover.u - Vector2::repeat(M * G / 2.0)).norm() < 1e-8);
+        assert_eq!(hover.x[1], 10.0);
+
+        // Stays in place
+        let input = ConstantInput {
+            problem: &Planar,
+            u: hover.u,
+        };
+        let x = (0..100).fold(hover.x, |x, i| {
+            RungeKutta4.solve(&input, i as f64 * 0.01, 0.01, x)
+        });
+        assert!((x - hover.x).norm() < 1e-6);
+
+        let lin = linearize(&Planar, 0.0, hover.x, hover.u);
+        let mut a = SMatrix::<f64, 6, 6>::zeros();
+        a[(0, 3)] = 1.0;
+        a[(1, 4)] = 1.0;
+        a[(2, 5)] = 1.0;
+        a[(3, 2)] = -G;
+        a[(3, 3)] = -DRAG;
+        a[(4, 4)] = -DRAG;
+        assert!((lin.a - a).norm() < 1e-6);
+
+        let mut b = SMatrix::<f64, 6, 2>::zeros();
+        b[(4, 0)] = 1.0 / M;
+        b[(4, 1)] = 1.0 / M;
+        b[(5, 0)] = -L / I;
+        b[(5, 1)] = L / I;
+        assert!((lin.b - b).norm() < 1e-6);
+    }
+
+    #[test]
+    fn test_cruise() {
+        // Constant forward speed, the position keeps changing
+        let mut trim = hover_trim();
+        trim.zero_derivatives[0] = false;
+
+        let x_guess = vector![0.0, 10.0, 0.0, 5.0, 0.0, 0.0];
+        let cruise = trim
+            .solve(&Planar, 0.0, x_guess, vector![7.0, 7.0])
+            .unwrap();
+
+        // Tilted forward to compensate the drag
+        let pitch = (-DRAG * 5.0 / G).atan();
+        assert!((cruise.x[2] - pitch).abs() < 1e-8);
+        assert!((cruise.u[0] - cruise.u[1]).abs() < 1e-8);
+        assert_eq!(cruise.x[3], 5.0);
+    }
+
+    #[test]
+    fn test_trim_errors() {
+        let mut trim = hover_trim();
+        trim.free_states = [false; 6];
+        trim.free_inputs = [false; 2];
+        assert_eq!(
+            trim.solve(&Planar, 0.0, Vector6::zeros(), Vector2::zeros()),
+            Err(TrimError::Empty)
+        );
+
+        // Without the inputs, nothing can compensate the gravity
+        let mut trim = hover_trim();
+        trim.free_inputs = [false; 2];
+        let res = trim.solve(&Planar, 0.0, Vector6::zeros(), Vector2::zeros());
+        assert!(matches!(res, Err(TrimError::NotConverged { .. })));
+    }
+}
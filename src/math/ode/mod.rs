@@ -1,3 +1,13 @@
+mod dopri;
+mod events;
+mod implicit;
+mod lie;
+mod linearize;
 mod ode;
 
-pub use ode::*;
\ No newline at end of file
+pub use dopri::*;
+pub use events::*;
+pub use implicit::*;
+pub use lie::*;
+pub use linearize::*;
+pub use ode::*;
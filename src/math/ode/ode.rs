@@ -1,4 +1,6 @@
-use nalgebra::{RealField, SVector};
+use nalgebra::{allocator::Allocator, DVector, DefaultAllocator, Dim, OVector, RealField, SVector};
+
+use super::events::{self, EventStep, OdeEvent};
 
 pub trait OdeSolver<T, const S: usize> {
     fn solve(
@@ -8,6 +10,21 @@ pub trait OdeSolver<T, const S: usize> {
         dt: T,
         y0: SVector<T, S>,
     ) -> SVector<T, S>;
+
+    /// Same as `solve()`, but also locates the events of `problem` that occur during the step.
+    /// If one of them is terminal, the step ends at the first terminal event.
+    fn solve_with_events(
+        &self,
+        problem: &dyn OdeEvents<T, S>,
+        t0: T,
+        dt: T,
+        y0: SVector<T, S>,
+    ) -> EventStep<T, S>
+    where
+        T: RealField + From<f64> + Copy,
+    {
+        events::solve_with_events(self, problem, t0, dt, y0)
+    }
 }
 
 pub trait OdeProblem<T, const S: usize>
@@ -17,9 +34,47 @@ where
     fn odefun(&self, t: T, y: SVector<T, S>) -> SVector<T, S>;
 }
 
-pub struct ForwardEuler;
+/// Events of an `OdeProblem`, located by `OdeSolver::solve_with_events()`
+pub trait OdeEvents<T, const S: usize>: OdeProblem<T, S>
+where
+    T: RealField,
+{
+    /// Events of the problem, which occur when the sign of their function `event()` changes
+    fn events(&self) -> Vec<OdeEvent>;
+
+    /// Function of the event at `index` in `events()`
+    fn event(&self, index: usize, t: T, y: &SVector<T, S>) -> T;
+}
 
-impl<T: RealField, const S: usize> OdeSolver<T, S> for ForwardEuler {
+/// Dynamically sized version of `OdeProblem`, for states whose size is only known at runtime
+pub trait DOdeProblem<T>
+where
+    T: RealField,
+{
+    fn odefun(&self, t: T, y: DVector<T>) -> DVector<T>;
+}
+
+/// Dynamically sized version of `OdeSolver`. The method has its own name, since every `OdeStep`
+/// implements both traits.
+pub trait DOdeSolver<T> {
+    fn solve_dyn(&self, problem: &dyn DOdeProblem<T>, t0: T, dt: T, y0: DVector<T>) -> DVector<T>;
+}
+
+/// Derivative of a problem of any size, as seen by `OdeStep`
+pub type OdeFun<'a, T, D> = &'a dyn Fn(T, &OVector<T, D>) -> OVector<T, D>;
+
+/// Implementation of a solver for states of any size. Every `OdeStep` is both an `OdeSolver`
+/// and a `DOdeSolver`.
+pub trait OdeStep<T>
+where
+    T: RealField,
+{
+    fn step<D: Dim>(&self, f: OdeFun<T, D>, t0: T, dt: T, y0: OVector<T, D>) -> OVector<T, D>
+    where
+        DefaultAllocator: Allocator<D> + Allocator<D, D>;
+}
+
+impl<T: RealField, const S: usize, M: OdeStep<T>> OdeSolver<T, S> for M {
     fn solve(
         &self,
         problem: &dyn OdeProblem<T, S>,
@@ -27,26 +82,125 @@ impl<T: RealField, const S: usize> OdeSolver<T, S> for ForwardEuler {
         dt: T,
         y0: SVector<T, S>,
     ) -> SVector<T, S> {
-        y0.clone() + problem.odefun(t0, y0) * dt
+        self.step(&|t, y| problem.odefun(t, y.clone()), t0, dt, y0)
+    }
+}
+
+impl<T: RealField, M: OdeStep<T>> DOdeSolver<T> for M {
+    fn solve_dyn(&self, problem: &dyn DOdeProblem<T>, t0: T, dt: T, y0: DVector<T>) -> DVector<T> {
+        self.step(&|t, y| problem.odefun(t, y.clone()), t0, dt, y0)
+    }
+}
+
+pub struct ForwardEuler;
+
+impl<T: RealField> OdeStep<T> for ForwardEuler {
+    fn step<D: Dim>(&self, f: OdeFun<T, D>, t0: T, dt: T, y0: OVector<T, D>) -> OVector<T, D>
+    where
+        DefaultAllocator: Allocator<D> + Allocator<D, D>,
+    {
+        f(t0, &y0) * dt + y0
     }
 }
 
 pub struct RungeKutta4;
 
-impl<T: RealField + From<f64> + Copy, const S: usize> OdeSolver<T, S> for RungeKutta4 {
-    fn solve(
-        &self,
-        problem: &dyn OdeProblem<T, S>,
-        t0: T,
-        dt: T,
-        y0: SVector<T, S>,
-    ) -> SVector<T, S> {
+impl<T: RealField + From<f64> + Copy> OdeStep<T> for RungeKutta4 {
+    fn step<D: Dim>(&self, f: OdeFun<T, D>, t0: T, dt: T, y0: OVector<T, D>) -> OVector<T, D>
+    where
+        DefaultAllocator: Allocator<D> + Allocator<D, D>,
+    {
         let hdt = dt / T::from(2.0);
-        let k1 = problem.odefun(t0, y0);
-        let k2 = problem.odefun(t0 + dt / 2.0.into(), y0 + k1 * hdt);
-        let k3 = problem.odefun(t0 + hdt, y0 + k2 * hdt);
-        let k4 = problem.odefun(t0 + dt, y0 + k3 * dt);
+        let k1 = f(t0, &y0);
+        let k2 = f(t0 + dt / 2.0.into(), &(&y0 + &k1 * hdt));
+        let k3 = f(t0 + hdt, &(&y0 + &k2 * hdt));
+        let k4 = f(t0 + dt, &(&y0 + &k3 * dt));
 
         y0 + (k1 + k2 * T::from(2.0) + k3 * T::from(2.0) + k4) * dt / T::from(6.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{DVector, SVector};
+
+    use super::*;
+    use crate::math::ode::{DormandPrince45, Sdirk2};
+
+    /// Vertical dynamics of a multicopter with any number of motors, whose speeds follow the
+    /// command with a first order lag. y = [altitude, vertical speed, motor speeds...]
+    struct Multicopter {
+        motors: usize,
+        command: f64,
+    }
+
+    const MASS: f64 = 1.2;
+    const THRUST_COEFF: f64 = 2e-6;
+    const TAU: f64 = 0.05;
+
+    impl Multicopter {
+        fn derivative<'a>(&self, y: impl Iterator<Item = &'a f64>) -> Vec<f64> {
+            let y: Vec<_> = y.copied().collect();
+            let thrust: f64 = y[2..].iter().map(|w| THRUST_COEFF * w * w).sum();
+
+            [y[1], thrust / MASS - 9.81]
+                .into_iter()
+                .chain(y[2..].iter().map(|w| (self.command - w) / TAU))
+                .collect()
+        }
+
+        fn y0(&self) -> Vec<f64> {
+            vec![0.0; self.motors + 2]
+        }
+    }
+
+    impl DOdeProblem<f64> for Multicopter {
+        fn odefun(&self, _: f64, y: DVector<f64>) -> DVector<f64> {
+            DVector::from_vec(self.derivative(y.iter()))
+        }
+    }
+
+    impl OdeProblem<f64, 6> for Multicopter {
+        fn odefun(&self, _: f64, y: SVector<f64, 6>) -> SVector<f64, 6> {
+            SVector::from_vec(self.derivative(y.iter()))
+        }
+    }
+
+    fn integrate_dyn(solver: &dyn DOdeSolver<f64>, problem: &Multicopter) -> DVector<f64> {
+        (0..200).fold(DVector::from_vec(problem.y0()), |y, i| {
+            solver.solve_dyn(problem, i as f64 * 0.01, 0.01, y)
+        })
+    }
+
+    #[test]
+    fn test_dynamic_size() {
+        // Static and dynamic states give the same results with the same solver
+        let quad = Multicopter {
+            motors: 4,
+            command: 1200.0,
+        };
+        let y = integrate_dyn(&RungeKutta4, &quad);
+        let y_static = (0..200).fold(SVector::from_vec(quad.y0()), |y, i| {
+            RungeKutta4.solve(&quad, i as f64 * 0.01, 0.01, y)
+        });
+        assert_eq!(y.as_slice(), y_static.as_slice());
+
+        // Hovering with any number of motors
+        for motors in [4, 6, 8] {
+            let command = (MASS * 9.81 / (THRUST_COEFF * motors as f64)).sqrt();
+            let multicopter = Multicopter { motors, command };
+
+            let solvers: [&dyn DOdeSolver<f64>; 3] =
+                [&RungeKutta4, &DormandPrince45::new(1e-8, 1e-8), &Sdirk2];
+            for solver in solvers {
+                let y = integrate_dyn(solver, &multicopter);
+                assert_eq!(y.len(), motors + 2);
+                assert!(y.rows(2, motors).iter().all(|w| (w - command).abs() < 1e-3));
+
+                // Sinks while the motors spin up, then holds its vertical speed
+                let dy = DOdeProblem::odefun(&multicopter, 2.0, y.clone());
+                assert!(y[1] < 0.0 && dy[1].abs() < 1e-3);
+            }
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use nalgebra::{
+    Matrix3, Quaternion, SVector, UnitQuaternion, Vector3, Vector4, VectorView, VectorViewMut, U1,
+    U13, U3, U4,
+};
+
+use super::ode::OdeProblem;
+
+/// State of a rigid body: position and velocity in the navigation frame (NED), attitude from the
+/// body to the navigation frame, stored as the `[x, y, z, w]` quaternion coordinates, and angular
+/// velocity in the body frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RigidBodyState(pub SVector<f64, 13>);
+
+impl RigidBodyState {
+    pub fn new(
+        pos_n: &Vector3<f64>,
+        vel_n: &Vector3<f64>,
+        q_nb: &UnitQuaternion<f64>,
+        angvel_b: &Vector3<f64>,
+    ) -> Self {
+        let mut state = Self::default();
+
+        state.pos_n_mut().copy_from(pos_n);
+        state.vel_n_mut().copy_from(vel_n);
+        state.quat_nb_vec_mut().copy_from(q_nb.as_vector());
+        state.angvel_b_mut().copy_from(angvel_b);
+
+        state
+    }
+
+    pub fn pos_n(&self) -> VectorView<'_, f64, U3, U1, U13> {
+        self.0.fixed_rows::<3>(0)
+    }
+
+    pub fn vel_n(&self) -> VectorView<'_, f64, U3, U1, U13> {
+        self.0.fixed_rows::<3>(3)
+    }
+
+    pub fn quat_nb_vec(&self) -> VectorView<'_, f64, U4, U1, U13> {
+        self.0.fixed_rows::<4>(6)
+    }
+
+    pub fn angvel_b(&self) -> VectorView<'_, f64, U3, U1, U13> {
+        self.0.fixed_rows::<3>(10)
+    }
+
+    pub fn pos_n_mut(&mut self) -> VectorViewMut<'_, f64, U3, U1, U13> {
+        self.0.fixed_rows_mut::<3>(0)
+    }
+
+    pub fn vel_n_mut(&mut self) -> VectorViewMut<'_, f64, U3, U1, U13> {
+        self.0.fixed_rows_mut::<3>(3)
+    }
+
+    pub fn quat_nb_vec_mut(&mut self) -> VectorViewMut<'_, f64, U4, U1, U13> {
+        self.0.fixed_rows_mut::<4>(6)
+    }
+
+    pub fn angvel_b_mut(&mut self) -> VectorViewMut<'_, f64, U3, U1, U13> {
+        self.0.fixed_rows_mut::<3>(10)
+    }
+
+    pub fn quat_nb(&self) -> UnitQuaternion<f64> {
+        UnitQuaternion::from_quaternion(Quaternion::from_vector(self.quat_nb_vec().clone_owned()))
+    }
+
+    /// Velocity in the body frame
+    pub fn vel_b(&self) -> Vector3<f64> {
+        self.quat_nb()
+            .inverse_transform_vector(&self.vel_n().clone_owned())
+    }
+
+    pub fn normalize_quat(&mut self) {
+        let n = self.quat_nb_vec().normalize();
+        self.quat_nb_vec_mut().set_column(0, &n);
+    }
+}
+
+/// Forces and moments applied to a rigid body. Forces may be expressed in either frame, and
+/// are applied at the center of mass.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Wrench {
+    pub force_n: Vector3<f64>,
+    pub force_b: Vector3<f64>,
+    pub moment_b: Vector3<f64>,
+}
+
+impl std::ops::Add for Wrench {
+    type Output = Wrench;
+
+    fn add(self, rhs: Wrench) -> Wrench {
+        Wrench {
+            force_n: self.force_n + rhs.force_n,
+            force_b: self.force_b + rhs.force_b,
+            moment_b: self.moment_b + rhs.moment_b,
+        }
+    }
+}
+
+/// Source of forces and moments acting on a rigid body, such as gravity, thrust, aerodynamics
+/// or the contact with the ground
+pub trait ForceContributor {
+    fn wrench(&self, t: f64, state: &RigidBodyState, body: &RigidBody) -> Wrench;
+}
+
+/// Uniform gravity field
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gravity {
+    pub g_n: Vector3<f64>,
+}
+
+impl ForceContributor for Gravity {
+    fn wrench(&self, _: f64, _: &RigidBodyState, body: &RigidBody) -> Wrench {
+        Wrench {
+            force_n: self.g_n * body.mass(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Flat ground modeled as a spring and a damper, acting on the center of mass when it is below
+/// the ground. The ground only pushes, and friction is not modeled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GroundContact {
+    /// Down coordinate of the ground in the navigation frame
+    pub ground_d: f64,
+    pub stiffness: f64,
+    pub damping: f64,
+}
+
+impl ForceContributor for GroundContact {
+    fn wrench(&self, _: f64, state: &RigidBodyState, _: &RigidBody) -> Wrench {
+        let penetration = state.pos_n()[2] - self.ground_d;
+        if penetration <= 0.0 {
+            return Wrench::default();
+        }
+
+        let push = self.stiffness * penetration + self.damping * state.vel_n()[2];
+
+        Wrench {
+            force_n: Vector3::new(0.0, 0.0, -push.max(0.0)),
+            ..Default::default()
+        }
+    }
+}
+
+/// Mass properties of a rigid body, and its equations of motion: Newton's law for the center of
+/// mass, quaternion kinematics and Euler's rotation equation
+#[derive(Debug, Clone, PartialEq)]
+pub struct RigidBody {
+    mass: f64,
+    inertia: Matrix3<f64>,
+    inv_inertia: Matrix3<f64>,
+}
+
+impl RigidBody {
+    /// Returns `None` if the inertia matrix is not invertible
+    pub fn new(mass: f64, inertia: Matrix3<f64>) -> Option<Self> {
+        Some(RigidBody {
+            mass,
+            inertia,
+            inv_inertia: inertia.try_inverse()?,
+        })
+    }
+
+    pub fn mass(&self) -> f64 {
+        self.mass
+    }
+
+    pub fn inertia(&self) -> &Matrix3<f64> {
+        &self.inertia
+    }
+
+    /// Sum of the forces and moments of all the contributors
+    pub fn wrench(
+        &self,
+        t: f64,
+        state: &RigidBodyState,
+        contributors: &[&dyn ForceContributor],
+    ) -> Wrench {
+        contributors
+            .iter()
+            .fold(Wrench::default(), |w, c| w + c.wrench(t, state, self))
+    }
+
+    /// Derivative of the state under the action of the contributors
+    pub fn derivative(
+        &self,
+        t: f64,
+        state: &RigidBodyState,
+        contributors: &[&dyn ForceContributor],
+    ) -> RigidBodyState {
+        self.derivative_with(state, &self.wrench(t, state, contributors))
+    }
+
+    /// Derivative of the state under the action of an already computed wrench
+    pub fn derivative_with(&self, state: &RigidBodyState, wrench: &Wrench) -> RigidBodyState {
+        let q_nb = state.quat_nb();
+        let w_b = state.angvel_b().clone_owned();
+
+        let f_n = wrench.force_n + q_nb.transform_vector(&wrench.force_b);
+        let acc_n = f_n / self.mass;
+
+        let qw = Quaternion::from_vector(Vector4::new(w_b[0], w_b[1], w_b[2], 0.0) / 2.0);
+        let qdot = q_nb.into_inner() * qw;
+
+        let w_dot = self.inv_inertia * (wrench.moment_b + (self.inertia * w_b).cross(&w_b));
+
+        let mut dstate = RigidBodyState::default();
+        dstate.pos_n_mut().copy_from(&state.vel_n());
+        dstate.vel_n_mut().copy_from(&acc_n);
+        dstate.quat_nb_vec_mut().copy_from(qdot.as_vector());
+        dstate.angvel_b_mut().copy_from(&w_dot);
+
+        dstate
+    }
+}
+
+/// A rigid body with the contributors it owns, for bodies that do not need to access their
+/// contributors from outside of the integration
+pub struct RigidBodyProblem {
+    pub body: RigidBody,
+    pub contributors: Vec<Box<dyn ForceContributor + Send>>,
+}
+
+impl OdeProblem<f64, 13> for RigidBodyProblem {
+    fn odefun(&self, t: f64, y: SVector<f64, 13>) -> SVector<f64, 13> {
+        let state = RigidBodyState(y);
+        let wrench = self.contributors.iter().fold(Wrench::default(), |w, c| {
+            w + c.wrench(t, &state, &self.body)
+        });
+
+        self.body.derivative_with(&state, &wrench).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::vector;
+
+    use super::*;
+    use crate::math::ode::{OdeSolver, RungeKutta4};
+
+    const G: f64 = 9.81;
+
+    fn simulate(
+        problem: &RigidBodyProblem,
+        state: RigidBodyState,
+        dt: f64,
+        steps: usize,
+    ) -> RigidBodyState {
+        (0..steps).fold(state, |s, i| {
+            let mut next = RigidBodyState(RungeKutta4.solve(problem, i as f64 * dt, dt, s.0));
+            next.normalize_quat();
+            next
+        })
+    }
+
+    /// Constant force and moment along the body axes
+    struct Constant(Wrench);
+
+    impl ForceContributor for Constant {
+        fn wrench(&self, _: f64, _: &RigidBodyState, _: &RigidBody) -> Wrench {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_free_fall() {
+        let body = RigidBody::new(2.0, Matrix3::identity()).unwrap();
+        let problem = RigidBodyProblem {
+            body,
+            contributors: vec![Box::new(Gravity {
+                g_n: vector![0.0, 0.0, G],
+            })],
+        };
+
+        let state = RigidBodyState::new(
+            &vector![0.0, 0.0, -100.0],
+            &vector![1.0, 0.0, 0.0],
+            &UnitQuaternion::from_euler_angles(0.3, 0.2, 0.1),
+            &Vector3::zeros(),
+        );
+        let state = simulate(&problem, state, 0.01, 200);
+
+        // Independent of the mass and of the attitude
+        let expected = vector![2.0, 0.0, -100.0 + G * 2.0];
+        assert!((state.pos_n() - expected).norm() < 1e-9);
+        assert!((state.vel_n() - vector![1.0, 0.0, G * 2.0]).norm() < 1e-9);
+
+        assert!(RigidBody::new(1.0, Matrix3::zeros()).is_none());
+    }
+
+    #[test]
+    fn test_body_frame() {
+        // Forward force on a body yawed by 90 degrees pushes it east
+        let body = RigidBody::new(4.0, Matrix3::from_diagonal(&vector![1.0, 2.0, 2.0])).unwrap();
+        let state = RigidBodyState::new(
+            &Vector3::zeros(),
+            &Vector3::zeros(),
+            &UnitQuaternion::from_euler_angles(0.0, 0.0, std::f64::consts::FRAC_PI_2),
+            &Vector3::zeros(),
+        );
+        let push = Constant(Wrench {
+            force_b: vector![8.0, 0.0, 0.0],
+            moment_b: vector![0.0, 0.0, 1.0],
+            ..Default::default()
+        });
+
+        let dstate = body.derivative(0.0, &state, &[&push]);
+        assert!((dstate.vel_n() - vector![0.0, 2.0, 0.0]).norm() < 1e-12);
+        assert!((dstate.angvel_b() - vector![0.0, 0.0, 0.5]).norm() < 1e-12);
+        assert_eq!(dstate.pos_n(), Vector3::zeros());
+    }
+
+    #[test]
+    fn test_torque_free() {
+        let inertia = Matrix3::from_diagonal(&vector![0.005, 0.26, 0.27]);
+        let problem = RigidBodyProblem {
+            body: RigidBody::new(1.0, inertia).unwrap(),
+            contributors: vec![],
+        };
+
+        let w0 = vector![0.2, 5.0, 0.1];
+        let state = RigidBodyState::new(
+            &Vector3::zeros(),
+            &Vector3::zeros(),
+            &UnitQuaternion::identity(),
+            &w0,
+        );
+        let end = simulate(&problem, state, 0.001, 1000);
+
+        // Angular momentum in the navigation frame and kinetic energy are conserved
+        let w = end.angvel_b().clone_owned();
+        let momentum = end.quat_nb().transform_vector(&(inertia * w));
+        assert!((momentum - inertia * w0).norm() < 1e-6);
+        assert!((w.dot(&(inertia * w)) - w0.dot(&(inertia * w0))).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_ground_contact() {
+        let problem = RigidBodyProblem {
+            body: RigidBody::new(1.0, Matrix3::identity()).unwrap(),
+            contributors: vec![
+                Box::new(Gravity {
+                    g_n: vector![0.0, 0.0, G],
+                }),
+                Box::new(GroundContact {
+                    ground_d: 0.0,
+                    stiffness: 1e4,
+                    damping: 100.0,
+                }),
+            ],
+        };
+
+        let state = RigidBodyState::new(
+            &vector![0.0, 0.0, -1.0],
+            &Vector3::zeros(),
+            &UnitQuaternion::identity(),
+            &Vector3::zeros(),
+        );
+        let end = simulate(&problem, state, 0.001, 5000);
+
+        // Settles where the spring holds the weight
+        assert!((end.pos_n()[2] - G / 1e4).abs() < 1e-6);
+        assert!(end.vel_n().norm() < 1e-6);
+    }
+}
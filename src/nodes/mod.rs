@@ -1,5 +1,7 @@
 mod executor;
 mod node;
+mod parameter_server;
 
 pub use executor::FtlOrderedExecutor;
 pub use node::*;
+pub use parameter_server::ParameterServer;
@@ -134,7 +134,7 @@ impl NodeTelemetry {
 }
 
 impl TelemetryDispatcher for NodeTelemetry {
-    fn publish<T: 'static + Send>(
+    fn publish<T: 'static + Send + Sync + Clone>(
         &self,
         channel_name: &str,
     ) -> Result<TelemetrySender<T>, TelemetryError> {
@@ -147,7 +147,7 @@ impl TelemetryDispatcher for NodeTelemetry {
         self.telemetry.publish::<T>(path.as_str())
     }
 
-    fn subscribe<T: 'static + Send>(
+    fn subscribe<T: 'static + Send + Sync + Clone>(
         &self,
         channel_name: &str,
         capacity: Capacity,
@@ -0,0 +1,208 @@
+use anyhow::Result;
+use chrono::TimeDelta;
+
+use super::{Node, NodeContext, StepResult};
+use crate::{
+    core::time::{Clock, Timestamp},
+    crater_messages::parameters::{
+        ParameterChanged, ParameterRequest, ParameterResponse, ParameterTree,
+    },
+    parameters::{Error, ParameterService, ParameterWatch},
+    telemetry::{TelemetryDispatcher, TelemetryReceiver, TelemetrySender, Timestamped},
+    utils::capacity::Capacity,
+};
+
+/// Makes the parameters available to ground tools, over the following channels:
+///
+/// - `/parameters/tree`: the whole tree and its content hash, latched, so that it is received
+///   even when subscribing late. Published at the first step and after every change.
+/// - `/parameters/changed`: one message per call to `ParameterService::set()` or `remove()`,
+///   with the values as they are when the change is published, at the end of the step.
+/// - `/parameters/request` and `/parameters/response`: reads and writes parameters, each
+///   response has the id of its request.
+pub struct ParameterServer {
+    params: ParameterService,
+    watch: ParameterWatch,
+
+    snd_tree: TelemetrySender<ParameterTree>,
+    snd_changed: TelemetrySender<ParameterChanged>,
+    rcv_request: TelemetryReceiver<ParameterRequest>,
+    snd_response: TelemetrySender<ParameterResponse>,
+}
+
+impl ParameterServer {
+    pub fn new(ctx: NodeContext) -> Result<Self> {
+        let telemetry = ctx.telemetry();
+
+        Ok(ParameterServer {
+            params: ctx.parameters().clone(),
+            watch: ctx.parameters().watch("/")?,
+            snd_tree: telemetry.publish_latched("/parameters/tree")?,
+            snd_changed: telemetry.publish("/parameters/changed")?,
+            rcv_request: telemetry.subscribe("/parameters/request", Capacity::Unbounded)?,
+            snd_response: telemetry.publish("/parameters/response")?,
+        })
+    }
+
+    fn handle(&mut self, ts: i64, request: ParameterRequest) -> ParameterResponse {
+        let res = match &request.value {
+            Some(value) => self
+                .params
+                .set_from_proto(&request.path, value)
+                .and_then(|_| self.params.to_proto(&request.path)),
+            None => self.params.to_proto(&request.path),
+        };
+
+        let (params, error) = match res {
+            Ok(params) => (params, None),
+            Err(e) => (vec![], Some(e.to_string())),
+        };
+
+        ParameterResponse {
+            timestamp: ts,
+            id: request.id,
+            params,
+            error,
+        }
+    }
+}
+
+impl Node for ParameterServer {
+    fn step(&mut self, i: usize, _: TimeDelta, clock: &dyn Clock) -> Result<StepResult> {
+        let t = Timestamp::now(clock);
+        let ts = t.monotonic.elapsed().num_nanoseconds().unwrap();
+
+        while let Ok(Timestamped(_, request)) = self.rcv_request.try_recv() {
+            let response = self.handle(ts, request);
+            self.snd_response.send(t, response);
+        }
+
+        let mut changed = i == 0;
+        while let Ok(path) = self.watch.try_recv() {
+            let params = match self.params.to_proto(path.as_str()) {
+                Ok(params) => params,
+                Err(Error::NotFound(_)) => vec![],
+                Err(e) => return Err(e.into()),
+            };
+
+            self.snd_changed.send(
+                t,
+                ParameterChanged {
+                    timestamp: ts,
+                    path: path.to_string(),
+                    params,
+                },
+            );
+            changed = true;
+        }
+
+        if changed {
+            self.snd_tree.send(
+                t,
+                ParameterTree {
+                    timestamp: ts,
+                    hash: self.params.content_hash(),
+                    params: self.params.to_proto("/")?,
+                },
+            );
+        }
+
+        Ok(StepResult::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        core::time::SystemClock,
+        crater_messages::parameters::{parameter_value::Value, ParameterValue},
+        nodes::{NodeConfig, NodeManager},
+        parameters::Parameter,
+        telemetry::TelemetryService,
+    };
+
+    #[test]
+    fn test_parameter_server() -> Result<()> {
+        let ts = TelemetryService::default();
+        let mut params = ParameterService::from_toml(
+            "[sim]
+            dt = {val=0.01, dtype=\"f64\"}
+            steps = {val=10, dtype=\"u32\"}
+            ",
+        )?;
+
+        let mut nm = NodeManager::new(
+            ts.clone(),
+            params.clone(),
+            HashMap::from([("params".to_string(), NodeConfig::default())]),
+        );
+        nm.add_node("params", |ctx| Ok(Box::new(ParameterServer::new(ctx)?)))?;
+
+        let changed =
+            ts.subscribe::<ParameterChanged>("/parameters/changed", Capacity::Unbounded)?;
+        let response =
+            ts.subscribe::<ParameterResponse>("/parameters/response", Capacity::Unbounded)?;
+        let request = ts.publish::<ParameterRequest>("/parameters/request")?;
+
+        let clock = SystemClock {};
+        let dt = TimeDelta::milliseconds(10);
+        nm.nodes[0].1.step(0, dt, &clock)?;
+
+        // The tree is latched
+        let tree = ts.subscribe::<ParameterTree>("/parameters/tree", Capacity::Unbounded)?;
+        let Timestamped(_, first) = tree.try_recv()?;
+        assert_eq!(first.hash, params.content_hash());
+        assert_eq!(first.params, params.to_proto("/")?);
+        assert!(changed.try_recv().is_err());
+
+        params.set(&"/sim/dt".into(), Parameter::F64(0.02))?;
+        request.send(
+            Timestamp::now(&clock),
+            ParameterRequest {
+                id: 1,
+                path: "/sim/steps".to_string(),
+                value: Some(ParameterValue {
+                    value: Some(Value::U32Val(20)),
+                }),
+            },
+        );
+        request.send(
+            Timestamp::now(&clock),
+            ParameterRequest {
+                id: 2,
+                path: "/sim/missing".to_string(),
+                value: None,
+            },
+        );
+        nm.nodes[0].1.step(1, dt, &clock)?;
+
+        assert_eq!(params.get_u32("/sim/steps")?, 20);
+
+        let Timestamped(_, r1) = response.try_recv()?;
+        assert_eq!((r1.id, r1.error), (1, None));
+        assert_eq!(r1.params, params.to_proto("/sim/steps")?);
+
+        let Timestamped(_, r2) = response.try_recv()?;
+        assert_eq!(r2.id, 2);
+        assert!(r2.params.is_empty() && r2.error.is_some());
+
+        let paths: Vec<_> = std::iter::from_fn(|| changed.try_recv().ok())
+            .map(|Timestamped(_, c)| c.path)
+            .collect();
+        assert_eq!(paths, vec!["/sim/dt", "/sim/steps"]);
+
+        let Timestamped(_, second) = tree.try_recv()?;
+        assert_eq!(second.hash, params.content_hash());
+        assert_ne!(second.hash, first.hash);
+
+        // Nothing changed, nothing sent
+        nm.nodes[0].1.step(2, dt, &clock)?;
+        assert!(changed.try_recv().is_err());
+        assert!(tree.try_recv().is_err());
+
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@ use serde::Deserialize;
 use thiserror::Error;
 use toml::{Table, Value};
 
-use super::Parameter;
+use super::{dist::Distribution, expr::Expression, Parameter, ParameterSchema};
 use anyhow::Result;
 
 #[derive(Debug, Clone, Error, PartialEq, Eq)]
@@ -19,49 +19,226 @@ pub enum Error {
     #[error("Invalid type: '{0}'")]
     InvalidType(String),
 
+    #[error("Value {value} of parameter '{path}' is out of range for '{dtype}'")]
+    OutOfRange {
+        path: String,
+        value: String,
+        dtype: String,
+    },
+
+    #[error("Value {value} of parameter '{path}' cannot be represented exactly as '{dtype}'")]
+    PrecisionLoss {
+        path: String,
+        value: String,
+        dtype: String,
+    },
+
+    #[error(
+        "Value \"{value}\" of parameter '{path}' is not a u64 above i64::MAX, the only values \
+         written as strings"
+    )]
+    BadU64String { path: String, value: String },
+
+    #[error("Invalid distribution for parameter '{path}': {msg}")]
+    BadDistribution { path: String, msg: String },
+
     #[error("Error deserializing parameters")]
     Deserialize(#[from] toml::de::Error),
 }
 
 #[derive(Debug, Clone, Deserialize)]
 struct ParameterDef {
-    val: Value,
+    // Either a value or an expression. The value is optional for distributions.
+    val: Option<Value>,
+    expr: Option<String>,
     dtype: String,
+
+    // Optional distribution
+    dist: Option<String>,
+    mean: Option<f64>,
+    std: Option<f64>,
+    low: Option<f64>,
+    high: Option<f64>,
+
+    // Optional schema
+    min: Option<f64>,
+    max: Option<f64>,
+    values: Option<Vec<Value>>,
+    unit: Option<String>,
+    description: Option<String>,
 }
 
-pub(super) fn parse_str(toml_str: &str) -> Result<Vec<(String, Parameter)>, Error> {
+/// A parameter file that can be layered on top of other ones
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(super) struct Layer {
+    /// Files to be loaded before this one, from the `include = [...]` directive
+    pub include: Vec<String>,
+
+    /// Paths to be removed before applying this layer, from the `delete = [...]` directive
+    pub delete: Vec<String>,
+    pub params: Vec<(String, Parameter)>,
+
+    /// Schemas of the parameters that define at least one of the schema fields
+    pub schemas: Vec<(String, ParameterSchema)>,
+
+    /// Expressions of the parameters defined by one. These parameters are also in `params`,
+    /// with a placeholder value of the right type.
+    pub exprs: Vec<(String, Expression)>,
+}
+
+/// Parses a parameter file without directives
+pub(super) fn parse_str(toml_str: &str) -> Result<Layer, Error> {
     let table = toml::from_str::<Table>(toml_str)?;
 
-    parse_table(table, "")
+    let mut layer = Layer::default();
+    parse_table(table, "", &mut layer)?;
+
+    Ok(layer)
 }
 
-pub(super) fn parse_table(table: Table, path: &str) -> Result<Vec<(String, Parameter)>, Error> {
-    let mut params: Vec<(String, Parameter)> = vec![];
+pub(super) fn parse_layer(toml_str: &str) -> Result<Layer, Error> {
+    let mut table = toml::from_str::<Table>(toml_str)?;
+
+    // Parameters are always tables, so there is no ambiguity with the directives
+    let mut directive = |name: &str| -> Result<Vec<String>, Error> {
+        match table.remove(name) {
+            None => Ok(vec![]),
+            Some(Value::Array(arr)) => arr
+                .into_iter()
+                .map(|v| match v {
+                    Value::String(s) => Ok(s),
+                    _ => Err(Error::BadStructure),
+                })
+                .collect(),
+            Some(v) => {
+                // Not a directive: put it back and let parse_table() deal with it
+                table.insert(name.to_string(), v);
+                Ok(vec![])
+            }
+        }
+    };
+
+    let mut layer = Layer {
+        include: directive("include")?,
+        delete: directive("delete")?,
+        ..Default::default()
+    };
+    parse_table(table, "", &mut layer)?;
+
+    Ok(layer)
+}
+
+fn parse_table(table: Table, path: &str, layer: &mut Layer) -> Result<(), Error> {
     if let Ok(def) = table.clone().try_into::<ParameterDef>() {
         if path == "" {
             // Root must be a map, not a scalar / list
             return Err(Error::BadStructure);
         }
-        params.push((path.to_string(), value_to_parameter(def.val, &def.dtype)?));
+
+        let schema = ParameterSchema {
+            min: def.min,
+            max: def.max,
+            values: def
+                .values
+                .map(|values| {
+                    values
+                        .into_iter()
+                        .map(|v| value_to_parameter(v, &def.dtype, path))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()?,
+            unit: def.unit,
+            description: def.description,
+            dist: def
+                .dist
+                .map(|name| {
+                    Distribution::from_fields(&name, def.mean, def.std, def.low, def.high).map_err(
+                        |msg| Error::BadDistribution {
+                            path: path.to_string(),
+                            msg,
+                        },
+                    )
+                })
+                .transpose()?,
+        };
+
+        let param = match (def.val, def.expr) {
+            (Some(val), None) => value_to_parameter(val, &def.dtype, path)?,
+            (None, None) if schema.dist.is_some() => {
+                let mean = schema.dist.map(|d| d.mean()).unwrap_or_default();
+
+                // The mean is rarely exact in f32, which is not an error for a nominal value
+                match def.dtype.as_str() {
+                    "f32" => Parameter::F32(mean as f32),
+                    dtype => value_to_parameter(Value::Float(mean), dtype, path)?,
+                }
+            }
+            (None, Some(expr)) => {
+                // Only numbers can be computed
+                let placeholder = value_to_parameter(Value::Integer(0), &def.dtype, path)?;
+
+                layer.exprs.push((
+                    path.to_string(),
+                    Expression {
+                        source: expr,
+                        dtype: def.dtype,
+                    },
+                ));
+
+                placeholder
+            }
+            _ => return Err(Error::BadStructure),
+        };
+
+        layer.params.push((path.to_string(), param));
+
+        if !schema.is_empty() {
+            layer.schemas.push((path.to_string(), schema));
+        }
+    } else if table.is_empty() && !path.is_empty() {
+        // Keep empty tables as empty maps
+        layer
+            .params
+            .push((path.to_string(), Parameter::Map(Default::default())));
     } else {
         for (k, v) in table.into_iter() {
             let nested = format!("{path}/{k}");
             if let Value::Table(t) = v {
-                params.append(&mut parse_table(t, nested.as_str())?);
+                parse_table(t, nested.as_str(), layer)?;
             } else {
                 return Err(Error::BadStructure);
             }
         }
     }
-    Ok(params)
+    Ok(())
+}
+
+/// Parses a single value written as in the `val` field of a parameter file, such as `1.2` or
+/// `[1, 2, 3]`. Quotes around strings can be omitted. `path` is only used in error messages.
+pub(super) fn parse_value(value: &str, dtype: &str, path: &str) -> Result<Parameter, Error> {
+    let parsed = toml::from_str::<Table>(&format!("val = {value}"))
+        .ok()
+        .filter(|t| t.len() == 1)
+        .and_then(|mut t| t.remove("val"));
+
+    match parsed {
+        Some(v @ (Value::String(_) | Value::Array(_))) => value_to_parameter(v, dtype, path),
+        _ if dtype == "string" => Ok(Parameter::String(value.to_string())),
+        Some(v) => value_to_parameter(v, dtype, path),
+        None => Err(Error::BadConversion(dtype.to_string())),
+    }
 }
 
-fn value_to_parameter(value: Value, dtype: &str) -> Result<Parameter, Error> {
+pub(super) fn value_to_parameter(
+    value: Value,
+    dtype: &str,
+    path: &str,
+) -> Result<Parameter, Error> {
     match value {
         Value::Array(arr) => {
             let mut out = Vec::with_capacity(arr.len());
             for v in arr {
-                out.push(value_to_parameter(v, dtype)?);
+                out.push(value_to_parameter(v, dtype, path)?);
             }
 
             Ok(Parameter::List(out))
@@ -74,59 +251,18 @@ fn value_to_parameter(value: Value, dtype: &str) -> Result<Parameter, Error> {
                     .ok_or(Error::BadConversion("bool".to_string()))?,
             )),
 
-            "u8" => Ok(Parameter::U8(
-                value
-                    .as_integer()
-                    .ok_or(Error::BadConversion("u8".to_string()))? as u8,
-            )),
-            "u16" => Ok(Parameter::U16(
-                value
-                    .as_integer()
-                    .ok_or(Error::BadConversion("u16".to_string()))? as u16,
-            )),
-            "u32" => Ok(Parameter::U32(
-                value
-                    .as_integer()
-                    .ok_or(Error::BadConversion("u32".to_string()))? as u32,
-            )),
-            "u64" => Ok(Parameter::U64(
-                value
-                    .as_integer()
-                    .ok_or(Error::BadConversion("u64".to_string()))? as u64,
-            )),
+            "u8" => Ok(Parameter::U8(to_integer(&value, dtype, path)?)),
+            "u16" => Ok(Parameter::U16(to_integer(&value, dtype, path)?)),
+            "u32" => Ok(Parameter::U32(to_integer(&value, dtype, path)?)),
+            "u64" => Ok(Parameter::U64(to_u64(&value, path)?)),
 
-            "i8" => Ok(Parameter::I8(
-                value
-                    .as_integer()
-                    .ok_or(Error::BadConversion("i8".to_string()))? as i8,
-            )),
-            "i16" => Ok(Parameter::I16(
-                value
-                    .as_integer()
-                    .ok_or(Error::BadConversion("i16".to_string()))? as i16,
-            )),
-            "i32" => Ok(Parameter::I32(
-                value
-                    .as_integer()
-                    .ok_or(Error::BadConversion("i32".to_string()))? as i32,
-            )),
-            "i64" => Ok(Parameter::I64(
-                value
-                    .as_integer()
-                    .ok_or(Error::BadConversion("i64".to_string()))? as i64,
-            )),
-            "f32" => Ok(Parameter::F32(
-                value
-                    .as_float()
-                    .or(value.as_integer().map(|v| v as f64))
-                    .ok_or(Error::BadConversion("f32".to_string()))? as f32,
-            )),
-            "f64" => Ok(Parameter::F64(
-                value
-                    .as_float()
-                    .or(value.as_integer().map(|v| v as f64))
-                    .ok_or(Error::BadConversion("f64".to_string()))?,
-            )),
+            "i8" => Ok(Parameter::I8(to_integer(&value, dtype, path)?)),
+            "i16" => Ok(Parameter::I16(to_integer(&value, dtype, path)?)),
+            "i32" => Ok(Parameter::I32(to_integer(&value, dtype, path)?)),
+            "i64" => Ok(Parameter::I64(to_integer(&value, dtype, path)?)),
+
+            "f32" => Ok(Parameter::F32(to_f32(&value, path)?)),
+            "f64" => Ok(Parameter::F64(to_float(&value, dtype, path)?)),
 
             "string" => Ok(Parameter::String(
                 value
@@ -140,12 +276,88 @@ fn value_to_parameter(value: Value, dtype: &str) -> Result<Parameter, Error> {
     }
 }
 
+/// Converts a toml integer to the integer type of the parameter, rejecting values that do not
+/// fit instead of wrapping them around
+fn to_integer<T: TryFrom<i64>>(value: &Value, dtype: &str, path: &str) -> Result<T, Error> {
+    let v = value
+        .as_integer()
+        .ok_or(Error::BadConversion(dtype.to_string()))?;
+
+    T::try_from(v).map_err(|_| Error::OutOfRange {
+        path: path.to_string(),
+        value: v.to_string(),
+        dtype: dtype.to_string(),
+    })
+}
+
+/// Converts a toml integer, or a string for the values that do not fit in a toml integer, to u64
+fn to_u64(value: &Value, path: &str) -> Result<u64, Error> {
+    match value {
+        Value::String(s) => s
+            .parse::<u64>()
+            .ok()
+            .filter(|v| i64::try_from(*v).is_err())
+            .ok_or_else(|| Error::BadU64String {
+                path: path.to_string(),
+                value: s.clone(),
+            }),
+        value => to_integer(value, "u64", path),
+    }
+}
+
+/// Converts a toml float, or integer, to f64. Integers too large to be represented exactly are
+/// rejected.
+fn to_float(value: &Value, dtype: &str, path: &str) -> Result<f64, Error> {
+    match value {
+        Value::Float(v) => Ok(*v),
+        Value::Integer(v) if (*v as f64) as i128 == *v as i128 => Ok(*v as f64),
+        Value::Integer(v) => Err(Error::PrecisionLoss {
+            path: path.to_string(),
+            value: v.to_string(),
+            dtype: dtype.to_string(),
+        }),
+        _ => Err(Error::BadConversion(dtype.to_string())),
+    }
+}
+
+/// Converts a toml float, or integer, to f32. Finite values that overflow are rejected, as well
+/// as values written with more significant digits than a f32 can hold: a value is accepted only
+/// if it is exactly the resulting f32, or if the shortest representation of the f32 reads back
+/// as the original value.
+fn to_f32(value: &Value, path: &str) -> Result<f32, Error> {
+    let v = to_float(value, "f32", path)?;
+    let v32 = v as f32;
+
+    let error = |overflow: bool| {
+        let (path, value, dtype) = (path.to_string(), v.to_string(), "f32".to_string());
+        if overflow {
+            Error::OutOfRange { path, value, dtype }
+        } else {
+            Error::PrecisionLoss { path, value, dtype }
+        }
+    };
+
+    if !v.is_finite() {
+        Ok(v32)
+    } else if v32.is_infinite() {
+        Err(error(true))
+    } else if v32 as f64 != v && v32.to_string().parse::<f64>() != Ok(v) {
+        Err(error(false))
+    } else {
+        Ok(v32)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     use anyhow::Result;
 
+    fn parse_str(toml_str: &str) -> Result<Vec<(String, Parameter)>, Error> {
+        super::parse_str(toml_str).map(|l| l.params)
+    }
+
     #[test]
     fn test_from_string() -> Result<()> {
         assert_eq!(
@@ -190,6 +402,99 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_empty_table() -> Result<()> {
+        assert_eq!(
+            parse_str(
+                "[a1]
+                [a2.b1]
+                c1 = {val=123, dtype=\"i32\"}
+                "
+            ),
+            Ok(vec![
+                ("/a1".to_string(), Parameter::Map(Default::default())),
+                ("/a2/b1/c1".to_string(), Parameter::I32(123)),
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_layer() -> Result<()> {
+        assert_eq!(
+            parse_layer(
+                "include = [\"base.toml\", \"other.toml\"]
+                delete = [\"/a3/b1\"]
+                a1 = {val=1.23, dtype=\"f32\"}
+                "
+            ),
+            Ok(Layer {
+                include: vec!["base.toml".to_string(), "other.toml".to_string()],
+                delete: vec!["/a3/b1".to_string()],
+                params: vec![("/a1".to_string(), Parameter::F32(1.23))],
+                ..Default::default()
+            })
+        );
+
+        // Parameters named like a directive are still parameters
+        assert_eq!(
+            parse_layer("include = {val=1, dtype=\"i32\"}"),
+            Ok(Layer {
+                params: vec![("/include".to_string(), Parameter::I32(1))],
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(parse_layer("include = [1, 2]"), Err(Error::BadStructure));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_value() -> Result<()> {
+        assert_eq!(
+            parse_value("0.001", "f64", "/a1"),
+            Ok(Parameter::F64(0.001))
+        );
+        assert_eq!(parse_value("-3", "i8", "/a1"), Ok(Parameter::I8(-3)));
+        assert_eq!(
+            parse_value("true", "bool", "/a1"),
+            Ok(Parameter::Bool(true))
+        );
+        assert_eq!(
+            parse_value("[1, 2.5]", "f32", "/a1"),
+            Ok(Parameter::List(vec![
+                Parameter::F32(1.0),
+                Parameter::F32(2.5)
+            ]))
+        );
+
+        assert_eq!(
+            parse_value("simple", "string", "/a1"),
+            Ok(Parameter::String("simple".to_string()))
+        );
+        assert_eq!(
+            parse_value("\"with spaces\"", "string", "/a1"),
+            Ok(Parameter::String("with spaces".to_string()))
+        );
+        assert_eq!(
+            parse_value("12", "string", "/a1"),
+            Ok(Parameter::String("12".to_string()))
+        );
+
+        assert_eq!(
+            parse_value("abc", "f64", "/a1"),
+            Err(Error::BadConversion("f64".to_string()))
+        );
+        assert_eq!(
+            parse_value("1\nother = 2", "f64", "/a1"),
+            Err(Error::BadConversion("f64".to_string()))
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_integer_to_float() -> Result<()> {
         assert_eq!(
@@ -266,6 +571,129 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_out_of_range() -> Result<()> {
+        let out_of_range = |value: &str, dtype: &str| {
+            Err(Error::OutOfRange {
+                path: "/a1/b1".to_string(),
+                value: value.to_string(),
+                dtype: dtype.to_string(),
+            })
+        };
+
+        assert_eq!(
+            parse_str("a1.b1 = {val=300, dtype=\"u8\"}"),
+            out_of_range("300", "u8")
+        );
+        assert_eq!(
+            parse_str("a1.b1 = {val=-1, dtype=\"u32\"}"),
+            out_of_range("-1", "u32")
+        );
+        assert_eq!(
+            parse_str("a1.b1 = {val=-1, dtype=\"u64\"}"),
+            out_of_range("-1", "u64")
+        );
+        assert_eq!(
+            parse_str("a1.b1 = {val=[1, 2, 40000], dtype=\"i16\"}"),
+            out_of_range("40000", "i16")
+        );
+        assert_eq!(
+            parse_str("a1.b1 = {val=1e39, dtype=\"f32\"}"),
+            out_of_range("1000000000000000000000000000000000000000", "f32")
+        );
+
+        assert_eq!(
+            parse_str("a1 = {val=255, dtype=\"u8\"}"),
+            Ok(vec![("/a1".to_string(), Parameter::U8(255))])
+        );
+        assert_eq!(
+            parse_str("a1 = {val=-128, dtype=\"i8\"}"),
+            Ok(vec![("/a1".to_string(), Parameter::I8(-128))])
+        );
+        assert_eq!(
+            parse_str("a1 = {val=\"18446744073709551615\", dtype=\"u64\"}"),
+            Ok(vec![("/a1".to_string(), Parameter::U64(u64::MAX))])
+        );
+
+        // Only the values that do not fit in a toml integer are written as strings
+        let bad_string = |value: &str| {
+            Err(Error::BadU64String {
+                path: "/a1".to_string(),
+                value: value.to_string(),
+            })
+        };
+        assert_eq!(
+            parse_str("a1 = {val=\"9223372036854775807\", dtype=\"u64\"}"),
+            bad_string("9223372036854775807")
+        );
+        assert_eq!(
+            parse_str("a1 = {val=\"abcd\", dtype=\"u64\"}"),
+            bad_string("abcd")
+        );
+        assert_eq!(
+            parse_str("a1 = {val=\"18446744073709551616\", dtype=\"u64\"}"),
+            bad_string("18446744073709551616")
+        );
+        assert_eq!(
+            parse_str("a1 = {val=inf, dtype=\"f32\"}"),
+            Ok(vec![("/a1".to_string(), Parameter::F32(f32::INFINITY))])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_precision_loss() -> Result<()> {
+        let precision_loss = |value: &str, dtype: &str| {
+            Err(Error::PrecisionLoss {
+                path: "/a1".to_string(),
+                value: value.to_string(),
+                dtype: dtype.to_string(),
+            })
+        };
+
+        assert_eq!(
+            parse_str("a1 = {val=0.123456789, dtype=\"f32\"}"),
+            precision_loss("0.123456789", "f32")
+        );
+        assert_eq!(
+            parse_str("a1 = {val=16777217, dtype=\"f32\"}"),
+            precision_loss("16777217", "f32")
+        );
+        assert_eq!(
+            parse_str("a1 = {val=1e-50, dtype=\"f32\"}"),
+            precision_loss(
+                "0.00000000000000000000000000000000000000000000000001",
+                "f32"
+            )
+        );
+        assert_eq!(
+            parse_str("a1 = {val=9007199254740993, dtype=\"f64\"}"),
+            precision_loss("9007199254740993", "f64")
+        );
+
+        assert_eq!(
+            parse_str("a1 = {val=[0.1, 1.23, 3e-5, 16777216], dtype=\"f32\"}"),
+            Ok(vec![(
+                "/a1".to_string(),
+                Parameter::List(vec![
+                    Parameter::F32(0.1),
+                    Parameter::F32(1.23),
+                    Parameter::F32(3e-5),
+                    Parameter::F32(16777216.0),
+                ])
+            )])
+        );
+
+        // The exact value of a f32, however long
+        assert_eq!(
+            parse_str("a1 = {val=0.100000001490116119384765625, dtype=\"f32\"}"),
+            Ok(vec![("/a1".to_string(), Parameter::F32(0.1))])
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_etero_list() -> Result<()> {
         assert_eq!(
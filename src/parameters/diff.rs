@@ -0,0 +1,254 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use super::{Parameter, ParameterService};
+use crate::core::path::Path;
+
+/// Differences between two parameter trees, compared parameter by parameter. Maps are not
+/// compared themselves, only the parameters they contain.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParameterDiff {
+    /// Parameters only in the new tree
+    pub added: Vec<(String, Parameter)>,
+
+    /// Parameters only in the old tree
+    pub removed: Vec<(String, Parameter)>,
+
+    /// Parameters in both trees with different values: (path, old, new)
+    pub changed: Vec<(String, Parameter, Parameter)>,
+}
+
+impl ParameterDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl Display for ParameterDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (path, new) in self.added.iter() {
+            writeln!(f, "+ {}: {} = {}", path, new.type_string(), new)?;
+        }
+        for (path, old) in self.removed.iter() {
+            writeln!(f, "- {}: {} = {}", path, old.type_string(), old)?;
+        }
+        for (path, old, new) in self.changed.iter() {
+            writeln!(
+                f,
+                "~ {}: {} = {} -> {} = {}",
+                path,
+                old.type_string(),
+                old,
+                new.type_string(),
+                new
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ParameterService {
+    /// Compares these parameters (the old ones) with `other` (the new ones). Paths in each list
+    /// are sorted.
+    pub fn diff(&self, other: &ParameterService) -> ParameterDiff {
+        let mut old = self.leaves();
+        let new = other.leaves();
+
+        let mut diff = ParameterDiff::default();
+
+        for (path, new_param) in new {
+            match old.remove(&path) {
+                None => diff.added.push((path, new_param)),
+                Some(old_param) if !same_value(&old_param, &new_param) => {
+                    diff.changed.push((path, old_param, new_param))
+                }
+                Some(_) => (),
+            }
+        }
+
+        diff.removed = old.into_iter().collect();
+
+        diff
+    }
+
+    /// Hash of the paths, types and values of all the parameters. It does not depend on the
+    /// order the parameters were set in, nor on the platform or the compiler version, so it can
+    /// be stored to identify a configuration later on. It is not meant to be cryptographically
+    /// secure.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::default();
+
+        for (path, param) in self.leaves() {
+            hasher.write(path.as_bytes());
+            hasher.write(&[0]);
+            hash_param(&mut hasher, &param);
+        }
+
+        hasher.0
+    }
+
+    fn leaves(&self) -> BTreeMap<String, Parameter> {
+        self.get(&Path::from_str("/").unwrap())
+            .map(|root| root.iter().map(|(p, v)| (p, v.clone())).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Like `==`, but NaNs are equal to themselves, so that they do not show up as changes
+fn same_value(a: &Parameter, b: &Parameter) -> bool {
+    match (a, b) {
+        (Parameter::F32(a), Parameter::F32(b)) => a.to_bits() == b.to_bits() || a == b,
+        (Parameter::F64(a), Parameter::F64(b)) => a.to_bits() == b.to_bits() || a == b,
+        (Parameter::List(a), Parameter::List(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_value(a, b))
+        }
+        (a, b) => a == b,
+    }
+}
+
+fn hash_param(hasher: &mut Fnv1a, param: &Parameter) {
+    hasher.write(param.type_string().as_bytes());
+    hasher.write(&[0]);
+
+    match param {
+        Parameter::Bool(v) => hasher.write(&[*v as u8]),
+        Parameter::U8(v) => hasher.write(&v.to_le_bytes()),
+        Parameter::U16(v) => hasher.write(&v.to_le_bytes()),
+        Parameter::U32(v) => hasher.write(&v.to_le_bytes()),
+        Parameter::U64(v) => hasher.write(&v.to_le_bytes()),
+        Parameter::I8(v) => hasher.write(&v.to_le_bytes()),
+        Parameter::I16(v) => hasher.write(&v.to_le_bytes()),
+        Parameter::I32(v) => hasher.write(&v.to_le_bytes()),
+        Parameter::I64(v) => hasher.write(&v.to_le_bytes()),
+        Parameter::F32(v) => hasher.write(&v.to_le_bytes()),
+        Parameter::F64(v) => hasher.write(&v.to_le_bytes()),
+        Parameter::String(v) => {
+            hasher.write(&(v.len() as u64).to_le_bytes());
+            hasher.write(v.as_bytes());
+        }
+        Parameter::List(l) => {
+            hasher.write(&(l.len() as u64).to_le_bytes());
+            l.iter().for_each(|p| hash_param(hasher, p));
+        }
+        // Not reached through leaves()
+        Parameter::Map(m) => {
+            hasher.write(&(m.len() as u64).to_le_bytes());
+        }
+    }
+}
+
+/// 64 bit FNV-1a, which, unlike `std::hash::DefaultHasher`, is guaranteed to never change
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf29ce484222325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::Error;
+
+    const OLD: &str = "
+        [sim]
+        dt = {val=0.01, dtype=\"f64\"}
+        max_t = {val=120, dtype=\"f64\"}
+        nan = {val=nan, dtype=\"f64\"}
+
+        [sim.rocket]
+        mass = {val=2, dtype=\"f64\"}
+        name = {val=\"crater\", dtype=\"string\"}
+        ";
+
+    const NEW: &str = "
+        [sim]
+        dt = {val=0.001, dtype=\"f64\"}
+        max_t = {val=120, dtype=\"f64\"}
+        nan = {val=nan, dtype=\"f64\"}
+
+        [sim.rocket]
+        mass = {val=2, dtype=\"f32\"}
+        diameter = {val=0.08, dtype=\"f64\"}
+        ";
+
+    #[test]
+    fn test_diff() -> Result<(), Error> {
+        let old = ParameterService::from_toml(OLD)?;
+        let new = ParameterService::from_toml(NEW)?;
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff,
+            ParameterDiff {
+                added: vec![("/sim/rocket/diameter".to_string(), Parameter::F64(0.08))],
+                removed: vec![(
+                    "/sim/rocket/name".to_string(),
+                    Parameter::String("crater".to_string())
+                )],
+                changed: vec![
+                    (
+                        "/sim/dt".to_string(),
+                        Parameter::F64(0.01),
+                        Parameter::F64(0.001)
+                    ),
+                    (
+                        "/sim/rocket/mass".to_string(),
+                        Parameter::F64(2.0),
+                        Parameter::F32(2.0)
+                    ),
+                ],
+            }
+        );
+
+        assert_eq!(
+            diff.to_string(),
+            "+ /sim/rocket/diameter: f64 = 0.08
+- /sim/rocket/name: string = crater
+~ /sim/dt: f64 = 0.01 -> f64 = 0.001
+~ /sim/rocket/mass: f64 = 2 -> f32 = 2
+"
+        );
+
+        assert!(old.diff(&old).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_hash() -> Result<(), Error> {
+        let old = ParameterService::from_toml(OLD)?;
+        let new = ParameterService::from_toml(NEW)?;
+
+        assert_ne!(old.content_hash(), new.content_hash());
+        assert_ne!(
+            ParameterService::default().content_hash(),
+            old.content_hash()
+        );
+
+        // Same parameters, set in a different order
+        let mut reordered = ParameterService::default();
+        let mut leaves: Vec<_> = old.leaves().into_iter().collect();
+        leaves.reverse();
+        for (path, param) in leaves {
+            reordered.set(&path.into(), param)?;
+        }
+        assert_eq!(reordered.content_hash(), old.content_hash());
+
+        // Must never change
+        let ps = ParameterService::from_toml("a = {val=1, dtype=\"i32\"}")?;
+        assert_eq!(ps.content_hash(), 0xf8c3b4e2bde71ca0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,293 @@
+use std::{collections::BTreeMap, f64::consts::PI, fmt::Display};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{Error, Parameter, ParameterService};
+use crate::core::path::Path;
+
+/// Random distribution of a parameter, for dispersion analyses. In parameter files it is written
+/// next to the value, which can be omitted and then defaults to the mean of the distribution:
+///
+/// ```toml
+/// mass = { dist = "normal", mean = 2.0, std = 0.05, dtype = "f64" }
+/// azimuth = { val = 170, dist = "uniform", low = 160, high = 180, dtype = "f64" }
+/// ```
+///
+/// Only f32 and f64 parameters, or lists of them, can have a distribution. The value is the
+/// nominal one until `ParameterService::sample()` is called.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distribution {
+    Normal { mean: f64, std: f64 },
+    Uniform { low: f64, high: f64 },
+}
+
+impl Distribution {
+    /// Builds a distribution from the fields of a parameter file
+    pub(super) fn from_fields(
+        name: &str,
+        mean: Option<f64>,
+        std: Option<f64>,
+        low: Option<f64>,
+        high: Option<f64>,
+    ) -> Result<Distribution, String> {
+        let dist = match (name, mean, std, low, high) {
+            ("normal", Some(mean), Some(std), None, None) => Distribution::Normal { mean, std },
+            ("uniform", None, None, Some(low), Some(high)) => Distribution::Uniform { low, high },
+            ("normal", ..) => return Err("'normal' requires 'mean' and 'std' only".to_string()),
+            ("uniform", ..) => return Err("'uniform' requires 'low' and 'high' only".to_string()),
+            (name, ..) => return Err(format!("unknown distribution '{name}'")),
+        };
+
+        match dist {
+            Distribution::Normal { std, .. } if std < 0.0 => {
+                Err("'std' must not be negative".to_string())
+            }
+            Distribution::Uniform { low, high } if low > high => {
+                Err("'low' must not be greater than 'high'".to_string())
+            }
+            dist => Ok(dist),
+        }
+    }
+
+    pub fn mean(&self) -> f64 {
+        match self {
+            Distribution::Normal { mean, .. } => *mean,
+            Distribution::Uniform { low, high } => (low + high) / 2.0,
+        }
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> f64 {
+        match *self {
+            Distribution::Normal { mean, std } => {
+                // Box-Muller transform, 1 - u1 is in (0, 1] so its log is finite
+                let u1: f64 = rng.gen();
+                let u2: f64 = rng.gen();
+                let z = (-2.0 * (1.0 - u1).ln()).sqrt() * (2.0 * PI * u2).cos();
+
+                mean + std * z
+            }
+            Distribution::Uniform { low, high } if low == high => low,
+            Distribution::Uniform { low, high } => rng.gen_range(low..high),
+        }
+    }
+
+    /// Name and fields, as written in parameter files
+    pub(super) fn fields(&self) -> (&'static str, [(&'static str, f64); 2]) {
+        match *self {
+            Distribution::Normal { mean, std } => ("normal", [("mean", mean), ("std", std)]),
+            Distribution::Uniform { low, high } => ("uniform", [("low", low), ("high", high)]),
+        }
+    }
+}
+
+impl Display for Distribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Distribution::Normal { mean, std } => write!(f, "normal(mean={mean}, std={std})"),
+            Distribution::Uniform { low, high } => write!(f, "uniform({low}, {high})"),
+        }
+    }
+}
+
+/// Values drawn by `ParameterService::sample()`, by path
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterSamples {
+    pub seed: u64,
+    pub values: BTreeMap<String, Parameter>,
+}
+
+impl ParameterService {
+    /// Replaces the value of every parameter with a distribution by a random sample, drawn from
+    /// a RNG seeded with `seed`: the same seed and parameters always give the same samples.
+    /// Samples are clamped to the `min` and `max` of the schema, if any.
+    ///
+    /// Expressions are evaluated again afterwards, so they use the sampled values. Returns the
+    /// sampled values, without the ones of expressions.
+    pub fn sample(&mut self, seed: u64) -> Result<ParameterSamples, Error> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut values = BTreeMap::new();
+
+        let dists: Vec<_> = self
+            .schemas()
+            .into_iter()
+            .filter_map(|(p, s)| s.dist.map(|d| (p, d, s.min, s.max)))
+            .collect();
+
+        for (path, dist, min, max) in dists {
+            let path = Path::from_str(&path)?;
+            let Some(param) = self.get(&path) else {
+                continue;
+            };
+
+            let mut draw = || {
+                let v = dist.sample(&mut rng);
+                v.max(min.unwrap_or(f64::NEG_INFINITY))
+                    .min(max.unwrap_or(f64::INFINITY))
+            };
+
+            let sampled = sample_param(&path, &param, &mut draw)?;
+            self.set(&path, sampled.clone())?;
+            values.insert(path.to_string(), sampled);
+        }
+
+        Ok(ParameterSamples { seed, values })
+    }
+}
+
+fn sample_param(
+    path: &Path,
+    param: &Parameter,
+    draw: &mut impl FnMut() -> f64,
+) -> Result<Parameter, Error> {
+    match param {
+        Parameter::F64(_) => Ok(Parameter::F64(draw())),
+        Parameter::F32(_) => Ok(Parameter::F32(draw() as f32)),
+        Parameter::List(l) => Ok(Parameter::List(
+            l.iter()
+                .map(|p| sample_param(path, p, draw))
+                .collect::<Result<_, _>>()?,
+        )),
+        p => Err(Error::InvalidSchema(
+            path.clone(),
+            format!("distributions cannot be used with '{}'", p.type_string()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nominal() -> Result<(), Error> {
+        let ps = ParameterService::from_toml(
+            "[rocket]
+            mass = {dist=\"normal\", mean=2.0, std=0.05, dtype=\"f64\"}
+            azimuth = {val=170, dist=\"uniform\", low=160, high=180, dtype=\"f64\", unit=\"deg\"}
+            ",
+        )?;
+
+        assert_eq!(ps.get_f64("/rocket/mass")?, 2.0);
+        assert_eq!(ps.get_f64("/rocket/azimuth")?, 170.0);
+        assert_eq!(
+            ps.schema("/rocket/mass")?.and_then(|s| s.dist),
+            Some(Distribution::Normal {
+                mean: 2.0,
+                std: 0.05
+            })
+        );
+
+        // Distributions are kept by copies
+        let mut copy = ps.deep_copy();
+        assert_eq!(copy.to_toml()?, ps.to_toml()?);
+
+        // Copies are independent
+        copy.sample(1)?;
+        assert_eq!(ps.get_f64("/rocket/mass")?, 2.0);
+
+        // The nominal value of an f32 is the mean rounded to f32
+        let ps = ParameterService::from_toml(
+            "gain = {dist=\"uniform\", low=0.1, high=0.2, dtype=\"f32\"}",
+        )?;
+        assert_eq!(ps.get_f32("/gain")?, 0.15);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample() -> Result<(), Error> {
+        let nominal = ParameterService::from_toml(
+            "[rocket]
+            mass = {dist=\"normal\", mean=2.0, std=0.05, dtype=\"f64\", min=1.95}
+            length = {dist=\"normal\", mean=1.5, std=0.05, dtype=\"f64\"}
+            azimuth = {val=170, dist=\"uniform\", low=160, high=180, dtype=\"f64\", unit=\"deg\"}
+            p0 = {val=[0, 0], dist=\"uniform\", low=-1, high=1, dtype=\"f32\"}
+            diameter = {val=0.08, dtype=\"f64\"}
+            ",
+        )?;
+
+        let mut ps = nominal.deep_copy();
+        let samples = ps.sample(42)?;
+
+        assert_eq!(
+            samples.values.keys().collect::<Vec<_>>(),
+            vec![
+                "/rocket/azimuth",
+                "/rocket/length",
+                "/rocket/mass",
+                "/rocket/p0"
+            ]
+        );
+        for (path, value) in &samples.values {
+            assert_eq!(ps.get(&path.as_str().into()).as_ref(), Some(value));
+        }
+
+        let mass = ps.get_f64("/rocket/mass")?;
+        assert!((1.95..2.3).contains(&mass));
+        assert!((160.0..180.0).contains(&ps.get_f64("/rocket/azimuth")?));
+        let p0 = ps.get_vec_f32("/rocket/p0")?;
+        assert!(p0[0] != p0[1] && p0.iter().all(|v| (-1.0..1.0).contains(v)));
+        assert_eq!(ps.get_f64("/rocket/diameter")?, 0.08);
+
+        // Same seed, same samples
+        assert_eq!(nominal.deep_copy().sample(42)?, samples);
+        assert_ne!(nominal.deep_copy().sample(43)?.values, samples.values);
+
+        // Sampling many times gives the expected mean and standard deviation, and respects the
+        // bounds of the schema
+        let samples: Vec<_> = (0..1000)
+            .map(|seed| nominal.deep_copy().sample(seed))
+            .collect::<Result<_, Error>>()?;
+        let values = |path: &str| -> Vec<f64> {
+            samples
+                .iter()
+                .filter_map(|s| s.values[path].as_f64().copied())
+                .collect()
+        };
+
+        let lengths = values("/rocket/length");
+        let mean = lengths.iter().sum::<f64>() / lengths.len() as f64;
+        let var = lengths.iter().map(|l| (l - mean).powi(2)).sum::<f64>() / lengths.len() as f64;
+        assert!((mean - 1.5).abs() < 0.01);
+        assert!((var.sqrt() - 0.05).abs() < 0.005);
+
+        assert!(values("/rocket/mass").iter().all(|m| *m >= 1.95));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample_expressions() -> Result<(), Error> {
+        let mut ps = ParameterService::from_toml(
+            "mass = {dist=\"normal\", mean=2.0, std=0.05, dtype=\"f64\"}
+            weight = {expr=\"${/mass} * 9.81\", dtype=\"f64\"}
+            ",
+        )?;
+
+        // Expressions use the sampled values
+        ps.sample(42)?;
+        assert_ne!(ps.get_f64("/mass")?, 2.0);
+        assert_eq!(ps.get_f64("/weight")?, ps.get_f64("/mass")? * 9.81);
+
+        // Unless they were overridden
+        ps.apply_overrides(&["/weight=10"])?;
+        ps.sample(43)?;
+        assert_eq!(ps.get_f64("/weight")?, 10.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_distributions() {
+        for toml in [
+            "a = {dist=\"normal\", mean=1, dtype=\"f64\"}",
+            "a = {dist=\"normal\", mean=1, std=-1, dtype=\"f64\"}",
+            "a = {dist=\"uniform\", low=2, high=1, dtype=\"f64\"}",
+            "a = {dist=\"uniform\", mean=1, std=1, dtype=\"f64\"}",
+            "a = {dist=\"poisson\", mean=1, dtype=\"f64\"}",
+            "a = {val=1, dist=\"uniform\", low=0, high=2, dtype=\"i32\"}",
+        ] {
+            assert!(ParameterService::from_toml(toml).is_err(), "{toml}");
+        }
+    }
+}
@@ -0,0 +1,532 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    f64::consts,
+};
+
+use toml::Value;
+
+use super::{
+    deser::value_to_parameter, layers::is_within, schema::as_number, Error, Parameter,
+    ParameterService,
+};
+use crate::core::path::Path;
+
+/// A parameter whose value is computed from other parameters, written in parameter files as
+/// `expr` instead of `val`:
+///
+/// ```toml
+/// max_t = { expr = "${/sim/dt} * 1000", dtype = "f64" }
+/// ```
+///
+/// Expressions support `+ - * / ^`, parentheses, the constants `pi` and `e`, and the functions
+/// `sqrt abs exp ln log10 sin cos tan asin acos atan atan2 min max`. References are written as
+/// `${/absolute/path}` and must be numeric parameters, possibly expressions themselves.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Expression {
+    pub source: String,
+    pub dtype: String,
+}
+
+impl ParameterService {
+    /// Evaluates the expressions, by path, and sets the parameters to the results. Expressions
+    /// referencing other expressions are evaluated after them.
+    pub(super) fn evaluate_expressions(
+        &mut self,
+        exprs: &BTreeMap<String, Expression>,
+    ) -> Result<(), Error> {
+        let mut done = BTreeSet::new();
+
+        for path in exprs.keys() {
+            self.evaluate(path, exprs, &mut done, &mut vec![])?;
+        }
+
+        Ok(())
+    }
+
+    /// Evaluates again the expressions that reference the parameter at `changed`, its subtree
+    /// or its parents, directly or through other expressions
+    pub(super) fn update_expressions(&mut self, changed: &Path) -> Result<(), Error> {
+        let exprs = self.expressions();
+        let mut affected = BTreeMap::new();
+        let mut changed = vec![changed.clone()];
+
+        while let Some(c) = changed.pop() {
+            for (path, expr) in &exprs {
+                if affected.contains_key(path) {
+                    continue;
+                }
+
+                let references = Parser::new(&expr.source)
+                    .parse()
+                    .map(|ast| ast.references().iter().map(|r| r.to_string()).collect())
+                    .unwrap_or(vec![]);
+
+                let related = |r: &String| {
+                    is_within(r, &c) || Path::from_str(r).is_ok_and(|r| is_within(c.as_str(), &r))
+                };
+
+                if references.iter().any(related) {
+                    affected.insert(path.clone(), expr.clone());
+                    changed.push(Path::from_str(path)?);
+                }
+            }
+        }
+
+        self.evaluate_expressions(&affected)
+    }
+
+    fn evaluate(
+        &mut self,
+        path: &str,
+        exprs: &BTreeMap<String, Expression>,
+        done: &mut BTreeSet<String>,
+        stack: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        let Some(expr) = exprs.get(path).filter(|_| !done.contains(path)) else {
+            return Ok(());
+        };
+
+        if let Some(i) = stack.iter().position(|p| p == path) {
+            let cycle: Vec<_> = stack[i..]
+                .iter()
+                .map(String::as_str)
+                .chain([path])
+                .collect();
+            return Err(Error::ExpressionCycle(cycle.join(" -> ")));
+        }
+
+        let path = Path::from_str(path)?;
+        let error = |msg: String| Error::Expression {
+            path: path.clone(),
+            msg,
+        };
+
+        let ast = Parser::new(&expr.source).parse().map_err(error)?;
+
+        stack.push(path.to_string());
+        for reference in ast.references() {
+            self.evaluate(reference, exprs, done, stack)?;
+        }
+        stack.pop();
+
+        let value = ast.eval(&|reference| {
+            let param = self
+                .get(&Path::from_str(reference)?)
+                .ok_or(Error::NotFound(Path::from_str(reference)?))?;
+
+            as_number(&param).ok_or_else(|| {
+                error(format!(
+                    "'{reference}' is a '{}', not a number",
+                    param.type_string()
+                ))
+            })
+        })?;
+
+        let param = to_parameter(value, &expr.dtype, &path).map_err(error)?;
+        self.set_value(&path, param)?;
+        done.insert(path.to_string());
+
+        Ok(())
+    }
+}
+
+fn to_parameter(value: f64, dtype: &str, path: &Path) -> Result<Parameter, String> {
+    if !value.is_finite() {
+        return Err(format!("evaluates to {value}"));
+    }
+
+    match dtype {
+        "f64" => Ok(Parameter::F64(value)),
+        "f32" if value.abs() > f32::MAX as f64 => Err(format!("{value} is out of range for f32")),
+        "f32" => Ok(Parameter::F32(value as f32)),
+        _ if value.fract() != 0.0 => Err(format!("{value} is not an integer")),
+        _ if value.abs() >= i64::MAX as f64 => Err(format!("{value} is out of range for {dtype}")),
+        _ => value_to_parameter(Value::Integer(value as i64), dtype, path.as_str())
+            .map_err(|e| e.to_string()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Ast {
+    Number(f64),
+    Reference(String),
+    Neg(Box<Ast>),
+    Binary(char, Box<Ast>, Box<Ast>),
+    Call(String, Vec<Ast>),
+}
+
+impl Ast {
+    fn references(&self) -> Vec<&str> {
+        match self {
+            Ast::Number(_) => vec![],
+            Ast::Reference(r) => vec![r.as_str()],
+            Ast::Neg(a) => a.references(),
+            Ast::Binary(_, a, b) => [a.references(), b.references()].concat(),
+            Ast::Call(_, args) => args.iter().flat_map(|a| a.references()).collect(),
+        }
+    }
+
+    fn eval(&self, lookup: &impl Fn(&str) -> Result<f64, Error>) -> Result<f64, Error> {
+        Ok(match self {
+            Ast::Number(v) => *v,
+            Ast::Reference(r) => lookup(r)?,
+            Ast::Neg(a) => -a.eval(lookup)?,
+            Ast::Binary(op, a, b) => {
+                let (a, b) = (a.eval(lookup)?, b.eval(lookup)?);
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    _ => a.powf(b),
+                }
+            }
+            Ast::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|a| a.eval(lookup))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                call(name, &args).expect("function checked while parsing")
+            }
+        })
+    }
+}
+
+fn call(name: &str, args: &[f64]) -> Option<f64> {
+    let v = match (name, args) {
+        ("sqrt", [x]) => x.sqrt(),
+        ("abs", [x]) => x.abs(),
+        ("exp", [x]) => x.exp(),
+        ("ln", [x]) => x.ln(),
+        ("log10", [x]) => x.log10(),
+        ("sin", [x]) => x.sin(),
+        ("cos", [x]) => x.cos(),
+        ("tan", [x]) => x.tan(),
+        ("asin", [x]) => x.asin(),
+        ("acos", [x]) => x.acos(),
+        ("atan", [x]) => x.atan(),
+        ("atan2", [y, x]) => y.atan2(*x),
+        ("min", [a, b]) => a.min(*b),
+        ("max", [a, b]) => a.max(*b),
+        _ => return None,
+    };
+
+    Some(v)
+}
+
+/// Recursive descent parser for:
+///
+/// ```text
+/// expr  := term (('+' | '-') term)*
+/// term  := unary (('*' | '/') unary)*
+/// unary := '-' unary | power
+/// power := atom ('^' unary)?
+/// atom  := number | '${' path '}' | name | name '(' expr (',' expr)* ')' | '(' expr ')'
+/// ```
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str) -> Self {
+        Parser { src, pos: 0 }
+    }
+
+    fn parse(mut self) -> Result<Ast, String> {
+        let ast = self.expr()?;
+
+        match self.peek() {
+            None => Ok(ast),
+            Some(c) => Err(self.unexpected(c)),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Ast, String> {
+        let mut ast = self.term()?;
+
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.pos += 1;
+            ast = Ast::Binary(op, Box::new(ast), Box::new(self.term()?));
+        }
+
+        Ok(ast)
+    }
+
+    fn term(&mut self) -> Result<Ast, String> {
+        let mut ast = self.unary()?;
+
+        while let Some(op @ ('*' | '/')) = self.peek() {
+            self.pos += 1;
+            ast = Ast::Binary(op, Box::new(ast), Box::new(self.unary()?));
+        }
+
+        Ok(ast)
+    }
+
+    fn unary(&mut self) -> Result<Ast, String> {
+        if self.peek() == Some('-') {
+            self.pos += 1;
+            return Ok(Ast::Neg(Box::new(self.unary()?)));
+        }
+
+        let base = self.atom()?;
+
+        if self.peek() == Some('^') {
+            self.pos += 1;
+            return Ok(Ast::Binary('^', Box::new(base), Box::new(self.unary()?)));
+        }
+
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Ast, String> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let ast = self.expr()?;
+                self.expect(')')?;
+                Ok(ast)
+            }
+            Some('$') => {
+                self.pos += 1;
+                self.expect('{')?;
+
+                let len = self.rest().find('}').ok_or("missing '}'")?;
+                let path = self.rest()[..len].trim().to_string();
+                self.pos += len + 1;
+
+                Path::from_str(&path).map_err(|_| format!("invalid path '{path}'"))?;
+                Ok(Ast::Reference(path))
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let len = self.span(|c| c.is_ascii_alphanumeric() || c == '.');
+                let mut end = self.pos + len;
+
+                // Exponent sign, as in 1e-3
+                if self.src[..end].ends_with(['e', 'E']) && self.src[end..].starts_with(['+', '-'])
+                {
+                    end += 1;
+                    end += self.src[end..]
+                        .find(|c: char| !c.is_ascii_digit())
+                        .unwrap_or(self.src.len() - end);
+                }
+
+                let number = &self.src[self.pos..end];
+                self.pos = end;
+
+                number
+                    .parse()
+                    .map(Ast::Number)
+                    .map_err(|_| format!("invalid number '{number}'"))
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let len = self.span(|c| c.is_ascii_alphanumeric() || c == '_');
+                let name = self.rest()[..len].to_string();
+                self.pos += len;
+
+                if self.peek() != Some('(') {
+                    return match name.as_str() {
+                        "pi" => Ok(Ast::Number(consts::PI)),
+                        "e" => Ok(Ast::Number(consts::E)),
+                        _ => Err(format!("unknown constant '{name}'")),
+                    };
+                }
+
+                self.pos += 1;
+                let mut args = vec![self.expr()?];
+                while self.peek() == Some(',') {
+                    self.pos += 1;
+                    args.push(self.expr()?);
+                }
+                self.expect(')')?;
+
+                if call(&name, &vec![0.0; args.len()]).is_none() {
+                    return Err(format!(
+                        "unknown function '{name}' with {} arguments",
+                        args.len()
+                    ));
+                }
+
+                Ok(Ast::Call(name, args))
+            }
+            Some(c) => Err(self.unexpected(c)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    /// Next character that is not a space
+    fn peek(&mut self) -> Option<char> {
+        self.pos += self.span(char::is_whitespace);
+        self.rest().chars().next()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => Err(self.unexpected(c)),
+            None => Err(format!("missing '{expected}'")),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    /// Length of the longest prefix of the rest of the input matching `pred`
+    fn span(&self, pred: impl Fn(char) -> bool) -> usize {
+        self.rest().find(|c| !pred(c)).unwrap_or(self.rest().len())
+    }
+
+    fn unexpected(&self, c: char) -> String {
+        format!("unexpected '{c}' at position {}", self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(src: &str) -> Result<f64, String> {
+        Parser::new(src)
+            .parse()?
+            .eval(&|_| Ok(2.0))
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7.0));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9.0));
+        assert_eq!(eval("8 / 4 / 2"), Ok(1.0));
+        assert_eq!(eval("-2^2"), Ok(-4.0));
+        assert_eq!(eval("2^3^2"), Ok(512.0));
+        assert_eq!(eval("2^-1"), Ok(0.5));
+        assert_eq!(eval("1.5e3 + 2E-3"), Ok(1500.002));
+        assert_eq!(eval("${/a/b} * 10"), Ok(20.0));
+        assert_eq!(eval("max(1, ${/a}) + sqrt(16)"), Ok(6.0));
+        assert_eq!(eval("cos(pi)"), Ok(-1.0));
+
+        assert!(eval("1 +").is_err());
+        assert!(eval("(1 + 2").is_err());
+        assert!(eval("1 2").is_err());
+        assert!(eval("${/a").is_err());
+        assert!(eval("${a b}").is_err());
+        assert!(eval("foo(1)").is_err());
+        assert!(eval("sqrt(1, 2)").is_err());
+        assert!(eval("1.2.3").is_err());
+    }
+
+    #[test]
+    fn test_expressions_from_toml() -> Result<(), Error> {
+        let ps = ParameterService::from_toml(
+            "[sim]
+            dt = {val=0.01, dtype=\"f64\"}
+            max_t = {expr=\"${/sim/steps} * ${/sim/dt}\", dtype=\"f64\"}
+            steps = {expr=\"${/sim/base_steps} * 10\", dtype=\"u32\"}
+            base_steps = {val=100, dtype=\"u16\"}
+            ratio = {expr=\"1 / 3\", dtype=\"f32\"}
+            ",
+        )?;
+
+        assert_eq!(ps.get_u32("/sim/steps")?, 1000);
+        assert_eq!(ps.get_f64("/sim/max_t")?, 10.0);
+        assert_eq!(ps.get_f32("/sim/ratio")?, 1.0 / 3.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expressions_follow_changes() -> Result<(), Error> {
+        let mut ps = ParameterService::from_toml(
+            "[sim]
+            dt = {val=0.01, dtype=\"f64\"}
+            log_dt = {expr=\"${/sim/dt} * 10\", dtype=\"f64\"}
+            log_rate = {expr=\"1 / ${/sim/log_dt}\", dtype=\"f64\"}
+            ",
+        )?;
+
+        ps.apply_overrides(&["/sim/dt=0.001"])?;
+        assert_eq!(ps.get_f64("/sim/log_dt")?, 0.01);
+        assert_eq!(ps.get_f64("/sim/log_rate")?, 100.0);
+
+        // Replacing a parent also counts as a change
+        ps.set(
+            &"/sim".into(),
+            Parameter::Map(
+                [
+                    ("dt".to_string(), Parameter::F64(0.1)),
+                    ("log_dt".to_string(), Parameter::F64(0.0)),
+                ]
+                .into(),
+            ),
+        )?;
+        assert_eq!(ps.get_f64("/sim/log_dt")?, 0.0);
+        assert!(ps.get(&"/sim/log_rate".into()).is_none());
+
+        // A value set explicitly replaces the expression
+        let mut ps = ParameterService::from_toml(
+            "dt = {val=0.01, dtype=\"f64\"}
+            log_dt = {expr=\"${/dt} * 10\", dtype=\"f64\"}
+            ",
+        )?;
+        ps.set(&"/log_dt".into(), Parameter::F64(1.0))?;
+        ps.set(&"/dt".into(), Parameter::F64(0.5))?;
+        assert_eq!(ps.get_f64("/log_dt")?, 1.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expression_errors() {
+        assert_eq!(
+            ParameterService::from_toml(
+                "a = {expr=\"${/b} + 1\", dtype=\"f64\"}
+                b = {expr=\"${/c}\", dtype=\"f64\"}
+                c = {expr=\"${/a} * 2\", dtype=\"f64\"}
+                d = {val=1, dtype=\"f64\"}
+                "
+            )
+            .err(),
+            Some(Error::ExpressionCycle("/a -> /b -> /c -> /a".to_string()))
+        );
+
+        assert_eq!(
+            ParameterService::from_toml("a = {expr=\"${/a}\", dtype=\"f64\"}").err(),
+            Some(Error::ExpressionCycle("/a -> /a".to_string()))
+        );
+
+        assert_eq!(
+            ParameterService::from_toml("a = {expr=\"${/b}\", dtype=\"f64\"}").err(),
+            Some(Error::NotFound("/b".into()))
+        );
+
+        let msg = |toml: &str| match ParameterService::from_toml(toml) {
+            Err(Error::Expression { msg, .. }) => Some(msg),
+            _ => None,
+        };
+
+        assert_eq!(
+            msg("a = {expr=\"1 / 2\", dtype=\"i32\"}"),
+            Some("0.5 is not an integer".to_string())
+        );
+        assert_eq!(
+            msg("a = {expr=\"1 / 0\", dtype=\"f64\"}"),
+            Some("evaluates to inf".to_string())
+        );
+        assert_eq!(
+            msg("a = {expr=\"${/b}\", dtype=\"f64\"}
+                b = {val=\"x\", dtype=\"string\"}"),
+            Some("'/b' is a 'string', not a number".to_string())
+        );
+        assert!(msg("a = {expr=\"300\", dtype=\"u8\"}").is_some());
+
+        // Either a value or an expression
+        assert!(ParameterService::from_toml("a = {val=1, expr=\"1\", dtype=\"f64\"}").is_err());
+        assert!(ParameterService::from_toml("a = {expr=\"1\", dtype=\"string\"}").is_err());
+    }
+}
@@ -0,0 +1,470 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Component, Path as FilePath, PathBuf},
+};
+
+use super::{
+    deser::{parse_layer, Layer},
+    expr::Expression,
+    Error, Parameter, ParameterSchema, ParameterService,
+};
+use crate::core::path::Path;
+
+/// Parts of the files that are applied only once all of them are loaded
+#[derive(Default)]
+struct Pending {
+    schemas: BTreeMap<String, ParameterSchema>,
+    exprs: BTreeMap<String, Expression>,
+}
+
+impl ParameterService {
+    /// Loads several parameter files in order, each one overriding the parameters with the same
+    /// path defined by the previous ones.
+    ///
+    /// On top of parameters, a file can contain:
+    ///  - `include = ["base.toml", ...]`: files loaded right before this one. Relative paths are
+    ///    resolved from the directory of the including file.
+    ///  - `delete = ["/sim/rocket/crater/init/p0_n", ...]`: parameters, or whole subtrees, to
+    ///    remove before applying this file.
+    ///
+    /// Overriding a parameter with one of a different type is an error, reported together with
+    /// the file that tried to do it.
+    ///
+    /// Schema fields override the ones defined by previous files one by one. Parameters are
+    /// validated against their schemas once all the files are loaded.
+    ///
+    /// Expressions are also evaluated once all the files are loaded, so they can reference
+    /// parameters from any file, and use their final values.
+    pub fn from_toml_files<P: AsRef<FilePath>>(files: &[P]) -> Result<Self, Error> {
+        Self::from_toml_files_with(files, |f| fs::read_to_string(f))
+    }
+
+    fn from_toml_files_with<P: AsRef<FilePath>>(
+        files: &[P],
+        read: impl Fn(&FilePath) -> io::Result<String>,
+    ) -> Result<Self, Error> {
+        let mut ps = ParameterService::default();
+        let mut pending = Pending::default();
+
+        for file in files {
+            ps.load_layer_file(&normalize(file.as_ref()), &read, &mut vec![], &mut pending)?;
+        }
+
+        ps.set_expressions(pending.exprs)?;
+
+        for (path, schema) in pending.schemas {
+            ps.set_schema(&path, schema)?;
+        }
+
+        Ok(ps)
+    }
+
+    fn load_layer_file(
+        &mut self,
+        file: &FilePath,
+        read: &impl Fn(&FilePath) -> io::Result<String>,
+        stack: &mut Vec<PathBuf>,
+        pending: &mut Pending,
+    ) -> Result<(), Error> {
+        let name = file.display().to_string();
+
+        if stack.iter().any(|f| f == file) {
+            return Err(Error::IncludeCycle(name));
+        }
+
+        let content = read(file).map_err(|e| Error::Io(name.clone(), e.to_string()))?;
+        let layer =
+            parse_layer(&content).map_err(|e| Error::File(name.clone(), Box::new(e.into())))?;
+
+        let dir = file.parent().unwrap_or(FilePath::new(""));
+
+        stack.push(file.to_path_buf());
+        for include in layer.include.iter() {
+            self.load_layer_file(&normalize(&dir.join(include)), read, stack, pending)?;
+        }
+        stack.pop();
+
+        self.apply_layer(&name, layer, pending)
+    }
+
+    fn apply_layer(
+        &mut self,
+        file: &str,
+        layer: Layer,
+        pending: &mut Pending,
+    ) -> Result<(), Error> {
+        let in_file = |e: Error| Error::File(file.to_string(), Box::new(e));
+
+        for path in layer.delete {
+            let path = Path::from_str(&path).map_err(|e| in_file(e.into()))?;
+            self.remove(&path).map_err(in_file)?;
+
+            pending.schemas.retain(|p, _| !is_within(p, &path));
+            pending.exprs.retain(|p, _| !is_within(p, &path));
+        }
+
+        for (path, param) in layer.params {
+            let path = Path::from_str(&path).map_err(|e| in_file(e.into()))?;
+
+            self.check_override(file, &path, &param)?;
+
+            // An empty table does not clear a previously defined one
+            if param.is_map() && self.get(&path).is_some_and(|p| p.is_map()) {
+                continue;
+            }
+
+            self.set(&path, param).map_err(in_file)?;
+
+            // A value replaces the expression of a previous file, if any
+            pending.exprs.remove(path.as_str());
+        }
+
+        for (path, schema) in layer.schemas {
+            pending.schemas.entry(path).or_default().merge(schema);
+        }
+
+        pending.exprs.extend(layer.exprs);
+
+        Ok(())
+    }
+
+    fn check_override(&self, file: &str, path: &Path, param: &Parameter) -> Result<(), Error> {
+        let conflict = |path: Path, expected: &Parameter, found: &Parameter| Error::TypeConflict {
+            file: file.to_string(),
+            path,
+            expected: type_name(expected),
+            found: type_name(found),
+        };
+
+        // Every parent must be a map (or not exist yet)
+        let parts: Vec<_> = path.iter_parts().collect();
+        for i in 1..parts.len() {
+            let parent = Path::from_str(&format!("/{}", parts[..i].join("/")))?;
+
+            match self.get(&parent) {
+                Some(p) if !p.is_map() => {
+                    return Err(conflict(parent, &p, &Parameter::Map(Default::default())))
+                }
+                _ => (),
+            }
+        }
+
+        match self.get(path) {
+            Some(old) if !compatible(&old, param) => Err(conflict(path.clone(), &old, param)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parameters of the same type, or lists of the same type. Empty lists have no element type, so
+/// they are compatible with any other list.
+fn compatible(old: &Parameter, new: &Parameter) -> bool {
+    match (old, new) {
+        (Parameter::List(a), Parameter::List(b)) if a.is_empty() || b.is_empty() => true,
+        (old, new) => type_name(old) == type_name(new),
+    }
+}
+
+/// Type of a parameter, including the type of the elements for lists
+fn type_name(param: &Parameter) -> String {
+    match (param, param.dtype()) {
+        (Parameter::List(_), Some(t)) => format!("list<{}>", t),
+        (p, _) => p.type_string().to_string(),
+    }
+}
+
+/// `path` is `parent` or one of its descendants
+pub(super) fn is_within(path: &str, parent: &Path) -> bool {
+    let mut parts = Path::split_parts(path);
+    parent.iter_parts().all(|p| parts.next() == Some(p))
+}
+
+/// Lexically removes "." and ".." components, so that include cycles are detected even if a
+/// file is reached through different relative paths
+fn normalize(path: &FilePath) -> PathBuf {
+    let mut out = PathBuf::new();
+
+    for c in path.components() {
+        match c {
+            Component::CurDir => (),
+            Component::ParentDir
+                if matches!(out.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn load(files: &[&str], fs: &HashMap<&str, &str>) -> Result<ParameterService, Error> {
+        ParameterService::from_toml_files_with(files, |f| {
+            fs.get(f.to_str().unwrap())
+                .map(|c| c.to_string())
+                .ok_or(io::Error::from(io::ErrorKind::NotFound))
+        })
+    }
+
+    fn base_fs() -> HashMap<&'static str, &'static str> {
+        HashMap::from([
+            (
+                "config/base.toml",
+                "[sim]
+                dt = {val=0.01, dtype=\"f64\"}
+                max_t = {val=120, dtype=\"f64\"}
+
+                [sim.rocket]
+                mass = {val=2, dtype=\"f64\"}
+                inertia = {val=[1, 2, 3], dtype=\"f64\"}
+                ",
+            ),
+            (
+                "config/scenarios/heavy.toml",
+                "include = [\"../base.toml\"]
+
+                [sim.rocket]
+                mass = {val=3, dtype=\"f64\"}
+                ",
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_override() -> Result<(), Error> {
+        let mut fs = base_fs();
+        fs.insert(
+            "short.toml",
+            "[sim]
+            max_t = {val=10, dtype=\"f64\"}
+            ",
+        );
+
+        let ps = load(&["config/base.toml", "short.toml"], &fs)?;
+
+        assert_eq!(ps.get_f64("/sim/dt")?, 0.01);
+        assert_eq!(ps.get_f64("/sim/max_t")?, 10.0);
+        assert_eq!(ps.get_f64("/sim/rocket/mass")?, 2.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_include() -> Result<(), Error> {
+        let fs = base_fs();
+
+        let ps = load(&["config/scenarios/heavy.toml"], &fs)?;
+
+        assert_eq!(ps.get_f64("/sim/dt")?, 0.01);
+        assert_eq!(ps.get_f64("/sim/rocket/mass")?, 3.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete() -> Result<(), Error> {
+        let mut fs = base_fs();
+        fs.insert(
+            "no_rocket.toml",
+            "delete = [\"/sim/rocket\", \"/sim/not_there\"]
+
+            [sim.rocket]
+            diameter = {val=0.1, dtype=\"f64\"}
+            ",
+        );
+
+        let ps = load(&["config/base.toml", "no_rocket.toml"], &fs)?;
+
+        assert_eq!(ps.get_f64("/sim/dt")?, 0.01);
+        assert!(ps.get(&"/sim/rocket/mass".into()).is_none());
+        assert_eq!(ps.get_f64("/sim/rocket/diameter")?, 0.1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expressions() -> Result<(), Error> {
+        let mut fs = base_fs();
+        fs.insert(
+            "weight.toml",
+            "[sim.rocket]
+            weight = {expr=\"${/sim/rocket/mass} * 9.81\", dtype=\"f64\"}
+            ",
+        );
+        fs.insert(
+            "fixed_weight.toml",
+            "[sim.rocket]
+            weight = {val=30, dtype=\"f64\"}
+            ",
+        );
+
+        // Evaluated with the value of the last file
+        let ps = load(&["weight.toml", "config/scenarios/heavy.toml"], &fs)?;
+        assert!((ps.get_f64("/sim/rocket/weight")? - 29.43).abs() < 1e-9);
+
+        // A value overrides the expression
+        let ps = load(
+            &["config/base.toml", "weight.toml", "fixed_weight.toml"],
+            &fs,
+        )?;
+        assert_eq!(ps.get_f64("/sim/rocket/weight")?, 30.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_type_conflict() {
+        let mut fs = base_fs();
+        fs.insert(
+            "bad_type.toml",
+            "[sim]
+            dt = {val=1, dtype=\"i32\"}
+            ",
+        );
+        fs.insert(
+            "bad_list.toml",
+            "[sim.rocket]
+            inertia = {val=[1, 2, 3], dtype=\"f32\"}
+            ",
+        );
+        fs.insert(
+            "bad_parent.toml",
+            "[sim.dt]
+            x = {val=1, dtype=\"f64\"}
+            ",
+        );
+        fs.insert(
+            "empty_list.toml",
+            "[sim.rocket]
+            inertia = {val=[], dtype=\"f64\"}
+            ",
+        );
+
+        assert_eq!(
+            load(&["config/base.toml", "bad_type.toml"], &fs).err(),
+            Some(Error::TypeConflict {
+                file: "bad_type.toml".to_string(),
+                path: "/sim/dt".into(),
+                expected: "f64".to_string(),
+                found: "i32".to_string()
+            })
+        );
+
+        assert_eq!(
+            load(&["config/base.toml", "bad_list.toml"], &fs).err(),
+            Some(Error::TypeConflict {
+                file: "bad_list.toml".to_string(),
+                path: "/sim/rocket/inertia".into(),
+                expected: "list<f64>".to_string(),
+                found: "list<f32>".to_string()
+            })
+        );
+
+        assert_eq!(
+            load(&["config/base.toml", "bad_parent.toml"], &fs).err(),
+            Some(Error::TypeConflict {
+                file: "bad_parent.toml".to_string(),
+                path: "/sim/dt".into(),
+                expected: "f64".to_string(),
+                found: "map".to_string()
+            })
+        );
+
+        assert!(load(&["config/base.toml", "empty_list.toml"], &fs).is_ok());
+    }
+
+    #[test]
+    fn test_schema() -> Result<(), Error> {
+        let mut fs = base_fs();
+        fs.insert(
+            "schema.toml",
+            "[sim]
+            dt = {val=0.01, dtype=\"f64\", min=0.001, max=0.1, unit=\"s\"}
+
+            [sim.rocket]
+            mass = {val=2, dtype=\"f64\", min=1}
+            ",
+        );
+        fs.insert(
+            "wider.toml",
+            "[sim]
+            dt = {val=0.5, dtype=\"f64\", max=1}
+            ",
+        );
+        fs.insert(
+            "too_large.toml",
+            "[sim]
+            dt = {val=0.5, dtype=\"f64\"}
+            ",
+        );
+        fs.insert(
+            "no_rocket.toml",
+            "delete = [\"/sim/rocket\"]
+
+            [sim.rocket]
+            mass = {val=0.5, dtype=\"f64\"}
+            ",
+        );
+
+        let ps = load(&["config/base.toml", "schema.toml", "wider.toml"], &fs)?;
+        assert_eq!(ps.get_f64("/sim/dt")?, 0.5);
+        assert_eq!(
+            ps.schema("/sim/dt")?,
+            Some(ParameterSchema {
+                min: Some(0.001),
+                max: Some(1.0),
+                unit: Some("s".to_string()),
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(
+            load(&["config/base.toml", "schema.toml", "too_large.toml"], &fs).err(),
+            Some(Error::AboveMax {
+                path: "/sim/dt".into(),
+                value: "0.5".to_string(),
+                max: "0.1".to_string()
+            })
+        );
+
+        // Deleting a parameter also deletes its schema
+        let ps = load(&["config/base.toml", "schema.toml", "no_rocket.toml"], &fs)?;
+        assert_eq!(ps.get_f64("/sim/rocket/mass")?, 0.5);
+        assert_eq!(ps.schema("/sim/rocket/mass")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let fs = HashMap::from([
+            ("a.toml", "include = [\"./b.toml\"]"),
+            ("b.toml", "include = [\"dir/../a.toml\"]"),
+            ("c.toml", "include = [\"missing.toml\"]"),
+            ("d.toml", "a1 = 2"),
+        ]);
+
+        assert_eq!(
+            load(&["a.toml"], &fs).err(),
+            Some(Error::IncludeCycle("a.toml".to_string()))
+        );
+
+        assert!(matches!(
+            load(&["c.toml"], &fs).err(),
+            Some(Error::Io(f, _)) if f == "missing.toml"
+        ));
+
+        assert!(matches!(
+            load(&["d.toml"], &fs).err(),
+            Some(Error::File(f, _)) if f == "d.toml"
+        ));
+    }
+}
@@ -0,0 +1,177 @@
+use nalgebra::{Matrix3, Quaternion, SMatrix, SVector, UnitQuaternion, Vector3};
+
+use super::{Error, Parameter, ParameterService};
+use crate::core::path::Path;
+
+impl ParameterService {
+    /// Gets a list of exactly `N` f64 as a vector
+    pub fn get_svector<const N: usize>(&self, path: &str) -> Result<SVector<f64, N>, Error> {
+        let v = self.get_vec_f64(path)?;
+
+        if v.len() != N {
+            return Err(Error::BadShape {
+                path: Path::from_str(path)?,
+                expected: N.to_string(),
+                found: v.len().to_string(),
+            });
+        }
+
+        Ok(SVector::from_column_slice(&v))
+    }
+
+    pub fn get_vector3(&self, path: &str) -> Result<Vector3<f64>, Error> {
+        self.get_svector::<3>(path)
+    }
+
+    /// Gets a `R`x`C` matrix, written either as a list of `R` rows of `C` f64 each, or as a flat
+    /// list of `R * C` f64 in row-major order
+    pub fn get_smatrix<const R: usize, const C: usize>(
+        &self,
+        path: &str,
+    ) -> Result<SMatrix<f64, R, C>, Error> {
+        let path = Path::from_str(path)?;
+        let param = self.get(&path).ok_or(Error::NotFound(path.clone()))?;
+
+        let type_mismatch = |found: &Parameter| {
+            Error::TypeMismatch(
+                "list<f64>".to_string(),
+                path.clone(),
+                found.type_string().to_string(),
+            )
+        };
+        let bad_shape = |found: String| Error::BadShape {
+            path: path.clone(),
+            expected: format!("{R}x{C} or {}", R * C),
+            found,
+        };
+
+        let list = param.as_list().ok_or_else(|| type_mismatch(&param))?;
+
+        let rows: Vec<&Parameter> = if !list.is_empty() && list.iter().all(|p| p.is_list()) {
+            let rows: Vec<_> = list.iter().filter_map(|r| r.as_list()).collect();
+
+            if rows.len() != R || rows.iter().any(|r| r.len() != C) {
+                let lengths: Vec<_> = rows.iter().map(|r| r.len().to_string()).collect();
+                return Err(bad_shape(format!("[{}]", lengths.join(", "))));
+            }
+
+            rows.into_iter().flatten().collect()
+        } else if list.len() == R * C {
+            list.iter().collect()
+        } else {
+            return Err(bad_shape(list.len().to_string()));
+        };
+
+        let values = rows
+            .into_iter()
+            .map(|p| p.as_f64().copied().ok_or_else(|| type_mismatch(p)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(SMatrix::from_row_slice(&values))
+    }
+
+    pub fn get_matrix3(&self, path: &str) -> Result<Matrix3<f64>, Error> {
+        self.get_smatrix::<3, 3>(path)
+    }
+
+    /// Gets a rotation written as a `[w, x, y, z]` quaternion. The quaternion is normalized, so
+    /// it does not need to have unit norm, but it cannot be zero.
+    pub fn get_unit_quaternion(&self, path: &str) -> Result<UnitQuaternion<f64>, Error> {
+        let v = self.get_svector::<4>(path)?;
+
+        UnitQuaternion::try_new(Quaternion::new(v[0], v[1], v[2], v[3]), f64::EPSILON)
+            .ok_or(Error::ZeroQuaternion(Path::from_str(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{matrix, vector};
+
+    use super::*;
+
+    #[test]
+    fn test_vectors() -> Result<(), Error> {
+        let ps = ParameterService::from_toml(
+            "v3 = {val=[1, 2, 3], dtype=\"f64\"}
+            v2 = {val=[1, 2], dtype=\"f64\"}
+            v3_f32 = {val=[1, 2, 3], dtype=\"f32\"}
+            ",
+        )?;
+
+        assert_eq!(ps.get_vector3("/v3")?, vector![1.0, 2.0, 3.0]);
+        assert_eq!(ps.get_svector::<2>("/v2")?, vector![1.0, 2.0]);
+
+        assert_eq!(
+            ps.get_vector3("/v2"),
+            Err(Error::BadShape {
+                path: "/v2".into(),
+                expected: "3".to_string(),
+                found: "2".to_string()
+            })
+        );
+        assert!(matches!(
+            ps.get_vector3("/v3_f32"),
+            Err(Error::TypeMismatch(..))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrices() -> Result<(), Error> {
+        let ps = ParameterService::from_toml(
+            "v3 = {val=[1, 2, 3], dtype=\"f64\"}
+            v3_f32 = {val=[1, 2, 3], dtype=\"f32\"}
+            flat = {val=[1, 2, 3, 4, 5, 6, 7, 8, 9], dtype=\"f64\"}
+            nested = {val=[[1, 2, 3], [4, 5, 6], [7, 8, 9]], dtype=\"f64\"}
+            ragged = {val=[[1, 2, 3], [4, 5], [7, 8, 9]], dtype=\"f64\"}
+            ",
+        )?;
+
+        let m = matrix![1.0, 2.0, 3.0; 4.0, 5.0, 6.0; 7.0, 8.0, 9.0];
+        assert_eq!(ps.get_matrix3("/flat")?, m);
+        assert_eq!(ps.get_matrix3("/nested")?, m);
+        assert_eq!(ps.get_smatrix::<1, 3>("/v3")?, matrix![1.0, 2.0, 3.0]);
+
+        assert_eq!(
+            ps.get_matrix3("/ragged"),
+            Err(Error::BadShape {
+                path: "/ragged".into(),
+                expected: "3x3 or 9".to_string(),
+                found: "[3, 2, 3]".to_string()
+            })
+        );
+        assert_eq!(
+            ps.get_matrix3("/v3"),
+            Err(Error::BadShape {
+                path: "/v3".into(),
+                expected: "3x3 or 9".to_string(),
+                found: "3".to_string()
+            })
+        );
+        assert!(matches!(
+            ps.get_smatrix::<1, 3>("/v3_f32"),
+            Err(Error::TypeMismatch(..))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_quaternions() -> Result<(), Error> {
+        let ps = ParameterService::from_toml(
+            "quat = {val=[2, 0, 0, 0], dtype=\"f64\"}
+            zero_quat = {val=[0, 0, 0, 0], dtype=\"f64\"}
+            ",
+        )?;
+
+        assert_eq!(ps.get_unit_quaternion("/quat")?, UnitQuaternion::identity());
+        assert_eq!(
+            ps.get_unit_quaternion("/zero_quat"),
+            Err(Error::ZeroQuaternion("/zero_quat".into()))
+        );
+
+        Ok(())
+    }
+}
@@ -1,4 +1,20 @@
 mod parameters;
 mod deser;
+mod diff;
+mod dist;
+mod expr;
+mod layers;
+mod linalg;
+mod overrides;
+mod proto;
+mod schema;
+mod ser;
+mod typed;
+mod units;
+mod watch;
 
-pub use parameters::*;
\ No newline at end of file
+pub use diff::ParameterDiff;
+pub use dist::{Distribution, ParameterSamples};
+pub use parameters::*;
+pub use schema::ParameterSchema;
+pub use watch::{ParameterWatch, WATCH_CAPACITY};
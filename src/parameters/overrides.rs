@@ -0,0 +1,220 @@
+use std::env;
+
+use itertools::join;
+
+use super::{deser::parse_value, Error, Parameter, ParameterService};
+use crate::core::path::Path;
+
+impl ParameterService {
+    /// Sets an existing parameter from a string, parsed according to the type of its current
+    /// value: `"0.001"` for a f64, `"[1, 2, 3]"` for a list, `"simple"` for a string. The value
+    /// replaces the expression of the parameter, if it had one.
+    ///
+    /// Empty lists cannot be set, as the type of their elements is not known.
+    pub fn set_from_str(&mut self, path: &str, value: &str) -> Result<Option<Parameter>, Error> {
+        let path = Path::from_str(path)?;
+        let current = self.get(&path).ok_or(Error::NotFound(path.clone()))?;
+
+        let bad_value = || Error::BadOverrideValue {
+            path: path.clone(),
+            value: value.to_string(),
+            dtype: current.type_string().to_string(),
+        };
+
+        let dtype = match current.dtype() {
+            Some(dtype) => dtype,
+            None if current.is_list() => return Err(Error::UntypedOverride(path)),
+            None => return Err(bad_value()),
+        };
+        let param = parse_value(value, dtype, &path.to_string()).map_err(|_| bad_value())?;
+
+        // Lists can only be replaced by lists and scalars by scalars
+        if param.is_list() != current.is_list() {
+            return Err(bad_value());
+        }
+
+        self.set(&path, param)
+    }
+
+    /// Applies overrides in the form `PATH=VALUE`, for example `/sim/dt=0.001`. See
+    /// `set_from_str()`.
+    pub fn apply_overrides<S: AsRef<str>>(&mut self, overrides: &[S]) -> Result<(), Error> {
+        for o in overrides {
+            let (path, value) = o
+                .as_ref()
+                .split_once('=')
+                .ok_or(Error::BadOverrideSyntax(o.as_ref().to_string()))?;
+
+            self.set_from_str(path.trim(), value.trim())?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies overrides from the environment variables starting with `prefix`. The rest of the
+    /// variable name is the path of the parameter, with "__" in place of "/": with prefix
+    /// `QUADCOPTER_PARAM`, `QUADCOPTER_PARAM__sim__dt=0.001` sets `/sim/dt`.
+    pub fn apply_env_overrides(&mut self, prefix: &str) -> Result<(), Error> {
+        self.apply_env_overrides_from(prefix, env::vars())
+    }
+
+    fn apply_env_overrides_from(
+        &mut self,
+        prefix: &str,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), Error> {
+        let mut overrides: Vec<_> = vars
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let path = name.strip_prefix(prefix)?.strip_prefix("__")?;
+                Some((format!("/{}", join(path.split("__"), "/")), value))
+            })
+            .collect();
+
+        // Environment variables come in no particular order
+        overrides.sort();
+
+        for (path, value) in overrides {
+            self.set_from_str(&path, &value)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_from_str() -> Result<(), Error> {
+        let mut ps = ParameterService::from_toml(
+            "[sim]
+            dt = {val=0.01, dtype=\"f64\"}
+            steps = {val=10, dtype=\"u8\"}
+            engine = {val=\"simple\", dtype=\"string\"}
+            inertia = {val=[1, 2, 3], dtype=\"f32\"}
+            ",
+        )?;
+
+        assert_eq!(
+            ps.set_from_str("/sim/dt", "0.001")?,
+            Some(Parameter::F64(0.01))
+        );
+        assert_eq!(ps.get_f64("/sim/dt")?, 0.001);
+
+        ps.set_from_str("/sim/steps", "20")?;
+        assert_eq!(ps.get_u8("/sim/steps")?, 20);
+
+        ps.set_from_str("/sim/engine", "complex")?;
+        assert_eq!(ps.get_string("/sim/engine")?, "complex");
+
+        ps.set_from_str("/sim/inertia", "[3, 2, 1]")?;
+        assert_eq!(ps.get_vec_f32("/sim/inertia")?, vec![3.0, 2.0, 1.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_from_str_errors() -> Result<(), Error> {
+        let mut ps = ParameterService::from_toml(
+            "[sim]
+            dt = {val=0.01, dtype=\"f64\"}
+            steps = {val=10, dtype=\"u8\"}
+            inertia = {val=[1, 2, 3], dtype=\"f32\"}
+            empty = {val=[], dtype=\"f32\"}
+            ",
+        )?;
+
+        assert_eq!(
+            ps.set_from_str("/sim/missing", "1"),
+            Err(Error::NotFound("/sim/missing".into()))
+        );
+
+        let bad_value = |path: &str, value: &str, dtype: &str| {
+            Err(Error::BadOverrideValue {
+                path: path.into(),
+                value: value.to_string(),
+                dtype: dtype.to_string(),
+            })
+        };
+
+        assert_eq!(
+            ps.set_from_str("/sim/dt", "abc"),
+            bad_value("/sim/dt", "abc", "f64")
+        );
+        assert_eq!(
+            ps.set_from_str("/sim/steps", "1.5"),
+            bad_value("/sim/steps", "1.5", "u8")
+        );
+        assert_eq!(
+            ps.set_from_str("/sim/dt", "[1.0]"),
+            bad_value("/sim/dt", "[1.0]", "f64")
+        );
+        assert_eq!(
+            ps.set_from_str("/sim/inertia", "1.0"),
+            bad_value("/sim/inertia", "1.0", "list")
+        );
+        assert_eq!(
+            ps.set_from_str("/sim/steps", "300"),
+            bad_value("/sim/steps", "300", "u8")
+        );
+        assert_eq!(
+            ps.set_from_str("/sim", "1.0"),
+            bad_value("/sim", "1.0", "map")
+        );
+        assert_eq!(
+            ps.set_from_str("/sim/empty", "[1.0]"),
+            Err(Error::UntypedOverride("/sim/empty".into()))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_overrides() -> Result<(), Error> {
+        let mut ps = ParameterService::from_toml(
+            "[sim]
+            dt = {val=0.01, dtype=\"f64\"}
+            engine = {val=\"simple\", dtype=\"string\"}
+            ",
+        )?;
+
+        ps.apply_overrides(&["/sim/dt=0.5", "/sim/engine = a=b"])?;
+        assert_eq!(ps.get_f64("/sim/dt")?, 0.5);
+        assert_eq!(ps.get_string("/sim/engine")?, "a=b");
+
+        assert_eq!(
+            ps.apply_overrides(&["/sim/dt"]),
+            Err(Error::BadOverrideSyntax("/sim/dt".to_string()))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_overrides() -> Result<(), Error> {
+        let mut ps = ParameterService::from_toml(
+            "[sim]
+            dt = {val=0.01, dtype=\"f64\"}
+            inertia = {val=[1, 2, 3], dtype=\"f32\"}
+            ",
+        )?;
+
+        ps.apply_env_overrides_from(
+            "QUADCOPTER_PARAM",
+            [
+                ("QUADCOPTER_PARAM__sim__dt", "0.5"),
+                ("QUADCOPTER_PARAM__sim__inertia", "[0, 0, 0]"),
+                ("QUADCOPTER_PARAMETERS", "ignored"),
+                ("HOME", "/home"),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string())),
+        )?;
+
+        assert_eq!(ps.get_f64("/sim/dt")?, 0.5);
+        assert_eq!(ps.get_vec_f32("/sim/inertia")?, vec![0.0, 0.0, 0.0]);
+
+        Ok(())
+    }
+}
@@ -1,4 +1,12 @@
-use super::deser::{self};
+use super::{
+    deser::{self},
+    expr::Expression,
+    layers::is_within,
+    schema::ParameterSchema,
+    ser,
+    units::Unit,
+    watch::{ParameterWatch, WatchEntry},
+};
 use crate::{
     parameters::deser::parse_str,
     core::path::{Path, PathError},
@@ -23,6 +31,9 @@ pub enum Error {
     #[error("Error parsing parameter from toml")]
     Toml(#[from] deser::Error),
 
+    #[error("Error writing parameters to toml")]
+    TomlSerialize(#[from] ser::Error),
+
     #[error("Invalid path")]
     Path(#[from] PathError),
 
@@ -31,6 +42,94 @@ pub enum Error {
 
     #[error("Requested type '{0}' for parameter '{1}', but is a '{2}")]
     TypeMismatch(String, Path, String),
+
+    #[error("Parameter '{path}' is a '{found}' in '{file}', but was previously defined as a '{expected}'")]
+    TypeConflict {
+        file: String,
+        path: Path,
+        expected: String,
+        found: String,
+    },
+
+    #[error("Error loading parameter file '{0}'")]
+    File(String, #[source] Box<Error>),
+
+    #[error("Cannot read parameter file '{0}': {1}")]
+    Io(String, String),
+
+    #[error("Parameter file '{0}' includes itself")]
+    IncludeCycle(String),
+
+    #[error("Invalid parameter override '{0}', expected 'PATH=VALUE'")]
+    BadOverrideSyntax(String),
+
+    #[error("Cannot set parameter '{path}' of type '{dtype}' to '{value}'")]
+    BadOverrideValue {
+        path: Path,
+        value: String,
+        dtype: String,
+    },
+
+    #[error(
+        "Cannot override parameter '{0}': it is an empty list, so the type of its elements is \
+        unknown. Give it a value in the parameter files instead."
+    )]
+    UntypedOverride(Path),
+
+    #[error("Value {value} of parameter '{path}' is below the minimum of {min}")]
+    BelowMin {
+        path: Path,
+        value: String,
+        min: String,
+    },
+
+    #[error("Value {value} of parameter '{path}' is above the maximum of {max}")]
+    AboveMax {
+        path: Path,
+        value: String,
+        max: String,
+    },
+
+    #[error("Value {value} of parameter '{path}' is not one of: {allowed}")]
+    NotAllowed {
+        path: Path,
+        value: String,
+        allowed: String,
+    },
+
+    #[error("Invalid schema for parameter '{0}': {1}")]
+    InvalidSchema(Path, String),
+
+    #[error("Cannot deserialize parameter '{path}': {msg}")]
+    Struct { path: String, msg: String },
+
+    #[error("Parameter '{path}' has shape {found}, expected {expected}")]
+    BadShape {
+        path: Path,
+        expected: String,
+        found: String,
+    },
+
+    #[error("Parameter '{0}' is a zero quaternion, which is not a valid rotation")]
+    ZeroQuaternion(Path),
+
+    #[error("Invalid protobuf value for parameter '{0}': {1}")]
+    BadProtoValue(Path, String),
+
+    #[error("Cannot evaluate the expression of parameter '{path}': {msg}")]
+    Expression { path: Path, msg: String },
+
+    #[error("Parameter expressions reference each other in a cycle: {0}")]
+    ExpressionCycle(String),
+
+    #[error("Invalid unit '{0}': {1}")]
+    BadUnit(String, String),
+
+    #[error("Parameter '{0}' has no unit, cannot convert it to '{1}'")]
+    NoUnit(Path, String),
+
+    #[error("Cannot convert parameter '{path}' from '{from}' to '{to}', the dimensions differ")]
+    IncompatibleUnits { path: Path, from: String, to: String },
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -68,7 +167,17 @@ impl Parameter {
         }
     }
 
-    fn type_string(&self) -> &str {
+    /// Type of a scalar parameter, or of the elements of a (possibly nested) list, as used in
+    /// the `dtype` field of parameter files. `None` for maps and empty lists.
+    pub fn dtype(&self) -> Option<&str> {
+        match self {
+            Parameter::List(l) => l.iter().find_map(|p| p.dtype()),
+            Parameter::Map(_) => None,
+            p => Some(p.type_string()),
+        }
+    }
+
+    pub fn type_string(&self) -> &str {
         match self {
             Parameter::Bool(_) => "bool",
             Parameter::U8(_) => "u8",
@@ -344,26 +453,47 @@ pub struct ParameterService {
     inner: Arc<Mutex<ParameterServiceInner>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 struct ParameterServiceInner {
     root: Parameter,
+
+    /// Incremented every time a parameter is set
+    version: u64,
+    watches: Vec<WatchEntry>,
+
+    /// Schemas by parameter path. A schema can exist before its parameter does.
+    schemas: BTreeMap<String, ParameterSchema>,
+
+    /// Expressions by parameter path, kept to evaluate them again after sampling
+    exprs: BTreeMap<String, Expression>,
 }
 
 impl Default for ParameterService {
     fn default() -> Self {
-        ParameterService {
-            inner: Arc::new(Mutex::new(ParameterServiceInner {
-                root: Parameter::Map(BTreeMap::default()),
-            })),
-        }
+        Self::from_root(Parameter::Map(BTreeMap::default()))
     }
 }
 
 impl ParameterService {
     pub fn from_toml(toml: &str) -> Result<Self, Error> {
-        let parsed: Vec<(String, Parameter)> = parse_str(toml)?;
+        let parsed = parse_str(toml)?;
+
+        let mut ps = Self::from_list(parsed.params)?;
+        ps.set_expressions(parsed.exprs.into_iter().collect())?;
+        for (path, schema) in parsed.schemas {
+            ps.set_schema(&path, schema)?;
+        }
+
+        Ok(ps)
+    }
+
+    /// Writes all the parameters in the same format accepted by `from_toml`. Parsing the result
+    /// gives back exactly the same parameters.
+    pub fn to_toml(&self) -> Result<String, Error> {
+        let inner = self.inner.lock().unwrap();
+        let root = inner.root.as_map().ok_or(Error::NonMapParent)?;
 
-        Self::from_list(parsed)
+        Ok(ser::to_string(root, &inner.schemas, &inner.exprs)?)
     }
 
     fn from_list(value: Vec<(String, Parameter)>) -> Result<Self, Error> {
@@ -375,28 +505,36 @@ impl ParameterService {
         Ok(ps)
     }
 
-    #[allow(dead_code)]
     fn from_root(root: Parameter) -> Self {
         ParameterService {
-            inner: Arc::new(Mutex::new(ParameterServiceInner { root })),
+            inner: Arc::new(Mutex::new(ParameterServiceInner {
+                root,
+                version: 0,
+                watches: vec![],
+                schemas: BTreeMap::new(),
+                exprs: BTreeMap::new(),
+            })),
         }
     }
 
-    pub fn get(&self, path: &Path) -> Option<Parameter> {
-        let mut root = &self.inner.lock().unwrap().root;
+    /// Independent copy of the parameters, with their schemas and expressions. Cloning a
+    /// `ParameterService` shares the parameters instead. Watches are not copied.
+    pub fn deep_copy(&self) -> ParameterService {
+        let inner = self.inner.lock().unwrap();
 
-        for part in path.iter_parts() {
-            match root {
-                Parameter::Map(m) => {
-                    root = m.get(part)?;
-                }
-                _ => {
-                    return None;
-                }
-            }
+        ParameterService {
+            inner: Arc::new(Mutex::new(ParameterServiceInner {
+                root: inner.root.clone(),
+                version: inner.version,
+                watches: vec![],
+                schemas: inner.schemas.clone(),
+                exprs: inner.exprs.clone(),
+            })),
         }
+    }
 
-        Some(root.clone())
+    pub fn get(&self, path: &Path) -> Option<Parameter> {
+        lookup(&self.inner.lock().unwrap().root, path.iter_parts()).cloned()
     }
 
     pub fn get_bool(&self, path: &str) -> Result<bool, Error> {
@@ -599,13 +737,145 @@ impl ParameterService {
         self.get_vec::<String>(path, "string", |p| p.as_string())
     }
 
+    /// Sets a parameter, or a whole subtree. The value replaces the expressions of the parameters
+    /// it overwrites, if any, and the expressions referencing them are evaluated again.
     pub fn set(&mut self, path: &Path, val: Parameter) -> Result<Option<Parameter>, Error> {
+        let old = self.set_value(path, val)?;
+
+        self.inner
+            .lock()
+            .unwrap()
+            .exprs
+            .retain(|p, _| !is_within(p, path));
+        self.update_expressions(path)?;
+
+        Ok(old)
+    }
+
+    /// Sets a parameter without looking at expressions
+    pub(super) fn set_value(
+        &mut self,
+        path: &Path,
+        val: Parameter,
+    ) -> Result<Option<Parameter>, Error> {
         if path.is_root() {
             return Err(Error::RootOverwrite);
         }
 
-        let mut root = &mut self.inner.lock().unwrap().root;
+        let mut inner = self.inner.lock().unwrap();
+        inner.validate(path, &val)?;
+        let old = Self::set_in(&mut inner.root, path, val)?;
+
+        inner.notify(path);
+
+        Ok(old)
+    }
+
+    /// Sets the schema of the parameter at `path`, replacing the previous one. If the parameter
+    /// exists, its current value must satisfy the schema.
+    pub fn set_schema(&mut self, path: &str, schema: ParameterSchema) -> Result<(), Error> {
+        let path = Path::from_str(path)?;
+
+        if let Some(unit) = &schema.unit {
+            Unit::parse(unit).map_err(|e| Error::InvalidSchema(path.clone(), e))?;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some(param) = lookup(&inner.root, path.iter_parts()) {
+            schema.validate(&path, param)?;
+        }
+
+        inner.schemas.insert(path.to_string(), schema);
+
+        Ok(())
+    }
+
+    pub fn schema(&self, path: &str) -> Result<Option<ParameterSchema>, Error> {
+        let path = Path::from_str(path)?;
+
+        Ok(self
+            .inner
+            .lock()
+            .unwrap()
+            .schemas
+            .get(path.as_str())
+            .cloned())
+    }
+
+    /// All the schemas, by path
+    pub fn schemas(&self) -> BTreeMap<String, ParameterSchema> {
+        self.inner.lock().unwrap().schemas.clone()
+    }
+
+    /// Evaluates the expressions, by path, and keeps them to evaluate them again when the
+    /// parameters they reference change, see `sample()`
+    pub(super) fn set_expressions(
+        &mut self,
+        exprs: BTreeMap<String, Expression>,
+    ) -> Result<(), Error> {
+        self.evaluate_expressions(&exprs)?;
+        self.inner.lock().unwrap().exprs = exprs;
+
+        Ok(())
+    }
+
+    pub(super) fn expressions(&self) -> BTreeMap<String, Expression> {
+        self.inner.lock().unwrap().exprs.clone()
+    }
+
+    /// Removes a parameter, or a whole subtree. Returns the removed value, if there was one.
+    pub fn remove(&mut self, path: &Path) -> Result<Option<Parameter>, Error> {
+        if path.is_root() {
+            return Err(Error::RootOverwrite);
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+
+        let mut root = &mut inner.root;
+        for part in Self::skip_last(path.iter_parts()) {
+            root = match root {
+                Parameter::Map(m) => match m.get_mut(part) {
+                    Some(p) => p,
+                    None => return Ok(None),
+                },
+                _ => return Ok(None),
+            };
+        }
+
+        let removed = match root {
+            Parameter::Map(m) => m.remove(path.iter_parts().last().unwrap()),
+            _ => None,
+        };
+
+        if removed.is_some() {
+            inner.exprs.retain(|p, _| !is_within(p, path));
+            inner.notify(path);
+        }
+
+        Ok(removed)
+    }
+
+    /// Watches the parameter at `path` and, if it is a map, all of its children. The parameter
+    /// does not need to exist yet.
+    pub fn watch(&self, path: &str) -> Result<ParameterWatch, Error> {
+        let (entry, watch) = WatchEntry::new(Path::from_str(path)?);
 
+        self.inner.lock().unwrap().watches.push(entry);
+
+        Ok(watch)
+    }
+
+    /// Number of times any parameter has been set
+    pub fn version(&self) -> u64 {
+        self.inner.lock().unwrap().version
+    }
+
+    fn set_in(
+        mut root: &mut Parameter,
+        path: &Path,
+        val: Parameter,
+    ) -> Result<Option<Parameter>, Error> {
         for part in Self::skip_last(path.iter_parts()) {
             root = match root {
                 Parameter::Map(m) => {
@@ -649,6 +919,57 @@ impl ParameterServiceInner {
     pub fn iter(&self) -> ParameterIter<'_> {
         self.root.iter()
     }
+
+    /// Checks `val`, about to be set at `path`, against the schemas of `path` and of all the
+    /// parameters below it
+    fn validate(&self, path: &Path, val: &Parameter) -> Result<(), Error> {
+        let prefix = path.as_str();
+
+        for (p, schema) in self.schemas.range(prefix.to_string()..) {
+            let Some(rest) = p.strip_prefix(prefix) else {
+                break;
+            };
+
+            // Sibling with a longer name, such as "/a/bc" for "/a/b"
+            if !rest.is_empty() && !rest.starts_with('/') {
+                continue;
+            }
+
+            if let Some(param) = lookup(val, Path::split_parts(rest)) {
+                schema.validate(&Path::from_str(p)?, param)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn notify(&mut self, path: &Path) {
+        self.version += 1;
+
+        self.watches.retain(|w| !w.is_orphan());
+        for w in self.watches.iter() {
+            w.notify(path);
+        }
+    }
+}
+
+/// Finds the parameter at the path made of `parts`, relative to `root`
+fn lookup<'a, 'p>(
+    mut root: &'a Parameter,
+    parts: impl Iterator<Item = &'p str>,
+) -> Option<&'a Parameter> {
+    for part in parts {
+        match root {
+            Parameter::Map(m) => {
+                root = m.get(part)?;
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+
+    Some(root)
 }
 
 impl Display for ParameterService {
@@ -698,6 +1019,7 @@ impl<'a> Iterator for ParameterIter<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{parameters::WATCH_CAPACITY, utils::ringchannel::ChannelError};
 
     /// Builds the following parameter tree:
     /// ```
@@ -843,6 +1165,188 @@ mod tests {
         check_parameters(&ps, &flattened);
     }
 
+    #[test]
+    fn test_to_toml() -> Result<(), Error> {
+        let ps = ParameterService::from_root(build_params());
+
+        assert_eq!(
+            ps.to_toml()?,
+            "a1 = { val = 1.23, dtype = \"f32\" }
+a2 = { val = \"hello param\", dtype = \"string\" }
+
+[a3]
+b2 = { val = 1.23, dtype = \"f64\" }
+
+[a3.b1]
+c1 = { val = 123, dtype = \"i32\" }
+c2 = { val = -123, dtype = \"i32\" }
+"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_round_trip() -> Result<(), Error> {
+        let mut ps = ParameterService::default();
+
+        let params = [
+            ("/bool", Parameter::Bool(true)),
+            ("/ints/u8", Parameter::U8(u8::MAX)),
+            ("/ints/u16", Parameter::U16(u16::MAX)),
+            ("/ints/u32", Parameter::U32(u32::MAX)),
+            ("/ints/u64", Parameter::U64(i64::MAX as u64)),
+            ("/ints/i8", Parameter::I8(i8::MIN)),
+            ("/ints/i16", Parameter::I16(i16::MIN)),
+            ("/ints/i32", Parameter::I32(i32::MIN)),
+            ("/ints/i64", Parameter::I64(i64::MIN)),
+            ("/floats/f32", Parameter::F32(0.1)),
+            ("/floats/f32_max", Parameter::F32(f32::MAX)),
+            ("/floats/f32_min", Parameter::F32(f32::MIN_POSITIVE)),
+            ("/floats/f64", Parameter::F64(0.1)),
+            ("/floats/f64_max", Parameter::F64(f64::MAX)),
+            ("/floats/f64_inf", Parameter::F64(f64::NEG_INFINITY)),
+            ("/floats/whole", Parameter::F64(2.0)),
+            (
+                "/strings/escaped",
+                Parameter::String("quote \" newline \n".to_string()),
+            ),
+            ("/lists/empty", Parameter::List(vec![])),
+            (
+                "/lists/u16",
+                Parameter::List(vec![Parameter::U16(1), Parameter::U16(2)]),
+            ),
+            (
+                "/lists/nested",
+                Parameter::List(vec![
+                    Parameter::List(vec![Parameter::F32(0.1), Parameter::F32(0.2)]),
+                    Parameter::List(vec![Parameter::F32(0.3)]),
+                ]),
+            ),
+            ("/u64", Parameter::U64(u64::MAX)),
+            (
+                "/lists/u64",
+                Parameter::List(vec![Parameter::U64(1), Parameter::U64(u64::MAX)]),
+            ),
+            ("/empty", Parameter::Map(BTreeMap::new())),
+            ("/a/b/c/d", Parameter::I8(1)),
+        ];
+
+        for (path, param) in params {
+            ps.set(&path.into(), param)?;
+        }
+
+        let schema = ParameterSchema {
+            min: Some(-1.0),
+            max: Some(2.5),
+            values: Some(vec![Parameter::U16(1), Parameter::U16(2)]),
+            unit: Some("m/s".to_string()),
+            description: Some("With \"quotes\"".to_string()),
+            dist: None,
+        };
+        ps.set_schema("/lists/u16", schema.clone())?;
+
+        let parsed = ParameterService::from_toml(&ps.to_toml()?)?;
+        assert_eq!(parsed.get(&"/".into()), ps.get(&"/".into()));
+        assert_eq!(parsed.schema("/lists/u16")?, Some(schema));
+
+        // Expressions are written as such, not as their current value
+        let ps = ParameterService::from_toml(
+            "dt = {val=0.01, dtype=\"f64\"}
+            log_dt = {expr=\"${/dt} * 10\", dtype=\"f64\", unit=\"s\"}
+            ",
+        )?;
+        let toml = ps.to_toml()?;
+        assert!(toml.contains(
+            "log_dt = { expr = \"${/dt} * 10\", dtype = \"f64\", unit = \"s\" }"
+        ));
+        let mut parsed = ParameterService::from_toml(&toml)?;
+        assert_eq!(parsed.get(&"/".into()), ps.get(&"/".into()));
+        parsed.set(&"/dt".into(), Parameter::F64(0.1))?;
+        assert_eq!(parsed.get_f64("/log_dt")?, 1.0);
+
+        let crater = ParameterService::from_toml(include_str!("../../config/crater/params.toml"))?;
+        let parsed = ParameterService::from_toml(&crater.to_toml()?)?;
+        assert_eq!(parsed.get(&"/".into()), crater.get(&"/".into()));
+        assert_eq!(
+            parsed.schema("/sim/rocket/crater/mass")?,
+            crater.schema("/sim/rocket/crater/mass")?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_toml_errors() -> Result<(), Error> {
+        let mut ps = ParameterService::default();
+        ps.set(
+            &"/a/b".into(),
+            Parameter::List(vec![Parameter::U8(1), Parameter::I8(1)]),
+        )?;
+        assert_eq!(
+            ps.to_toml(),
+            Err(Error::TomlSerialize(ser::Error::BadList(
+                "/a/b".to_string()
+            )))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch() -> Result<(), Error> {
+        let mut ps = ParameterService::from_root(build_params());
+
+        let mut w_leaf = ps.watch("/a3/b1/c1")?;
+        let mut w_subtree = ps.watch("/a3")?;
+        let mut w_other = ps.watch("/a1")?;
+
+        assert!(!w_leaf.has_changed());
+        assert_eq!(w_leaf.try_recv(), Err(ChannelError::Empty));
+
+        ps.set(&"/a3/b1/c1".into(), Parameter::I32(1))?;
+
+        assert!(w_leaf.has_changed());
+        assert!(!w_leaf.has_changed());
+        assert_eq!(w_leaf.try_recv(), Ok("/a3/b1/c1".into()));
+
+        assert!(w_subtree.has_changed());
+        assert_eq!(w_subtree.try_recv(), Ok("/a3/b1/c1".into()));
+
+        assert!(!w_other.has_changed());
+        assert_eq!(w_other.try_recv(), Err(ChannelError::Empty));
+
+        // Replacing a parent notifies the watches below it
+        ps.set(&"/a3/b1".into(), Parameter::Bool(true))?;
+        assert!(w_leaf.has_changed());
+        assert_eq!(w_leaf.try_recv(), Ok("/a3/b1".into()));
+        assert_eq!(w_subtree.version(), 2);
+
+        // Clones of the service share the watches
+        let mut ps2 = ps.clone();
+        ps2.set(&"/a1".into(), Parameter::F32(3.0))?;
+        assert!(w_other.has_changed());
+        assert_eq!(ps.version(), 3);
+
+        // Failed sets do not notify
+        assert!(ps.set(&"/a1/b1".into(), Parameter::F32(3.0)).is_err());
+        assert!(!w_other.has_changed());
+
+        // Paths that are not consumed do not pile up, the oldest ones are dropped
+        for i in 0..2 * WATCH_CAPACITY.get() {
+            ps.set(&"/a1".into(), Parameter::F32(i as f32))?;
+        }
+        assert_eq!(w_other.version(), 1 + 2 * WATCH_CAPACITY.get() as u64);
+        let paths: Vec<_> = std::iter::from_fn(|| w_other.try_recv().ok()).collect();
+        assert_eq!(paths.len(), WATCH_CAPACITY.get());
+
+        drop(w_leaf);
+        ps.set(&"/a3/b2".into(), Parameter::F64(2.0))?;
+        assert_eq!(ps.inner.lock().unwrap().watches.len(), 2);
+
+        Ok(())
+    }
+
     #[test]
     fn test_iter() {
         let ps = ParameterService::from_root(build_params());
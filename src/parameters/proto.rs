@@ -0,0 +1,177 @@
+use std::fmt::Display;
+
+use super::{Error, Parameter, ParameterService};
+use crate::{
+    core::path::Path,
+    crater_messages::parameters::{
+        self as proto, parameter_value::Value, ParameterList, ParameterValue,
+    },
+};
+
+impl ParameterService {
+    /// The parameters at or below `path` as protobuf messages, sorted by path
+    pub fn to_proto(&self, path: &str) -> Result<Vec<proto::Parameter>, Error> {
+        let path = Path::from_str(path)?;
+        let param = self.get(&path).ok_or(Error::NotFound(path.clone()))?;
+
+        let params = match param {
+            Parameter::Map(_) => param
+                .iter()
+                .map(|(p, v)| proto::Parameter {
+                    path: format!("{}{}", path.as_str().trim_end_matches('/'), p),
+                    value: v.into(),
+                })
+                .collect(),
+            p => vec![proto::Parameter {
+                path: path.to_string(),
+                value: (&p).into(),
+            }],
+        };
+
+        Ok(params)
+    }
+
+    /// Sets an existing parameter from a protobuf value, which must have the same type as the
+    /// current one
+    pub fn set_from_proto(
+        &mut self,
+        path: &str,
+        value: &ParameterValue,
+    ) -> Result<Option<Parameter>, Error> {
+        let path = Path::from_str(path)?;
+        let current = self.get(&path).ok_or(Error::NotFound(path.clone()))?;
+
+        let param = from_proto(&path, value)?;
+
+        if param.type_string() != current.type_string() || param.dtype() != current.dtype() {
+            return Err(Error::TypeMismatch(
+                current.dtype().unwrap_or(current.type_string()).to_string(),
+                path,
+                param.dtype().unwrap_or(param.type_string()).to_string(),
+            ));
+        }
+
+        self.set(&path, param)
+    }
+}
+
+impl From<&Parameter> for ParameterValue {
+    fn from(param: &Parameter) -> Self {
+        let value = match param {
+            Parameter::Bool(v) => Value::BoolVal(*v),
+            Parameter::U8(v) => Value::U8Val(*v as u32),
+            Parameter::U16(v) => Value::U16Val(*v as u32),
+            Parameter::U32(v) => Value::U32Val(*v),
+            Parameter::U64(v) => Value::U64Val(*v),
+            Parameter::I8(v) => Value::I8Val(*v as i32),
+            Parameter::I16(v) => Value::I16Val(*v as i32),
+            Parameter::I32(v) => Value::I32Val(*v),
+            Parameter::I64(v) => Value::I64Val(*v),
+            Parameter::F32(v) => Value::F32Val(*v),
+            Parameter::F64(v) => Value::F64Val(*v),
+            Parameter::String(v) => Value::StringVal(v.clone()),
+            Parameter::List(l) => Value::ListVal(ParameterList {
+                values: l.iter().map(|p| p.into()).collect(),
+            }),
+            // Maps are sent as the list of the parameters they contain, see to_proto()
+            Parameter::Map(_) => return ParameterValue { value: None },
+        };
+
+        ParameterValue { value: Some(value) }
+    }
+}
+
+fn from_proto(path: &Path, value: &ParameterValue) -> Result<Parameter, Error> {
+    let value = value
+        .value
+        .as_ref()
+        .ok_or(Error::BadProtoValue(path.clone(), "no value".to_string()))?;
+
+    let param = match value {
+        Value::BoolVal(v) => Parameter::Bool(*v),
+        Value::U8Val(v) => Parameter::U8(narrow(path, *v, "u8")?),
+        Value::U16Val(v) => Parameter::U16(narrow(path, *v, "u16")?),
+        Value::U32Val(v) => Parameter::U32(*v),
+        Value::U64Val(v) => Parameter::U64(*v),
+        Value::I8Val(v) => Parameter::I8(narrow(path, *v, "i8")?),
+        Value::I16Val(v) => Parameter::I16(narrow(path, *v, "i16")?),
+        Value::I32Val(v) => Parameter::I32(*v),
+        Value::I64Val(v) => Parameter::I64(*v),
+        Value::F32Val(v) => Parameter::F32(*v),
+        Value::F64Val(v) => Parameter::F64(*v),
+        Value::StringVal(v) => Parameter::String(v.clone()),
+        Value::ListVal(l) => Parameter::List(
+            l.values
+                .iter()
+                .map(|v| from_proto(path, v))
+                .collect::<Result<_, _>>()?,
+        ),
+    };
+
+    Ok(param)
+}
+
+/// Converts the widened protobuf integers back to the parameter type
+fn narrow<T: TryFrom<V>, V: Display + Copy>(path: &Path, v: V, dtype: &str) -> Result<T, Error> {
+    T::try_from(v)
+        .map_err(|_| Error::BadProtoValue(path.clone(), format!("{v} does not fit in a {dtype}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proto_roundtrip() -> Result<(), Error> {
+        let mut ps = ParameterService::from_toml(
+            "[sim]
+            dt = {val=0.01, dtype=\"f64\"}
+            steps = {val=[1, 2], dtype=\"u8\"}
+            ",
+        )?;
+
+        assert_eq!(
+            ps.to_proto("/")?,
+            vec![
+                proto::Parameter {
+                    path: "/sim/dt".to_string(),
+                    value: (&Parameter::F64(0.01)).into(),
+                },
+                proto::Parameter {
+                    path: "/sim/steps".to_string(),
+                    value: (&Parameter::List(vec![Parameter::U8(1), Parameter::U8(2)])).into(),
+                },
+            ]
+        );
+        assert_eq!(ps.to_proto("/sim")?, ps.to_proto("/")?);
+        assert_eq!(ps.to_proto("/sim/dt")?, ps.to_proto("/")?[..1]);
+
+        let steps = ParameterValue {
+            value: Some(Value::ListVal(ParameterList {
+                values: vec![ParameterValue {
+                    value: Some(Value::U8Val(3)),
+                }],
+            })),
+        };
+        ps.set_from_proto("/sim/steps", &steps)?;
+        assert_eq!(ps.get_vec_u8("/sim/steps")?, vec![3]);
+
+        let dt = ParameterValue {
+            value: Some(Value::F32Val(0.1)),
+        };
+        assert!(matches!(
+            ps.set_from_proto("/sim/dt", &dt),
+            Err(Error::TypeMismatch(..))
+        ));
+
+        let steps = ParameterValue {
+            value: Some(Value::U8Val(300)),
+        };
+        assert!(matches!(
+            ps.set_from_proto("/sim/steps", &steps),
+            Err(Error::BadProtoValue(..))
+        ));
+
+        Ok(())
+    }
+}
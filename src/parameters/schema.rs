@@ -0,0 +1,285 @@
+use itertools::join;
+
+use super::{dist::Distribution, Error, Parameter};
+use crate::core::path::Path;
+
+/// Constraints and documentation of a parameter.
+///
+/// In parameter files the schema is written next to the value, with all the fields optional:
+///
+/// ```toml
+/// mass = { val = 2, dtype = "f64", min = 0, unit = "kg", description = "Dry mass" }
+/// engine_type = { val = "simple", dtype = "string", values = ["simple"] }
+/// ```
+///
+/// For lists, the constraints apply to each element.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParameterSchema {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+
+    /// Allowed values, of the same type as the parameter
+    pub values: Option<Vec<Parameter>>,
+
+    /// Unit of the value, such as `deg` or `m/s^2`. Getters like `get_f64_in()` convert the
+    /// value from this unit to the requested one.
+    pub unit: Option<String>,
+    pub description: Option<String>,
+
+    /// Distribution the value is drawn from by `ParameterService::sample()`
+    pub dist: Option<Distribution>,
+}
+
+impl ParameterSchema {
+    pub fn is_empty(&self) -> bool {
+        *self == ParameterSchema::default()
+    }
+
+    /// Replaces the fields that are set in `other`, keeping the others
+    pub fn merge(&mut self, other: ParameterSchema) {
+        self.min = other.min.or(self.min);
+        self.max = other.max.or(self.max);
+        self.values = other.values.or(self.values.take());
+        self.unit = other.unit.or(self.unit.take());
+        self.description = other.description.or(self.description.take());
+        self.dist = other.dist.or(self.dist);
+    }
+
+    /// Checks that `param`, the value of the parameter at `path`, satisfies the schema
+    pub fn validate(&self, path: &Path, param: &Parameter) -> Result<(), Error> {
+        match param {
+            Parameter::List(l) => l.iter().try_for_each(|p| self.validate(path, p)),
+            Parameter::Map(_) if self.has_constraints() => Err(Error::InvalidSchema(
+                path.clone(),
+                "maps cannot have constraints".to_string(),
+            )),
+            Parameter::Map(_) => Ok(()),
+            p => self.validate_scalar(path, p),
+        }
+    }
+
+    fn has_constraints(&self) -> bool {
+        self.min.is_some() || self.max.is_some() || self.values.is_some() || self.dist.is_some()
+    }
+
+    fn validate_scalar(&self, path: &Path, param: &Parameter) -> Result<(), Error> {
+        if self.dist.is_some() && !matches!(param, Parameter::F32(_) | Parameter::F64(_)) {
+            return Err(Error::InvalidSchema(
+                path.clone(),
+                format!(
+                    "distributions cannot be used with '{}'",
+                    param.type_string()
+                ),
+            ));
+        }
+
+        if self.min.is_some() || self.max.is_some() {
+            let v = as_number(param).ok_or_else(|| {
+                Error::InvalidSchema(
+                    path.clone(),
+                    format!("min and max cannot be used with '{}'", param.type_string()),
+                )
+            })?;
+
+            if let Some(min) = self.min.filter(|min| v < *min) {
+                return Err(Error::BelowMin {
+                    path: path.clone(),
+                    value: param.to_string(),
+                    min: min.to_string(),
+                });
+            }
+
+            if let Some(max) = self.max.filter(|max| v > *max) {
+                return Err(Error::AboveMax {
+                    path: path.clone(),
+                    value: param.to_string(),
+                    max: max.to_string(),
+                });
+            }
+        }
+
+        match &self.values {
+            Some(values) if !values.contains(param) => Err(Error::NotAllowed {
+                path: path.clone(),
+                value: param.to_string(),
+                allowed: join(values, ", "),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Value of a numeric parameter as a f64, `None` for the other types
+pub(super) fn as_number(param: &Parameter) -> Option<f64> {
+    match param {
+        Parameter::U8(v) => Some(*v as f64),
+        Parameter::U16(v) => Some(*v as f64),
+        Parameter::U32(v) => Some(*v as f64),
+        Parameter::U64(v) => Some(*v as f64),
+        Parameter::I8(v) => Some(*v as f64),
+        Parameter::I16(v) => Some(*v as f64),
+        Parameter::I32(v) => Some(*v as f64),
+        Parameter::I64(v) => Some(*v as f64),
+        Parameter::F32(v) => Some(*v as f64),
+        Parameter::F64(v) => Some(*v),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::ParameterService;
+
+    #[test]
+    fn test_schema_from_toml() -> Result<(), Error> {
+        let ps = ParameterService::from_toml(
+            "[sim]
+            dt = {val=0.01, dtype=\"f64\", min=0, max=1, unit=\"s\", description=\"Time step\"}
+            engine = {val=\"simple\", dtype=\"string\", values=[\"simple\", \"complex\"]}
+            ",
+        )?;
+
+        assert_eq!(
+            ps.schema("/sim/dt")?,
+            Some(ParameterSchema {
+                min: Some(0.0),
+                max: Some(1.0),
+                unit: Some("s".to_string()),
+                description: Some("Time step".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            ps.schema("/sim/engine")?.and_then(|s| s.values),
+            Some(vec![
+                Parameter::String("simple".to_string()),
+                Parameter::String("complex".to_string())
+            ])
+        );
+        assert_eq!(ps.schema("/sim")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_on_load() {
+        assert_eq!(
+            ParameterService::from_toml("a = {val=-1, dtype=\"f64\", min=0}").err(),
+            Some(Error::BelowMin {
+                path: "/a".into(),
+                value: "-1".to_string(),
+                min: "0".to_string()
+            })
+        );
+        assert_eq!(
+            ParameterService::from_toml("a = {val=[1, 20], dtype=\"i32\", min=0, max=10}").err(),
+            Some(Error::AboveMax {
+                path: "/a".into(),
+                value: "20".to_string(),
+                max: "10".to_string()
+            })
+        );
+        assert_eq!(
+            ParameterService::from_toml("a = {val=\"x\", dtype=\"string\", values=[\"y\", \"z\"]}")
+                .err(),
+            Some(Error::NotAllowed {
+                path: "/a".into(),
+                value: "x".to_string(),
+                allowed: "y, z".to_string()
+            })
+        );
+        assert!(matches!(
+            ParameterService::from_toml("a = {val=\"x\", dtype=\"string\", min=0}").err(),
+            Some(Error::InvalidSchema(p, _)) if p == "/a".into()
+        ));
+    }
+
+    #[test]
+    fn test_validate_on_set() -> Result<(), Error> {
+        let mut ps = ParameterService::from_toml(
+            "[sim]
+            dt = {val=0.01, dtype=\"f64\", min=0, max=1}
+            engine = {val=\"simple\", dtype=\"string\", values=[\"simple\", \"complex\"]}
+            ",
+        )?;
+
+        ps.set(&"/sim/dt".into(), Parameter::F64(0.1))?;
+        assert_eq!(
+            ps.set(&"/sim/dt".into(), Parameter::F64(2.0)),
+            Err(Error::AboveMax {
+                path: "/sim/dt".into(),
+                value: "2".to_string(),
+                max: "1".to_string()
+            })
+        );
+        assert_eq!(ps.get_f64("/sim/dt")?, 0.1);
+
+        // Setting a parent validates the whole subtree
+        assert!(matches!(
+            ps.set(
+                &"/sim".into(),
+                Parameter::Map([("engine".to_string(), Parameter::String("x".to_string()))].into())
+            ),
+            Err(Error::NotAllowed { path, .. }) if path == "/sim/engine".into()
+        ));
+
+        assert_eq!(
+            ps.set_from_str("/sim/engine", "other").err(),
+            Some(Error::NotAllowed {
+                path: "/sim/engine".into(),
+                value: "other".to_string(),
+                allowed: "simple, complex".to_string()
+            })
+        );
+
+        // A schema set in code is checked against the current value
+        assert!(ps
+            .set_schema(
+                "/sim/dt",
+                ParameterSchema {
+                    min: Some(0.5),
+                    ..Default::default()
+                }
+            )
+            .is_err());
+
+        ps.set_schema(
+            "/sim/new",
+            ParameterSchema {
+                min: Some(0.5),
+                ..Default::default()
+            },
+        )?;
+        assert!(ps.set(&"/sim/new".into(), Parameter::F32(0.1)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut schema = ParameterSchema {
+            min: Some(0.0),
+            max: Some(1.0),
+            unit: Some("s".to_string()),
+            ..Default::default()
+        };
+
+        schema.merge(ParameterSchema {
+            max: Some(2.0),
+            description: Some("Time".to_string()),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            schema,
+            ParameterSchema {
+                min: Some(0.0),
+                max: Some(2.0),
+                unit: Some("s".to_string()),
+                description: Some("Time".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+}
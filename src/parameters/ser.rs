@@ -0,0 +1,180 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use itertools::join;
+use thiserror::Error;
+use toml::Value;
+
+use super::{expr::Expression, Parameter, ParameterSchema};
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum Error {
+    #[error("List parameter '{0}' must only contain values of the same type")]
+    BadList(String),
+
+    #[error("Error formatting toml")]
+    Format(#[from] std::fmt::Error),
+}
+
+/// Serializes a parameter tree in the same format parsed by `deser::parse_str`: every map is
+/// a table and every other parameter is an inline `{ val = ..., dtype = "..." }` table, followed
+/// by the fields of its schema, if it has one. Parameters with an expression are written as
+/// `{ expr = "...", dtype = "..." }` instead.
+pub(super) fn to_string(
+    root: &BTreeMap<String, Parameter>,
+    schemas: &BTreeMap<String, ParameterSchema>,
+    exprs: &BTreeMap<String, Expression>,
+) -> Result<String, Error> {
+    let mut out = String::new();
+    write_table(&mut out, root, schemas, exprs, &[])?;
+
+    Ok(out)
+}
+
+fn write_table(
+    out: &mut String,
+    table: &BTreeMap<String, Parameter>,
+    schemas: &BTreeMap<String, ParameterSchema>,
+    exprs: &BTreeMap<String, Expression>,
+    path: &[&str],
+) -> Result<(), Error> {
+    let has_values = table.values().any(|p| !p.is_map());
+
+    // Tables only containing other tables are implicitly defined by their children
+    if !path.is_empty() && (has_values || table.is_empty()) {
+        if !out.is_empty() {
+            writeln!(out)?;
+        }
+        writeln!(out, "[{}]", join(path.iter().map(|k| key(k)), "."))?;
+    }
+
+    for (name, param) in table.iter().filter(|(_, p)| !p.is_map()) {
+        let param_path = format!("/{}", join(path.iter().chain([&name.as_str()]), "/"));
+
+        match exprs.get(&param_path) {
+            Some(expr) => write!(
+                out,
+                "{} = {{ expr = {}, dtype = \"{}\"",
+                key(name),
+                Value::String(expr.source.clone()),
+                expr.dtype
+            )?,
+            None => write!(
+                out,
+                "{} = {{ val = {}, dtype = \"{}\"",
+                key(name),
+                parameter_to_value(param, &param_path)?,
+                dtype(param, &param_path)?
+            )?,
+        }
+
+        if let Some(schema) = schemas.get(&param_path) {
+            write_schema(out, schema, &param_path)?;
+        }
+
+        writeln!(out, " }}")?;
+    }
+
+    for (name, param) in table.iter() {
+        if let Parameter::Map(m) = param {
+            let mut nested = path.to_vec();
+            nested.push(name.as_str());
+            write_table(out, m, schemas, exprs, &nested)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_schema(out: &mut String, schema: &ParameterSchema, path: &str) -> Result<(), Error> {
+    if let Some(min) = schema.min {
+        write!(out, ", min = {}", Value::Float(min))?;
+    }
+    if let Some(max) = schema.max {
+        write!(out, ", max = {}", Value::Float(max))?;
+    }
+    if let Some(values) = &schema.values {
+        let values = Parameter::List(values.clone());
+        write!(out, ", values = {}", parameter_to_value(&values, path)?)?;
+    }
+    if let Some(unit) = &schema.unit {
+        write!(out, ", unit = {}", Value::String(unit.clone()))?;
+    }
+    if let Some(description) = &schema.description {
+        write!(
+            out,
+            ", description = {}",
+            Value::String(description.clone())
+        )?;
+    }
+    if let Some(dist) = &schema.dist {
+        let (name, fields) = dist.fields();
+        write!(out, ", dist = {}", Value::String(name.to_string()))?;
+        for (field, v) in fields {
+            write!(out, ", {field} = {}", Value::Float(v))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Parameter names may contain non-ascii alphanumeric characters, which need quoting in toml
+fn key(name: &str) -> String {
+    if name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        name.to_string()
+    } else {
+        Value::String(name.to_string()).to_string()
+    }
+}
+
+fn parameter_to_value(param: &Parameter, path: &str) -> Result<Value, Error> {
+    Ok(match param {
+        Parameter::Bool(v) => Value::Boolean(*v),
+        Parameter::U8(v) => Value::Integer(*v as i64),
+        Parameter::U16(v) => Value::Integer(*v as i64),
+        Parameter::U32(v) => Value::Integer(*v as i64),
+        // Toml integers are i64, larger values are written as strings
+        Parameter::U64(v) => match i64::try_from(*v) {
+            Ok(v) => Value::Integer(v),
+            Err(_) => Value::String(v.to_string()),
+        },
+        Parameter::I8(v) => Value::Integer(*v as i64),
+        Parameter::I16(v) => Value::Integer(*v as i64),
+        Parameter::I32(v) => Value::Integer(*v as i64),
+        Parameter::I64(v) => Value::Integer(*v),
+        // Go through the shortest decimal representation of the f32, so that "0.1" is written as
+        // 0.1 and not as 0.10000000149011612. Both are parsed back to the same f32.
+        Parameter::F32(v) => Value::Float(v.to_string().parse::<f64>().unwrap()),
+        Parameter::F64(v) => Value::Float(*v),
+        Parameter::String(v) => Value::String(v.clone()),
+        Parameter::List(v) => Value::Array(
+            v.iter()
+                .map(|p| parameter_to_value(p, path))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        Parameter::Map(_) => return Err(Error::BadList(path.to_string())),
+    })
+}
+
+/// Type of a scalar parameter, or of the elements of a (possibly nested) list
+fn dtype<'a>(param: &'a Parameter, path: &str) -> Result<&'a str, Error> {
+    fn scalar_types<'a>(param: &'a Parameter, types: &mut Vec<&'a str>) {
+        match param {
+            Parameter::List(l) => l.iter().for_each(|p| scalar_types(p, types)),
+            p => types.push(p.type_string()),
+        }
+    }
+
+    let mut types = vec![];
+    scalar_types(param, &mut types);
+
+    match types.first() {
+        // Any type will do for an empty list
+        None => Ok("f64"),
+        Some(&"map") => Err(Error::BadList(path.to_string())),
+        Some(t) if types.iter().all(|other| other == t) => Ok(t),
+        Some(_) => Err(Error::BadList(path.to_string())),
+    }
+}
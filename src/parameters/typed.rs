@@ -0,0 +1,392 @@
+use std::{collections::btree_map, fmt, slice};
+
+use serde::{
+    de::{
+        self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor,
+    },
+    forward_to_deserialize_any, Deserializer,
+};
+
+use super::{Error, Parameter, ParameterService};
+use crate::core::path::Path;
+
+impl ParameterService {
+    /// Deserializes the parameter at `path`, usually a map, into `T`. Struct fields are looked
+    /// up by name in the map, lists can be deserialized into `Vec`s, arrays and tuples, and
+    /// strings into unit enum variants.
+    ///
+    /// ```ignore
+    /// #[derive(Deserialize)]
+    /// struct Engine {
+    ///     total_impulse: f64,
+    ///     thrust_duration: f64,
+    /// }
+    ///
+    /// let engine: Engine = params.get_struct("/sim/rocket/crater/engine/simple")?;
+    /// ```
+    pub fn get_struct<T: DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
+        let path = Path::from_str(path)?;
+        let param = self.get(&path).ok_or(Error::NotFound(path.clone()))?;
+
+        T::deserialize(ParameterDeserializer {
+            param: &param,
+            path: path.to_string(),
+        })
+        .map_err(|e| Error::Struct {
+            path: e.path.unwrap_or_else(|| path.to_string()),
+            msg: e.msg,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct DeError {
+    /// Path of the parameter that caused the error, set while unwinding to the first
+    /// deserializer that knows it
+    path: Option<String>,
+    msg: String,
+
+    /// Name of a missing struct field, reported by the visitor of the containing map
+    missing: Option<&'static str>,
+}
+
+impl DeError {
+    fn at(mut self, path: &str) -> Self {
+        if self.path.is_none() {
+            self.path = Some(match self.missing.take() {
+                Some(field) => child_path(path, field),
+                None => path.to_string(),
+            });
+        }
+
+        self
+    }
+}
+
+impl de::Error for DeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        DeError {
+            path: None,
+            msg: msg.to_string(),
+            missing: None,
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        DeError {
+            path: None,
+            msg: "missing parameter".to_string(),
+            missing: Some(field),
+        }
+    }
+}
+
+impl de::StdError for DeError {}
+
+impl fmt::Display for DeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+fn child_path(path: &str, name: &str) -> String {
+    format!("{}/{}", path.trim_end_matches('/'), name)
+}
+
+struct ParameterDeserializer<'a> {
+    param: &'a Parameter,
+    path: String,
+}
+
+impl<'a> ParameterDeserializer<'a> {
+    fn visit<'de, V: Visitor<'de>>(&self, visitor: V) -> Result<V::Value, DeError> {
+        match self.param {
+            Parameter::Bool(v) => visitor.visit_bool(*v),
+            Parameter::U8(v) => visitor.visit_u8(*v),
+            Parameter::U16(v) => visitor.visit_u16(*v),
+            Parameter::U32(v) => visitor.visit_u32(*v),
+            Parameter::U64(v) => visitor.visit_u64(*v),
+            Parameter::I8(v) => visitor.visit_i8(*v),
+            Parameter::I16(v) => visitor.visit_i16(*v),
+            Parameter::I32(v) => visitor.visit_i32(*v),
+            Parameter::I64(v) => visitor.visit_i64(*v),
+            Parameter::F32(v) => visitor.visit_f32(*v),
+            Parameter::F64(v) => visitor.visit_f64(*v),
+            Parameter::String(v) => visitor.visit_str(v),
+            Parameter::List(l) => {
+                let mut access = ListAccess {
+                    iter: l.iter().enumerate(),
+                    path: &self.path,
+                };
+                let value = visitor.visit_seq(&mut access)?;
+
+                // Fixed size arrays and tuples stop reading at their length
+                match access.iter.len() {
+                    0 => Ok(value),
+                    _ => Err(de::Error::invalid_length(l.len(), &"fewer elements")),
+                }
+            }
+            Parameter::Map(m) => visitor.visit_map(MapParamAccess {
+                iter: m.iter(),
+                value: None,
+                path: &self.path,
+            }),
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for ParameterDeserializer<'_> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        self.visit(visitor).map_err(|e| e.at(&self.path))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        match self.param {
+            Parameter::String(s) => visitor
+                .visit_enum(s.as_str().into_deserializer())
+                .map_err(|e: DeError| e.at(&self.path)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct ListAccess<'a> {
+    iter: std::iter::Enumerate<slice::Iter<'a, Parameter>>,
+    path: &'a str,
+}
+
+impl<'de> SeqAccess<'de> for ListAccess<'_> {
+    type Error = DeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, DeError> {
+        match self.iter.next() {
+            Some((i, param)) => seed
+                .deserialize(ParameterDeserializer {
+                    param,
+                    path: format!("{}[{}]", self.path, i),
+                })
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct MapParamAccess<'a> {
+    iter: btree_map::Iter<'a, String, Parameter>,
+    value: Option<(&'a String, &'a Parameter)>,
+    path: &'a str,
+}
+
+impl<'de> MapAccess<'de> for MapParamAccess<'_> {
+    type Error = DeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, DeError> {
+        match self.iter.next() {
+            Some((name, param)) => {
+                self.value = Some((name, param));
+                seed.deserialize(name.as_str().into_deserializer())
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, DeError> {
+        let (name, param) = self
+            .value
+            .take()
+            .ok_or_else(|| de::Error::custom("value requested before key"))?;
+
+        seed.deserialize(ParameterDeserializer {
+            param,
+            path: child_path(self.path, name),
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum EngineType {
+        #[serde(rename = "simple")]
+        Simple,
+        #[serde(rename = "complex")]
+        Complex,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Init {
+        azimuth: f64,
+        p0_n: [f64; 3],
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Rocket {
+        mass: f64,
+        steps: u32,
+        inertia: Vec<f32>,
+        engine_type: EngineType,
+        name: Option<String>,
+        init: Init,
+    }
+
+    #[test]
+    fn test_get_struct() -> Result<(), Error> {
+        let ps = ParameterService::from_toml(
+            "[rocket]
+            mass = {val=2.5, dtype=\"f64\"}
+            steps = {val=10, dtype=\"u8\"}
+            inertia = {val=[1, 2, 3], dtype=\"f32\"}
+            engine_type = {val=\"complex\", dtype=\"string\"}
+            unused = {val=true, dtype=\"bool\"}
+
+            [rocket.init]
+            azimuth = {val=170, dtype=\"f64\"}
+            p0_n = {val=[0, 0, -1], dtype=\"f64\"}
+            ",
+        )?;
+
+        assert_eq!(
+            ps.get_struct::<Rocket>("/rocket")?,
+            Rocket {
+                mass: 2.5,
+                steps: 10,
+                inertia: vec![1.0, 2.0, 3.0],
+                engine_type: EngineType::Complex,
+                name: None,
+                init: Init {
+                    azimuth: 170.0,
+                    p0_n: [0.0, 0.0, -1.0]
+                }
+            }
+        );
+
+        assert_eq!(ps.get_struct::<f64>("/rocket/mass")?, 2.5);
+        assert!(ps
+            .get_struct::<HashMap<String, f32>>("/rocket/init/p0_n")
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_struct_errors() -> Result<(), Error> {
+        let mut ps = ParameterService::from_toml(
+            "[rocket]
+            mass = {val=2.5, dtype=\"f64\"}
+            steps = {val=10, dtype=\"u8\"}
+            inertia = {val=[1, 2, 3], dtype=\"f32\"}
+            engine_type = {val=\"complex\", dtype=\"string\"}
+
+            [rocket.init]
+            azimuth = {val=170, dtype=\"f64\"}
+            p0_n = {val=[0, 0, -1], dtype=\"f64\"}
+            ",
+        )?;
+
+        assert_eq!(
+            ps.get_struct::<Rocket>("/missing").err(),
+            Some(Error::NotFound("/missing".into()))
+        );
+
+        let error_path = |res: Result<Rocket, Error>| match res {
+            Err(Error::Struct { path, .. }) => Some(path),
+            _ => None,
+        };
+
+        ps.remove(&"/rocket/init/azimuth".into())?;
+        assert_eq!(
+            error_path(ps.get_struct("/rocket")),
+            Some("/rocket/init/azimuth".to_string())
+        );
+
+        ps.set(
+            &"/rocket/init/azimuth".into(),
+            Parameter::String("x".into()),
+        )?;
+        assert_eq!(
+            error_path(ps.get_struct("/rocket")),
+            Some("/rocket/init/azimuth".to_string())
+        );
+
+        ps.set(&"/rocket/init/azimuth".into(), Parameter::F64(1.0))?;
+        ps.set(
+            &"/rocket/inertia".into(),
+            Parameter::List(vec![Parameter::F32(1.0), Parameter::Bool(true)]),
+        )?;
+        assert_eq!(
+            error_path(ps.get_struct("/rocket")),
+            Some("/rocket/inertia[1]".to_string())
+        );
+
+        ps.set(
+            &"/rocket/inertia".into(),
+            Parameter::List(vec![Parameter::F32(1.0)]),
+        )?;
+        ps.set(
+            &"/rocket/init/p0_n".into(),
+            Parameter::List(vec![Parameter::F64(1.0)]),
+        )?;
+        assert_eq!(
+            error_path(ps.get_struct("/rocket")),
+            Some("/rocket/init/p0_n".to_string())
+        );
+
+        ps.set(
+            &"/rocket/init/p0_n".into(),
+            Parameter::List(vec![Parameter::F64(1.0); 3]),
+        )?;
+        ps.set(&"/rocket/engine_type".into(), Parameter::String("x".into()))?;
+        assert_eq!(
+            error_path(ps.get_struct("/rocket")),
+            Some("/rocket/engine_type".to_string())
+        );
+
+        Ok(())
+    }
+}
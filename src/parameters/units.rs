@@ -0,0 +1,234 @@
+use std::f64::consts::PI;
+
+use nalgebra::Vector3;
+
+use super::{Error, ParameterService};
+use crate::core::path::Path;
+
+/// Number of base dimensions: length, mass, time, current, temperature, angle
+const DIMS: usize = 6;
+
+/// A unit, as a scale factor to SI units and the exponents of the base dimensions.
+///
+/// Angles are a base dimension, so that degrees cannot be converted to a length, or deg/s to Hz.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct Unit {
+    scale: f64,
+    dims: [i32; DIMS],
+}
+
+/// Named units: symbol, scale to SI, dimension exponents
+const UNITS: &[(&str, f64, [i32; DIMS])] = &[
+    // Length
+    ("m", 1.0, [1, 0, 0, 0, 0, 0]),
+    ("km", 1e3, [1, 0, 0, 0, 0, 0]),
+    ("cm", 1e-2, [1, 0, 0, 0, 0, 0]),
+    ("mm", 1e-3, [1, 0, 0, 0, 0, 0]),
+    ("ft", 0.3048, [1, 0, 0, 0, 0, 0]),
+    ("in", 0.0254, [1, 0, 0, 0, 0, 0]),
+    ("mi", 1609.344, [1, 0, 0, 0, 0, 0]),
+    ("nmi", 1852.0, [1, 0, 0, 0, 0, 0]),
+    // Mass
+    ("kg", 1.0, [0, 1, 0, 0, 0, 0]),
+    ("g", 1e-3, [0, 1, 0, 0, 0, 0]),
+    ("lb", 0.453_592_37, [0, 1, 0, 0, 0, 0]),
+    // Time
+    ("s", 1.0, [0, 0, 1, 0, 0, 0]),
+    ("ms", 1e-3, [0, 0, 1, 0, 0, 0]),
+    ("us", 1e-6, [0, 0, 1, 0, 0, 0]),
+    ("min", 60.0, [0, 0, 1, 0, 0, 0]),
+    ("h", 3600.0, [0, 0, 1, 0, 0, 0]),
+    ("Hz", 1.0, [0, 0, -1, 0, 0, 0]),
+    // Electric current
+    ("A", 1.0, [0, 0, 0, 1, 0, 0]),
+    ("mA", 1e-3, [0, 0, 0, 1, 0, 0]),
+    // Temperature, only as a difference: there is no offset
+    ("K", 1.0, [0, 0, 0, 0, 1, 0]),
+    // Angle
+    ("rad", 1.0, [0, 0, 0, 0, 0, 1]),
+    ("deg", PI / 180.0, [0, 0, 0, 0, 0, 1]),
+    ("rev", 2.0 * PI, [0, 0, 0, 0, 0, 1]),
+    ("rpm", 2.0 * PI / 60.0, [0, 0, -1, 0, 0, 1]),
+    // Derived
+    ("N", 1.0, [1, 1, -2, 0, 0, 0]),
+    ("kN", 1e3, [1, 1, -2, 0, 0, 0]),
+    ("lbf", 4.448_221_615_260_5, [1, 1, -2, 0, 0, 0]),
+    ("Pa", 1.0, [-1, 1, -2, 0, 0, 0]),
+    ("hPa", 1e2, [-1, 1, -2, 0, 0, 0]),
+    ("kPa", 1e3, [-1, 1, -2, 0, 0, 0]),
+    ("bar", 1e5, [-1, 1, -2, 0, 0, 0]),
+    ("psi", 6_894.757_293_168_36, [-1, 1, -2, 0, 0, 0]),
+    ("J", 1.0, [2, 1, -2, 0, 0, 0]),
+    ("W", 1.0, [2, 1, -3, 0, 0, 0]),
+    ("V", 1.0, [2, 1, -3, -1, 0, 0]),
+];
+
+impl Unit {
+    /// Parses a product of named units, each optionally raised to an integer power, such as
+    /// `kg*m^2`, `m/s^2` or `deg/s`. Everything after a `/` is in the denominator. `1` is the
+    /// dimensionless unit.
+    pub(super) fn parse(s: &str) -> Result<Unit, String> {
+        let mut unit = Unit {
+            scale: 1.0,
+            dims: [0; DIMS],
+        };
+
+        let (num, den) = match s.split_once('/') {
+            Some((num, den)) => (num, Some(den)),
+            None => (s, None),
+        };
+
+        for (factors, sign) in [(num, 1), (den.unwrap_or("1"), -1)] {
+            for factor in factors.split('*').map(str::trim) {
+                let (name, exp) = match factor.split_once('^') {
+                    Some((name, exp)) => {
+                        let exp = exp
+                            .trim()
+                            .parse::<i32>()
+                            .map_err(|_| format!("bad exponent '{exp}'"))?;
+                        (name.trim(), exp)
+                    }
+                    None => (factor, 1),
+                };
+
+                if name == "1" {
+                    continue;
+                }
+
+                let (_, scale, dims) = UNITS
+                    .iter()
+                    .find(|(n, ..)| *n == name)
+                    .ok_or_else(|| format!("unknown unit '{name}'"))?;
+
+                let exp = exp * sign;
+                unit.scale *= scale.powi(exp);
+                for (d, e) in unit.dims.iter_mut().zip(dims) {
+                    *d += e * exp;
+                }
+            }
+        }
+
+        Ok(unit)
+    }
+
+    /// Factor to multiply a value in this unit by to express it in `to`, `None` if the
+    /// dimensions are different
+    pub(super) fn factor_to(&self, to: &Unit) -> Option<f64> {
+        (self.dims == to.dims).then(|| self.scale / to.scale)
+    }
+}
+
+impl ParameterService {
+    /// Gets a f64 parameter converted to `unit`, from the unit declared in its schema
+    pub fn get_f64_in(&self, path: &str, unit: &str) -> Result<f64, Error> {
+        Ok(self.get_f64(path)? * self.unit_factor(path, unit)?)
+    }
+
+    /// Gets a list of f64 converted to `unit`, from the unit declared in its schema
+    pub fn get_vec_f64_in(&self, path: &str, unit: &str) -> Result<Vec<f64>, Error> {
+        let factor = self.unit_factor(path, unit)?;
+        Ok(self
+            .get_vec_f64(path)?
+            .into_iter()
+            .map(|v| v * factor)
+            .collect())
+    }
+
+    pub fn get_vector3_in(&self, path: &str, unit: &str) -> Result<Vector3<f64>, Error> {
+        Ok(self.get_vector3(path)? * self.unit_factor(path, unit)?)
+    }
+
+    fn unit_factor(&self, path: &str, unit: &str) -> Result<f64, Error> {
+        let to = Unit::parse(unit).map_err(|e| Error::BadUnit(unit.to_string(), e))?;
+
+        let from = self
+            .schema(path)?
+            .and_then(|s| s.unit)
+            .ok_or_else(|| Error::NoUnit(Path::from_str(path).unwrap(), unit.to_string()))?;
+
+        // Already checked by set_schema()
+        let from_unit = Unit::parse(&from).map_err(|e| Error::BadUnit(from.clone(), e))?;
+
+        from_unit
+            .factor_to(&to)
+            .ok_or_else(|| Error::IncompatibleUnits {
+                path: Path::from_str(path).unwrap(),
+                from,
+                to: unit.to_string(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(v: f64, from: &str, to: &str) -> Option<f64> {
+        let from = Unit::parse(from).unwrap();
+        let to = Unit::parse(to).unwrap();
+        from.factor_to(&to).map(|f| v * f)
+    }
+
+    #[test]
+    fn test_units() {
+        let close = |a: Option<f64>, b: f64| (a.unwrap() - b).abs() < 1e-9 * b.abs().max(1.0);
+
+        assert!(close(convert(180.0, "deg", "rad"), PI));
+        assert!(close(convert(250.0, "ms", "s"), 0.25));
+        assert!(close(convert(1000.0, "ft", "m"), 304.8));
+        assert!(close(convert(90.0, "deg/s", "rad/s"), PI / 2.0));
+        assert!(close(convert(1.0, "N*s", "kg*m/s"), 1.0));
+        assert!(close(convert(2.0, "kg*m^2", "g*cm^2"), 2e7));
+        assert!(close(convert(9.81, "m/s^2", "ft/s^2"), 9.81 / 0.3048));
+        assert!(close(convert(60.0, "rpm", "rad/s"), 2.0 * PI));
+        assert!(close(convert(1.0, "Hz", "1/s"), 1.0));
+
+        assert_eq!(convert(1.0, "deg", "m"), None);
+        assert_eq!(convert(1.0, "deg/s", "Hz"), None);
+        assert_eq!(convert(1.0, "N", "kg*m/s"), None);
+
+        assert!(Unit::parse("furlong").is_err());
+        assert!(Unit::parse("m^x").is_err());
+    }
+
+    #[test]
+    fn test_get_in() -> Result<(), Error> {
+        let ps = ParameterService::from_toml(
+            "azimuth = {val=90, dtype=\"f64\", unit=\"deg\"}
+            w0 = {val=[0, 180, 360], dtype=\"f64\", unit=\"deg/s\"}
+            count = {val=2.0, dtype=\"f64\"}
+            ",
+        )?;
+
+        assert!((ps.get_f64_in("/azimuth", "rad")? - PI / 2.0).abs() < 1e-12);
+        assert_eq!(ps.get_f64_in("/azimuth", "deg")?, 90.0);
+        assert!(
+            (ps.get_vector3_in("/w0", "rad/s")? - Vector3::new(0.0, PI, 2.0 * PI)).norm() < 1e-12
+        );
+        let w0 = ps.get_vec_f64_in("/w0", "deg/min")?;
+        assert!(w0
+            .iter()
+            .zip([0.0, 10800.0, 21600.0])
+            .all(|(a, b)| (a - b).abs() < 1e-9));
+
+        assert!(matches!(
+            ps.get_f64_in("/azimuth", "m"),
+            Err(Error::IncompatibleUnits { .. })
+        ));
+        assert!(matches!(
+            ps.get_f64_in("/count", "m"),
+            Err(Error::NoUnit(..))
+        ));
+        assert!(matches!(
+            ps.get_f64_in("/azimuth", "furlong"),
+            Err(Error::BadUnit(..))
+        ));
+
+        assert!(matches!(
+            ParameterService::from_toml("x = {val=1.0, dtype=\"f64\", unit=\"furlong\"}"),
+            Err(Error::InvalidSchema(..))
+        ));
+
+        Ok(())
+    }
+}
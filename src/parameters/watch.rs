@@ -0,0 +1,124 @@
+use std::{
+    num::NonZero,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    core::path::Path,
+    utils::{
+        capacity::Capacity,
+        ringchannel::{
+            channel, ChannelError, Receiver, SelectGroup, SelectToken, Selectable, Sender,
+        },
+    },
+};
+
+/// Receives notifications when a parameter, or any parameter in a subtree, is changed with
+/// `ParameterService::set()`.
+///
+/// Changes can either be polled through the version counter (`has_changed()`), or consumed one
+/// by one as the paths of the parameters that were set (`try_recv()`, `recv()`). A watch is
+/// also `Selectable`, so it can be waited on together with telemetry receivers.
+///
+/// At most `WATCH_CAPACITY` paths are kept: if they are not consumed, the oldest ones are
+/// dropped, while the version keeps counting every change.
+#[derive(Debug)]
+pub struct ParameterWatch {
+    path: Path,
+    version: Arc<AtomicU64>,
+    seen: u64,
+    receiver: Receiver<Path>,
+}
+
+pub const WATCH_CAPACITY: NonZero<usize> = NonZero::new(64).unwrap();
+
+#[derive(Debug)]
+pub(super) struct WatchEntry {
+    path: Path,
+    version: Arc<AtomicU64>,
+    sender: Sender<Path>,
+}
+
+impl WatchEntry {
+    pub(super) fn new(path: Path) -> (WatchEntry, ParameterWatch) {
+        let (sender, receiver) = channel::<Path>(Capacity::Bounded(WATCH_CAPACITY));
+        let version = Arc::new(AtomicU64::new(0));
+
+        (
+            WatchEntry {
+                path: path.clone(),
+                version: version.clone(),
+                sender,
+            },
+            ParameterWatch {
+                path,
+                version,
+                seen: 0,
+                receiver,
+            },
+        )
+    }
+
+    /// Notifies the watch if `changed` is inside the watched subtree, or if it is one of its
+    /// parents (setting a parent replaces the whole subtree)
+    pub(super) fn notify(&self, changed: &Path) {
+        let related = self
+            .path
+            .iter_parts()
+            .zip(changed.iter_parts())
+            .all(|(a, b)| a == b);
+
+        if related {
+            self.version.fetch_add(1, Ordering::Release);
+            self.sender.send(changed.clone());
+        }
+    }
+
+    /// The watch has been dropped, nobody is listening anymore
+    pub(super) fn is_orphan(&self) -> bool {
+        Arc::strong_count(&self.version) == 1
+    }
+}
+
+impl ParameterWatch {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of changes to the watched subtree since the watch was created
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// Returns true if the watched subtree changed since the last call to this function
+    pub fn has_changed(&mut self) -> bool {
+        let version = self.version();
+        let changed = version != self.seen;
+        self.seen = version;
+
+        changed
+    }
+
+    /// Path of the next parameter that was set, if any
+    pub fn try_recv(&self) -> Result<Path, ChannelError> {
+        self.receiver.try_recv()
+    }
+
+    /// Blocks until a parameter in the watched subtree is set
+    pub fn recv(&self) -> Result<Path, ChannelError> {
+        self.receiver.recv()
+    }
+}
+
+impl Selectable for ParameterWatch {
+    fn register(&self, token: SelectToken, handle: SelectGroup) {
+        self.receiver.register(token, handle)
+    }
+
+    fn unregister(&self) {
+        self.receiver.unregister()
+    }
+}
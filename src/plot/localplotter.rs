@@ -61,7 +61,7 @@ impl LocalPlotter {
         }
     }
 
-    pub fn plot_channel<T: ReflectMessage + Default + 'static>(
+    pub fn plot_channel<T: ReflectMessage + Default + Clone + 'static>(
         &mut self,
         signals: &mut PlotSignals,
         channel: &str,
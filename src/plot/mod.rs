@@ -1,4 +1,5 @@
 pub mod localplotter;
+pub mod parameditor;
 pub mod plotter;
 
 use rust_data_inspector::PlotSignalError;
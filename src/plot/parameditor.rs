@@ -0,0 +1,294 @@
+use std::fs;
+
+use egui::{CollapsingHeader, ComboBox, DragValue, Response, ScrollArea, Ui};
+
+use crate::{
+    core::path::Path,
+    parameters::{Parameter, ParameterSchema, ParameterService},
+};
+
+/// Side panel to edit a copy of the parameters of a simulation, restart the simulation with
+/// them, or save them to a parameter file.
+///
+/// Edits are validated against the parameter schemas, and are only applied to the copy: the
+/// running simulation is not affected.
+pub struct ParameterEditor {
+    draft: Option<ParameterService>,
+    save_path: String,
+    status: Option<Result<String, String>>,
+}
+
+impl ParameterEditor {
+    /// `save_path` is the default path of the file written by "Save as TOML"
+    pub fn new(save_path: &str) -> Self {
+        ParameterEditor {
+            draft: None,
+            save_path: save_path.to_string(),
+            status: None,
+        }
+    }
+
+    /// Draws the editor. `current` are the parameters of the last run, edited if nothing is being
+    /// edited yet. Returns the parameters to restart the simulation with, if requested.
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        current: Option<&ParameterService>,
+        can_restart: bool,
+    ) -> Option<ParameterService> {
+        if self.draft.is_none() {
+            if let Some(current) = current {
+                self.reset(current);
+            }
+        }
+
+        let mut restart = None;
+
+        ui.horizontal(|ui| {
+            let restart_button = egui::Button::new("↻ Restart with these parameters");
+            if ui
+                .add_enabled(can_restart && self.draft.is_some(), restart_button)
+                .clicked()
+            {
+                restart = self.draft.as_ref().map(ParameterService::deep_copy);
+            }
+
+            if ui
+                .add_enabled(current.is_some(), egui::Button::new("Discard edits"))
+                .clicked()
+            {
+                if let Some(current) = current {
+                    self.reset(current);
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.save_path);
+
+            if ui
+                .add_enabled(self.draft.is_some(), egui::Button::new("Save as TOML"))
+                .clicked()
+            {
+                self.save();
+            }
+        });
+
+        match &self.status {
+            Some(Ok(msg)) => {
+                ui.label(msg);
+            }
+            Some(Err(msg)) => {
+                ui.colored_label(ui.visuals().error_fg_color, msg);
+            }
+            None => (),
+        }
+
+        ui.separator();
+
+        if let Some(draft) = self.draft.as_mut() {
+            let leaves: Vec<_> = draft
+                .get(&Path::from_str("/").unwrap())
+                .map(|root| root.iter().map(|(p, v)| (p, v.clone())).collect())
+                .unwrap_or_default();
+
+            let edit = ScrollArea::vertical()
+                .show(ui, |ui| tree_ui(ui, draft, &leaves, 0))
+                .inner;
+
+            if let Some((path, value)) = edit {
+                let res = draft
+                    .set(&path.as_str().into(), value)
+                    .map(|_| format!("Set {path}"));
+                self.status = Some(res.map_err(|e| e.to_string()));
+            }
+        }
+
+        restart
+    }
+
+    fn reset(&mut self, current: &ParameterService) {
+        self.status = None;
+
+        self.draft = Some(current.deep_copy());
+    }
+
+    fn save(&mut self) {
+        let Some(draft) = &self.draft else {
+            return;
+        };
+
+        let res = draft
+            .to_toml()
+            .map_err(|e| e.to_string())
+            .and_then(|toml| fs::write(&self.save_path, toml).map_err(|e| e.to_string()))
+            .map(|_| format!("Saved to {}", self.save_path));
+
+        self.status = Some(res);
+    }
+}
+
+/// Draws the leaves (as returned by `Parameter::iter()`, so sorted and grouped by parent) whose
+/// paths share the first `depth` parts, nesting the maps below them in collapsing headers.
+/// Returns the parameter that was edited, if any.
+fn tree_ui(
+    ui: &mut Ui,
+    params: &ParameterService,
+    leaves: &[(String, Parameter)],
+    depth: usize,
+) -> Option<(String, Parameter)> {
+    let part = |path: &str| {
+        Path::split_parts(path)
+            .nth(depth)
+            .unwrap_or_default()
+            .to_string()
+    };
+    let is_leaf = |path: &str| Path::split_parts(path).count() == depth + 1;
+
+    let mut edit = None;
+    let mut i = 0;
+
+    while i < leaves.len() {
+        let (path, value) = &leaves[i];
+        let name = part(path);
+
+        if is_leaf(path) {
+            let schema = params.schema(path).ok().flatten();
+            if let Some(value) = leaf_ui(ui, path, &name, value, schema.as_ref()) {
+                edit = Some((path.clone(), value));
+            }
+
+            i += 1;
+            continue;
+        }
+
+        let end = i + leaves[i..]
+            .iter()
+            .take_while(|(p, _)| !is_leaf(p) && part(p) == name)
+            .count();
+
+        let id: Vec<_> = Path::split_parts(path).take(depth + 1).collect();
+        let group = CollapsingHeader::new(&name)
+            .id_salt(id)
+            .show(ui, |ui| tree_ui(ui, params, &leaves[i..end], depth + 1));
+
+        edit = edit.or(group.body_returned.flatten());
+        i = end;
+    }
+
+    edit
+}
+
+fn leaf_ui(
+    ui: &mut Ui,
+    path: &str,
+    name: &str,
+    value: &Parameter,
+    schema: Option<&ParameterSchema>,
+) -> Option<Parameter> {
+    let mut edited = value.clone();
+
+    let changed = ui
+        .horizontal(|ui| {
+            let label = ui.label(name);
+            if let Some(description) = schema.and_then(|s| s.description.as_ref()) {
+                label.on_hover_text(description);
+            }
+
+            let res = value_ui(ui, path, &mut edited, schema);
+
+            if let Some(unit) = schema.and_then(|s| s.unit.as_ref()) {
+                ui.label(unit);
+            }
+
+            res.changed()
+        })
+        .inner;
+
+    (changed && edited != *value).then_some(edited)
+}
+
+/// Widget matching the type of `value`, constrained by the schema if there is one. `path` is
+/// the id of the widgets that need one.
+fn value_ui(
+    ui: &mut Ui,
+    path: &str,
+    value: &mut Parameter,
+    schema: Option<&ParameterSchema>,
+) -> Response {
+    match value {
+        Parameter::Bool(v) => ui.checkbox(v, ""),
+        Parameter::U8(v) => number_ui(ui, v, 1.0, schema),
+        Parameter::U16(v) => number_ui(ui, v, 1.0, schema),
+        Parameter::U32(v) => number_ui(ui, v, 1.0, schema),
+        Parameter::U64(v) => number_ui(ui, v, 1.0, schema),
+        Parameter::I8(v) => number_ui(ui, v, 1.0, schema),
+        Parameter::I16(v) => number_ui(ui, v, 1.0, schema),
+        Parameter::I32(v) => number_ui(ui, v, 1.0, schema),
+        Parameter::I64(v) => number_ui(ui, v, 1.0, schema),
+        Parameter::F32(v) => {
+            let speed = float_speed(*v as f64);
+            number_ui(ui, v, speed, schema)
+        }
+        Parameter::F64(v) => {
+            let speed = float_speed(*v);
+            number_ui(ui, v, speed, schema)
+        }
+        Parameter::String(v) => match schema.and_then(|s| s.values.as_ref()) {
+            Some(values) => string_choice_ui(ui, path, v, values),
+            None => ui.text_edit_singleline(v),
+        },
+        Parameter::List(l) => {
+            ui.horizontal(|ui| {
+                let mut res = ui.label("[");
+                for (i, v) in l.iter_mut().enumerate() {
+                    res |= ui.push_id(i, |ui| value_ui(ui, path, v, schema)).inner;
+                }
+                res | ui.label("]")
+            })
+            .inner
+        }
+        // Not reached, maps are drawn as collapsing headers
+        Parameter::Map(_) => ui.label("{...}"),
+    }
+}
+
+fn number_ui<T: egui::emath::Numeric>(
+    ui: &mut Ui,
+    v: &mut T,
+    speed: f64,
+    schema: Option<&ParameterSchema>,
+) -> Response {
+    let min = schema.and_then(|s| s.min).unwrap_or(f64::NEG_INFINITY);
+    let max = schema.and_then(|s| s.max).unwrap_or(f64::INFINITY);
+
+    ui.add(
+        DragValue::new(v)
+            .speed(speed)
+            .range(min.max(T::MIN.to_f64())..=max.min(T::MAX.to_f64())),
+    )
+}
+
+/// Dragging changes floats by about 1% of their value per pixel
+fn float_speed(v: f64) -> f64 {
+    (v.abs() * 0.01).max(1e-6)
+}
+
+fn string_choice_ui(ui: &mut Ui, path: &str, v: &mut String, values: &[Parameter]) -> Response {
+    let mut changed = false;
+
+    let mut res = ComboBox::from_id_salt(path)
+        .selected_text(v.as_str())
+        .show_ui(ui, |ui| {
+            for choice in values.iter().filter_map(|p| p.as_string()) {
+                changed |= ui.selectable_value(v, choice.clone(), choice).changed();
+            }
+        })
+        .response;
+
+    if changed {
+        res.mark_changed();
+    }
+
+    res
+}
@@ -10,7 +10,9 @@ use crate::{
     core::time::Timestamp,
     utils::{
         capacity::Capacity,
-        ringchannel::{channel, Channel, ChannelError, Receiver, SelectToken, Selectable, Sender},
+        ringchannel::{
+            channel_with, Backend, Channel, ChannelError, Receiver, SelectToken, Selectable, Sender,
+        },
     },
 };
 
@@ -30,6 +32,9 @@ pub enum TelemetryError {
 
     #[error("Provided channel name is not valid")]
     InvalidChannelName,
+
+    #[error("Cannot change the backend of a channel that already exists")]
+    ChannelAlreadyCreated,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -46,6 +51,14 @@ impl<T: 'static + Clone> TelemetrySender<T> {
     }
 }
 
+impl<T: 'static> TelemetrySender<Arc<T>> {
+    /// Wraps `value` in an `Arc` once, so that every subscriber receives a reference to the same
+    /// payload instead of a deep copy. Meant for large messages, see `publish_shared()`.
+    pub fn send_shared(&self, timestamp: Timestamp, value: T) {
+        self.sender.send(Timestamped(timestamp, Arc::new(value)));
+    }
+}
+
 #[derive(Debug)]
 pub struct TelemetryReceiver<T> {
     receiver: Receiver<Timestamped<T>>,
@@ -83,8 +96,8 @@ struct TelemetryChannelTransport<T> {
 }
 
 impl TelemetryChannel {
-    fn new<T: 'static + Send>(name: &str) -> Self {
-        let (sender, _) = channel::<Timestamped<T>>(Capacity::Unbounded);
+    fn new<T: 'static + Send + Sync + Clone>(name: &str, backend: Backend) -> Self {
+        let (sender, _) = channel_with::<Timestamped<T>>(backend, Capacity::Unbounded);
 
         let transport = TelemetryChannelTransport::<T> {
             channel: Arc::downgrade(&sender.get_channel()),
@@ -152,6 +165,7 @@ pub struct TelemetryService {
 #[derive(Debug, Default)]
 pub struct TelemetryServiceInner {
     remap: HashMap<String, String>,
+    backends: HashMap<String, Backend>,
     channels: HashMap<String, TelemetryChannel>,
 }
 
@@ -160,27 +174,72 @@ impl TelemetryService {
         TelemetryService {
             inner: Arc::new(Mutex::new(TelemetryServiceInner {
                 remap,
+                backends: HashMap::new(),
                 channels: HashMap::new(),
             })),
         }
     }
+
+    /// Selects the backend of a channel, before it is published or subscribed to. Channels use
+    /// `Backend::Queues` by default, `Backend::Ring` suits high rate channels such as the IMU.
+    pub fn set_backend(&self, channel_name: &str, backend: Backend) -> Result<(), TelemetryError> {
+        let mut inner = self.inner.lock().unwrap();
+
+        if inner.channels.contains_key(channel_name) {
+            return Err(TelemetryError::ChannelAlreadyCreated);
+        }
+
+        inner.backends.insert(channel_name.to_string(), backend);
+        Ok(())
+    }
 }
 
 pub trait TelemetryDispatcher {
-    fn publish<T: 'static + Send>(
+    fn publish<T: 'static + Send + Sync + Clone>(
         &self,
         channel_name: &str,
     ) -> Result<TelemetrySender<T>, TelemetryError>;
 
-    fn subscribe<T: 'static + Send>(
+    fn subscribe<T: 'static + Send + Sync + Clone>(
         &self,
         channel_name: &str,
         capacity: Capacity,
     ) -> Result<TelemetryReceiver<T>, TelemetryError>;
+
+    /// Publishes a channel whose payload is shared among all the subscribers instead of being
+    /// cloned for each one of them. The channel type is `Arc<T>`, so it can only be subscribed
+    /// with `subscribe_shared::<T>()` (or `subscribe::<Arc<T>>()`).
+    fn publish_shared<T: 'static + Send + Sync>(
+        &self,
+        channel_name: &str,
+    ) -> Result<TelemetrySender<Arc<T>>, TelemetryError> {
+        self.publish::<Arc<T>>(channel_name)
+    }
+
+    fn subscribe_shared<T: 'static + Send + Sync>(
+        &self,
+        channel_name: &str,
+        capacity: Capacity,
+    ) -> Result<TelemetryReceiver<Arc<T>>, TelemetryError> {
+        self.subscribe::<Arc<T>>(channel_name, capacity)
+    }
+
+    /// Publishes a channel that keeps the last value sent, and delivers it to subscribers as
+    /// soon as they subscribe, even if they subscribe after it was sent. Meant for values that
+    /// rarely change, which late subscribers would otherwise never see.
+    fn publish_latched<T: 'static + Send + Sync + Clone>(
+        &self,
+        channel_name: &str,
+    ) -> Result<TelemetrySender<T>, TelemetryError> {
+        let sender = self.publish::<T>(channel_name)?;
+        sender.sender.latch();
+
+        Ok(sender)
+    }
 }
 
 impl TelemetryDispatcher for TelemetryService {
-    fn publish<T: 'static + Send>(
+    fn publish<T: 'static + Send + Sync + Clone>(
         &self,
         channel_name: &str,
     ) -> Result<TelemetrySender<T>, TelemetryError> {
@@ -198,7 +257,7 @@ impl TelemetryDispatcher for TelemetryService {
         channel.take_producer()
     }
 
-    fn subscribe<T: 'static + Send>(
+    fn subscribe<T: 'static + Send + Sync + Clone>(
         &self,
         channel_name: &str,
         capacity: Capacity,
@@ -211,14 +270,16 @@ impl TelemetryDispatcher for TelemetryService {
 }
 
 impl TelemetryServiceInner {
-    fn get_channel<'a, T: 'static + Send>(
+    fn get_channel<'a, T: 'static + Send + Sync + Clone>(
         &'a mut self,
         channel_name: &str,
     ) -> &'a mut TelemetryChannel {
         if !self.channels.contains_key(channel_name) {
+            let backend = self.backends.get(channel_name).copied().unwrap_or_default();
+
             self.channels.insert(
                 channel_name.to_string(),
-                TelemetryChannel::new::<T>(channel_name),
+                TelemetryChannel::new::<T>(channel_name, backend),
             );
         }
 
@@ -384,6 +445,83 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_shared_payload() -> Result<(), TelemetryError> {
+        let telem_service = TelemetryService::default();
+
+        let sub1 = telem_service.subscribe_shared::<Vec<f64>>("/test/channel/1", 1usize.into())?;
+        let sub2 = telem_service.subscribe_shared::<Vec<f64>>("/test/channel/1", 1usize.into())?;
+
+        let prod = telem_service.publish_shared::<Vec<f64>>("/test/channel/1")?;
+
+        let ts = Timestamp::now(&SystemClock::default());
+        prod.send_shared(ts, vec![1.0; 1000]);
+
+        let Timestamped(ts1, v1) = sub1.try_recv()?;
+        let Timestamped(ts2, v2) = sub2.try_recv()?;
+
+        assert_eq!(ts1, ts);
+        assert_eq!(ts2, ts);
+        assert_eq!(*v1, vec![1.0; 1000]);
+        assert!(Arc::ptr_eq(&v1, &v2));
+
+        // Shared and non-shared channels are different types
+        assert!(telem_service
+            .subscribe::<Vec<f64>>("/test/channel/1", 1usize.into())
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_latched() -> Result<(), TelemetryError> {
+        let telem_service = TelemetryService::default();
+
+        let early = telem_service.subscribe::<f64>("/test/channel/1", 1usize.into())?;
+        let prod = telem_service.publish_latched::<f64>("/test/channel/1")?;
+
+        let ts = Timestamp::now(&SystemClock {});
+        prod.send(ts, 1.0);
+        prod.send(ts, 2.0);
+
+        assert_eq!(early.try_recv(), Ok(Timestamped(ts, 2.0)));
+        assert_eq!(early.try_recv(), Err(TelemetryError::EmptyChannel));
+
+        let late = telem_service.subscribe::<f64>("/test/channel/1", 1usize.into())?;
+        assert_eq!(late.try_recv(), Ok(Timestamped(ts, 2.0)));
+        assert_eq!(late.try_recv(), Err(TelemetryError::EmptyChannel));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ring_backend() -> Result<(), TelemetryError> {
+        let telem_service = TelemetryService::default();
+        let size = std::num::NonZero::new(2).unwrap();
+
+        telem_service.set_backend("/test/channel/1", Backend::Ring(size))?;
+
+        let sub = telem_service.subscribe::<f64>("/test/channel/1", 3usize.into())?;
+        let prod = telem_service.publish::<f64>("/test/channel/1")?;
+
+        let ts = Timestamp::now(&SystemClock {});
+        prod.send(ts, 1.0);
+        prod.send(ts, 2.0);
+        prod.send(ts, 3.0);
+
+        // Limited by the size of the ring
+        assert_eq!(sub.try_recv(), Ok(Timestamped(ts, 2.0)));
+        assert_eq!(sub.try_recv(), Ok(Timestamped(ts, 3.0)));
+        assert_eq!(sub.try_recv(), Err(TelemetryError::EmptyChannel));
+
+        assert_eq!(
+            telem_service.set_backend("/test/channel/1", Backend::Queues),
+            Err(TelemetryError::ChannelAlreadyCreated)
+        );
+
+        Ok(())
+    }
+
     use anyhow::Result;
 
     #[test]
@@ -1,6 +1,8 @@
 use thiserror::Error;
 
 use std::{
+    cell::Cell,
+    marker::PhantomData,
     num::NonZero,
     sync::{Arc, Condvar, Mutex},
 };
@@ -10,6 +12,7 @@ use crate::utils::capacity::Capacity;
 use super::{
     buffer::Buffer,
     select::{SelectGroup, SelectToken, Selectable},
+    spmc::{Ring, RingReceiver},
 };
 
 #[derive(Debug, Error, PartialEq, Eq)]
@@ -21,9 +24,29 @@ pub enum ChannelError {
     Empty,
 }
 
+/// How a channel stores the messages and delivers them to the receivers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Every receiver has its own queue protected by a mutex, and the sender clones each message
+    /// into every queue
+    #[default]
+    Queues,
+
+    /// Lock-free ring of the given size shared by all the receivers, which clone the messages
+    /// they read. Meant for high rate channels with small messages. Receivers cannot hold more
+    /// messages than the ring, even if their capacity is larger.
+    Ring(NonZero<usize>),
+}
+
 #[derive(Debug)]
 pub struct Channel<T> {
-    inner: Mutex<ChannelInner<T>>,
+    flavor: Flavor<T>,
+}
+
+#[derive(Debug)]
+enum Flavor<T> {
+    Queues(Mutex<ChannelInner<T>>),
+    Ring(Ring<T>),
 }
 
 #[derive(Debug)]
@@ -31,14 +54,51 @@ struct ChannelInner<T> {
     receivers: Vec<(usize, Arc<ReceiverShared<T>>)>,
     counter: usize,
     is_closed: bool,
+    latch: Option<Latch<T>>,
+}
+
+/// Last value written to a latched channel, replayed to the receivers added after it
+#[derive(Debug)]
+struct Latch<T> {
+    value: Option<T>,
+
+    // Stored here because adding a receiver does not require `T: Clone`
+    clone: fn(&T) -> T,
 }
 
 impl<T: Clone> Channel<T> {
     fn write(&self, data: T) {
-        let receivers = &self.inner.lock().unwrap().receivers;
+        let inner = match &self.flavor {
+            Flavor::Queues(inner) => inner,
+            Flavor::Ring(ring) => return ring.write(data),
+        };
 
-        for (_, receiver) in receivers.iter() {
-            receiver.write(data.clone());
+        let mut inner = inner.lock().unwrap();
+
+        if let Some(latch) = inner.latch.as_mut() {
+            latch.value = Some(data.clone());
+        }
+
+        let receivers = &inner.receivers;
+
+        // The last receiver gets the original value, so there is one less clone
+        if let Some(((_, last), others)) = receivers.split_last() {
+            for (_, receiver) in others.iter() {
+                receiver.write(data.clone());
+            }
+            last.write(data);
+        }
+    }
+
+    fn latch(&self) {
+        match &self.flavor {
+            Flavor::Queues(inner) => {
+                inner.lock().unwrap().latch.get_or_insert(Latch {
+                    value: None,
+                    clone: T::clone,
+                });
+            }
+            Flavor::Ring(ring) => ring.latch(),
         }
     }
 }
@@ -46,41 +106,82 @@ impl<T: Clone> Channel<T> {
 impl<T> Default for Channel<T> {
     fn default() -> Self {
         Self {
-            inner: Mutex::new(ChannelInner {
+            flavor: Flavor::Queues(Mutex::new(ChannelInner {
                 receivers: vec![],
                 counter: 0usize,
                 is_closed: false,
-            }),
+                latch: None,
+            })),
         }
     }
 }
 
 impl<T> Channel<T> {
     pub fn add_receiver(capacity: Capacity, this: &Arc<Channel<T>>) -> Receiver<T> {
-        let mut inner = this.inner.lock().unwrap();
+        let inner = match &this.flavor {
+            Flavor::Queues(inner) => inner,
+            Flavor::Ring(ring) => {
+                let capacity = match capacity {
+                    Capacity::Unbounded => ring.size(),
+                    Capacity::Bounded(capacity) => capacity.min(ring.size()),
+                };
+
+                return Receiver {
+                    end: ReceiverEnd::Ring(ring.add_receiver(capacity)),
+                    capacity: Capacity::Bounded(capacity),
+                    channel: this.clone(),
+                };
+            }
+        };
+
+        let mut inner = inner.lock().unwrap();
 
         let index = inner.counter;
         inner.counter += 1;
 
         let shared = Arc::new(ReceiverShared::<T>::new(capacity, inner.is_closed));
 
+        if let Some(Latch {
+            value: Some(value),
+            clone,
+        }) = &inner.latch
+        {
+            shared.write(clone(value));
+        }
+
         inner.receivers.push((index, shared.clone()));
 
         Receiver {
-            shared,
-            channel_index: index,
+            end: ReceiverEnd::Queue {
+                shared,
+                channel_index: index,
+            },
             capacity,
             channel: this.clone(),
         }
     }
 
+    pub fn backend(&self) -> Backend {
+        match &self.flavor {
+            Flavor::Queues(_) => Backend::Queues,
+            Flavor::Ring(ring) => Backend::Ring(ring.size()),
+        }
+    }
+
     fn remove_receiver(&self, index: usize) {
-        let mut inner = self.inner.lock().unwrap();
-        inner.receivers.retain(|(i, _)| *i != index);
+        if let Flavor::Queues(inner) = &self.flavor {
+            let mut inner = inner.lock().unwrap();
+            inner.receivers.retain(|(i, _)| *i != index);
+        }
     }
 
     fn close(&self) {
-        let mut inner = self.inner.lock().unwrap();
+        let inner = match &self.flavor {
+            Flavor::Queues(inner) => inner,
+            Flavor::Ring(ring) => return ring.close(),
+        };
+
+        let mut inner = inner.lock().unwrap();
 
         inner.is_closed = true;
 
@@ -98,19 +199,30 @@ impl<T> Channel<T> {
 
     #[allow(dead_code)]
     fn num_receivers(&self) -> usize {
-        let inner = self.inner.lock().unwrap();
-        inner.receivers.len()
+        match &self.flavor {
+            Flavor::Queues(inner) => inner.lock().unwrap().receivers.len(),
+            // Receivers of a ring only hold a cursor, they are not registered with the channel
+            Flavor::Ring(_) => 0,
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct Receiver<T> {
-    shared: Arc<ReceiverShared<T>>,
-    channel_index: usize,
+    end: ReceiverEnd<T>,
     capacity: Capacity,
     channel: Arc<Channel<T>>,
 }
 
+#[derive(Debug)]
+enum ReceiverEnd<T> {
+    Queue {
+        shared: Arc<ReceiverShared<T>>,
+        channel_index: usize,
+    },
+    Ring(RingReceiver),
+}
+
 #[derive(Debug)]
 struct ReceiverShared<T> {
     inner: Mutex<ReceiverInner<T>>,
@@ -163,16 +275,23 @@ impl<T> Clone for Receiver<T> {
 
 impl<T> Drop for Receiver<T> {
     fn drop(&mut self) {
-        self.channel.remove_receiver(self.channel_index);
+        if let ReceiverEnd::Queue { channel_index, .. } = &self.end {
+            self.channel.remove_receiver(*channel_index);
+        }
     }
 }
 
 impl<T> Receiver<T> {
     pub fn recv(&self) -> Result<T, ChannelError> {
-        let inner = self.shared.inner.lock().unwrap();
+        let shared = match (&self.end, &self.channel.flavor) {
+            (ReceiverEnd::Queue { shared, .. }, _) => shared,
+            (ReceiverEnd::Ring(end), Flavor::Ring(ring)) => return ring.recv(end),
+            _ => unreachable!(),
+        };
+
+        let inner = shared.inner.lock().unwrap();
 
-        let mut inner = self
-            .shared
+        let mut inner = shared
             .cv
             .wait_while(inner, |inner| inner.buf.is_empty() && !inner.closed)
             .unwrap();
@@ -194,7 +313,13 @@ impl<T> Receiver<T> {
     }
 
     pub fn try_recv(&self) -> Result<T, ChannelError> {
-        let mut inner = self.shared.inner.lock().unwrap();
+        let shared = match (&self.end, &self.channel.flavor) {
+            (ReceiverEnd::Queue { shared, .. }, _) => shared,
+            (ReceiverEnd::Ring(end), Flavor::Ring(ring)) => return ring.try_recv(end),
+            _ => unreachable!(),
+        };
+
+        let mut inner = shared.inner.lock().unwrap();
 
         if inner.closed && inner.buf.is_empty() {
             if let Some((tk, handle)) = &inner.select_handle {
@@ -215,6 +340,7 @@ impl<T> Receiver<T> {
         Channel::<T>::add_receiver(capacity, &self.channel)
     }
 
+    /// Capacity of the receiver, which for the `Ring` backend is at most the size of the ring
     pub fn capacity(&self) -> Capacity {
         self.capacity
     }
@@ -222,7 +348,15 @@ impl<T> Receiver<T> {
 
 impl<T> Selectable for Receiver<T> {
     fn register(&self, token: SelectToken, handle: SelectGroup) {
-        let mut inner = self.shared.inner.lock().unwrap();
+        let shared = match (&self.end, &self.channel.flavor) {
+            (ReceiverEnd::Queue { shared, .. }, _) => shared,
+            (ReceiverEnd::Ring(end), Flavor::Ring(ring)) => {
+                return ring.register(end, token, handle)
+            }
+            _ => unreachable!(),
+        };
+
+        let mut inner = shared.inner.lock().unwrap();
 
         debug_assert!(inner.select_handle.is_none());
 
@@ -230,7 +364,13 @@ impl<T> Selectable for Receiver<T> {
     }
 
     fn unregister(&self) {
-        let mut inner = self.shared.inner.lock().unwrap();
+        let shared = match (&self.end, &self.channel.flavor) {
+            (ReceiverEnd::Queue { shared, .. }, _) => shared,
+            (ReceiverEnd::Ring(end), Flavor::Ring(ring)) => return ring.unregister(end),
+            _ => unreachable!(),
+        };
+
+        let mut inner = shared.inner.lock().unwrap();
 
         debug_assert!(inner.select_handle.is_some());
 
@@ -238,9 +378,12 @@ impl<T> Selectable for Receiver<T> {
     }
 }
 
+/// Sending side of the channel. There can only be one, so it is neither `Clone` nor `Sync`.
 #[derive(Debug)]
 pub struct Sender<T> {
     channel: Arc<Channel<T>>,
+
+    not_sync: PhantomData<Cell<()>>,
 }
 
 impl<T> Drop for Sender<T> {
@@ -253,6 +396,12 @@ impl<T: Clone> Sender<T> {
     pub fn send(&self, val: T) {
         self.channel.write(val);
     }
+
+    /// Makes the channel keep the last value sent, and deliver it to every receiver added
+    /// afterwards, as soon as it is added. Values sent before calling this are not kept.
+    pub fn latch(&self) {
+        self.channel.latch();
+    }
 }
 
 impl<T> Sender<T> {
@@ -265,7 +414,31 @@ pub fn channel<T>(capacity: Capacity) -> (Sender<T>, Receiver<T>) {
     let channel = Arc::new(Channel::<T>::default());
 
     let receiver = Channel::<T>::add_receiver(capacity, &channel);
-    let sender = Sender { channel };
+    let sender = Sender {
+        channel,
+        not_sync: PhantomData,
+    };
+
+    (sender, receiver)
+}
+
+/// Creates a channel with the given backend. `capacity` is the one of the returned receiver.
+pub fn channel_with<T: Clone + Send + Sync>(
+    backend: Backend,
+    capacity: Capacity,
+) -> (Sender<T>, Receiver<T>) {
+    let channel = Arc::new(match backend {
+        Backend::Queues => Channel::default(),
+        Backend::Ring(size) => Channel {
+            flavor: Flavor::Ring(Ring::new(size)),
+        },
+    });
+
+    let receiver = Channel::<T>::add_receiver(capacity, &channel);
+    let sender = Sender {
+        channel,
+        not_sync: PhantomData,
+    };
 
     (sender, receiver)
 }
@@ -418,6 +591,36 @@ mod tests {
         assert_eq!(r2.try_recv(), Ok(2.2));
     }
 
+    #[test]
+    fn test_latch() {
+        let (s, r) = channel::<f32>(Capacity::Bounded(NonZero::new(2).unwrap()));
+
+        s.send(1.1);
+        assert_eq!(r.clone().try_recv(), Err(ChannelError::Empty));
+
+        s.latch();
+        assert_eq!(r.clone().try_recv(), Err(ChannelError::Empty));
+
+        s.send(1.2);
+        s.send(1.3);
+
+        let r2 = r.clone();
+        assert_eq!(r2.try_recv(), Ok(1.3));
+        assert_eq!(r2.try_recv(), Err(ChannelError::Empty));
+
+        // Existing receivers get the values as usual
+        assert_eq!(r.try_recv(), Ok(1.2));
+        assert_eq!(r.try_recv(), Ok(1.3));
+        assert_eq!(r.try_recv(), Err(ChannelError::Empty));
+
+        // The latched value is still there after the sender is gone
+        let channel = s.get_channel();
+        drop(s);
+        let r3 = Channel::<f32>::add_receiver(Capacity::Unbounded, &channel);
+        assert_eq!(r3.try_recv(), Ok(1.3));
+        assert_eq!(r3.try_recv(), Err(ChannelError::Closed));
+    }
+
     #[test]
     fn test_thread_send() {
         let (s, r) = channel::<f32>(Capacity::Bounded(NonZero::new(2).unwrap()));
@@ -1,6 +1,7 @@
 mod channel;
 mod select;
 mod buffer;
+mod spmc;
 
 pub use channel::*;
 
@@ -0,0 +1,689 @@
+use std::{
+    cell::UnsafeCell,
+    num::NonZero,
+    ptr,
+    sync::{
+        atomic::{self, AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+};
+
+use super::{
+    select::{SelectGroup, SelectToken},
+    ChannelError,
+};
+
+/// Number of attempts to read an empty channel before a blocking `recv()` goes to sleep
+const SPIN_LIMIT: usize = 128;
+
+/// Number of retired nodes a slot may hold before the sender waits for its receivers to be done
+/// with them
+const MAX_RETIRED: usize = 4;
+
+/// A message stored in the ring, with its sequence number
+struct Node<T> {
+    n: u64,
+    value: T,
+}
+
+/// A single slot of the ring. Message `n` is stored in slot `n % capacity`, in its own heap
+/// allocation, so that the sender never writes to memory that a receiver may be reading.
+struct Slot<T> {
+    node: AtomicPtr<Node<T>>,
+
+    /// Number of receivers currently cloning the node of this slot
+    readers: AtomicUsize,
+
+    /// Nodes replaced while a receiver may still have been reading them, at most `MAX_RETIRED`.
+    /// Only accessed by the sender, see `Ring::write`.
+    retired: UnsafeCell<Vec<*mut Node<T>>>,
+}
+
+/// Lock-free single producer, multiple consumer broadcast ring buffer, the backend of the
+/// channels created with `Backend::Ring`.
+///
+/// The sender never takes a lock unless some receiver is blocked in `recv()` or registered in a
+/// `Select`, and it does not clone the messages: each receiver clones the messages it reads.
+/// Every receiver has its own read cursor: a receiver that falls behind by more than its
+/// capacity skips to the oldest message it can hold, same as the bounded queues.
+pub(super) struct Ring<T> {
+    slots: Box<[Slot<T>]>,
+
+    /// Number of messages written so far
+    head: AtomicU64,
+    closed: AtomicBool,
+
+    /// First message kept for the receivers added later, `u64::MAX` if the ring is not latched
+    latched_from: AtomicU64,
+
+    /// Number of blocked receivers plus receivers registered in a select
+    num_watchers: AtomicUsize,
+    watchers: Mutex<Vec<Watcher>>,
+    cv: Condvar,
+
+    receiver_counter: AtomicUsize,
+
+    // Stored here because receiving does not require `T: Clone`
+    clone: fn(&T) -> T,
+}
+
+struct Watcher {
+    receiver_index: usize,
+    cursor: Arc<AtomicU64>,
+    capacity: u64,
+    select_handle: (SelectToken, SelectGroup),
+}
+
+/// Read position of a receiver of a `Ring`
+#[derive(Debug)]
+pub(super) struct RingReceiver {
+    cursor: Arc<AtomicU64>,
+
+    /// How far the receiver may fall behind, at most the size of the ring
+    capacity: u64,
+    index: usize,
+    is_selected: AtomicBool,
+}
+
+// SAFETY: receivers on different threads clone the messages through shared references, which
+// requires `T: Sync`. It is not required here, but by `Ring::new()`, the only way to create a
+// ring, so that channels keep the same auto traits whatever their backend. The raw pointers are
+// owned by the ring, and `retired` is only accessed by the single sender.
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T> Ring<T> {
+    pub(super) fn new(size: NonZero<usize>) -> Self
+    where
+        T: Clone + Send + Sync,
+    {
+        Self {
+            slots: (0..size.get())
+                .map(|_| Slot {
+                    node: AtomicPtr::new(ptr::null_mut()),
+                    readers: AtomicUsize::new(0),
+                    retired: UnsafeCell::new(vec![]),
+                })
+                .collect(),
+            head: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
+            latched_from: AtomicU64::new(u64::MAX),
+            num_watchers: AtomicUsize::new(0),
+            watchers: Mutex::new(vec![]),
+            cv: Condvar::default(),
+            receiver_counter: AtomicUsize::new(0),
+            clone: T::clone,
+        }
+    }
+
+    pub(super) fn size(&self) -> NonZero<usize> {
+        NonZero::new(self.slots.len()).unwrap()
+    }
+
+    fn slot(&self, n: u64) -> &Slot<T> {
+        &self.slots[(n % self.slots.len() as u64) as usize]
+    }
+
+    fn pending(head: u64, cursor: u64, capacity: u64) -> usize {
+        head.saturating_sub(cursor).min(capacity) as usize
+    }
+
+    /// Must only be called by the single sender
+    pub(super) fn write(&self, data: T) {
+        let n = self.head.load(Ordering::Relaxed);
+        let slot = self.slot(n);
+
+        let node = Box::into_raw(Box::new(Node { n, value: data }));
+        let old = slot.node.swap(node, Ordering::SeqCst);
+
+        // SAFETY: only the single sender accesses `retired`
+        let retired = unsafe { &mut *slot.retired.get() };
+        if !old.is_null() {
+            retired.push(old);
+        }
+
+        // A receiver increments `readers` before loading the node pointer: if it loaded one of
+        // the retired nodes, it did so before the swap above, so it is counted here until it is
+        // done cloning. Receivers arriving later can only load the new node.
+        if slot.readers.load(Ordering::SeqCst) == 0 || retired.len() > MAX_RETIRED {
+            // Slow receivers could keep the slot busy at every write: past `MAX_RETIRED`, wait for
+            // them. Without new messages they stop reading once they catch up, so this only lasts
+            // as long as cloning the messages still in the ring.
+            while slot.readers.load(Ordering::SeqCst) != 0 {
+                std::thread::yield_now();
+            }
+
+            for node in retired.drain(..) {
+                // SAFETY: allocated with `Box::into_raw`, and no receiver can access it anymore
+                drop(unsafe { Box::from_raw(node) });
+            }
+        }
+
+        self.head.store(n + 1, Ordering::Release);
+
+        // Pairs with the fence in `wait()`: either the waiter sees the new head, or we see it
+        atomic::fence(Ordering::SeqCst);
+        if self.num_watchers.load(Ordering::Relaxed) > 0 {
+            self.notify();
+        }
+    }
+
+    fn try_read(&self, receiver: &RingReceiver) -> Option<T> {
+        let mut c = receiver.cursor.load(Ordering::Relaxed);
+
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            if c >= head {
+                return None;
+            }
+
+            // Skip messages that were overwritten, or that do not fit in the receiver capacity
+            c = c.max(head.saturating_sub(receiver.capacity));
+
+            let slot = self.slot(c);
+            slot.readers.fetch_add(1, Ordering::SeqCst);
+            let node = slot.node.load(Ordering::SeqCst);
+
+            // SAFETY: message `c` was published before `head` went past it, so the pointer is not
+            // null, and the sender does not free a node while we are counted in `readers`
+            let node = unsafe { &*node };
+            let value = (node.n == c).then(|| (self.clone)(&node.value));
+
+            slot.readers.fetch_sub(1, Ordering::Release);
+
+            if let Some(value) = value {
+                receiver.cursor.store(c + 1, Ordering::Release);
+                return Some(value);
+            }
+
+            // The sender lapped us while reading: try again from the oldest available message
+            std::hint::spin_loop();
+        }
+    }
+
+    fn notify(&self) {
+        let watchers = self.watchers.lock().unwrap();
+        let head = self.head.load(Ordering::SeqCst);
+
+        for w in watchers.iter() {
+            let (tk, handle) = &w.select_handle;
+            handle.update(
+                *tk,
+                Self::pending(head, w.cursor.load(Ordering::Acquire), w.capacity),
+            );
+        }
+
+        self.cv.notify_all();
+    }
+
+    fn wait(&self, receiver: &RingReceiver) {
+        self.num_watchers.fetch_add(1, Ordering::Relaxed);
+        atomic::fence(Ordering::SeqCst);
+
+        let watchers = self.watchers.lock().unwrap();
+        let _watchers = self
+            .cv
+            .wait_while(watchers, |_| {
+                self.head.load(Ordering::SeqCst) <= receiver.cursor.load(Ordering::Relaxed)
+                    && !self.closed.load(Ordering::SeqCst)
+            })
+            .unwrap();
+
+        self.num_watchers.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub(super) fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+
+        let watchers = self.watchers.lock().unwrap();
+        for w in watchers.iter() {
+            let (tk, handle) = &w.select_handle;
+            handle.close(*tk);
+        }
+
+        self.cv.notify_all();
+    }
+
+    /// Keeps the last message written from now on for the receivers added afterwards
+    pub(super) fn latch(&self) {
+        let head = self.head.load(Ordering::Acquire);
+        let _ =
+            self.latched_from
+                .compare_exchange(u64::MAX, head, Ordering::AcqRel, Ordering::Acquire);
+    }
+
+    pub(super) fn add_receiver(&self, capacity: NonZero<usize>) -> RingReceiver {
+        let index = self.receiver_counter.fetch_add(1, Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+
+        // Latched rings replay their last message
+        let start = match head.checked_sub(1) {
+            Some(last) if last >= self.latched_from.load(Ordering::Acquire) => last,
+            _ => head,
+        };
+
+        RingReceiver {
+            cursor: Arc::new(AtomicU64::new(start)),
+            capacity: capacity.min(self.size()).get() as u64,
+            index,
+            is_selected: AtomicBool::new(false),
+        }
+    }
+
+    pub(super) fn recv(&self, receiver: &RingReceiver) -> Result<T, ChannelError> {
+        let mut spins = 0;
+        loop {
+            match self.try_recv(receiver) {
+                // Spin for a little while before blocking, so that the sender does not have to
+                // take the lock to wake us up when we are keeping up with it
+                Err(ChannelError::Empty) if spins < SPIN_LIMIT => {
+                    if spins < SPIN_LIMIT / 2 {
+                        std::hint::spin_loop();
+                    } else {
+                        std::thread::yield_now();
+                    }
+                    spins += 1;
+                }
+                Err(ChannelError::Empty) => self.wait(receiver),
+                res => return res,
+            }
+        }
+    }
+
+    pub(super) fn try_recv(&self, receiver: &RingReceiver) -> Result<T, ChannelError> {
+        // Read the flag first: everything sent before closing is still delivered
+        let closed = self.closed.load(Ordering::SeqCst);
+
+        match self.try_read(receiver) {
+            Some(v) => {
+                self.update_select(receiver);
+                Ok(v)
+            }
+            None if closed => {
+                self.ack_close(receiver);
+                Err(ChannelError::Closed)
+            }
+            None => Err(ChannelError::Empty),
+        }
+    }
+
+    fn update_select(&self, receiver: &RingReceiver) {
+        if !receiver.is_selected.load(Ordering::Relaxed) {
+            return;
+        }
+
+        // Updated with the lock held, so we cannot overwrite a more recent count from the sender
+        let watchers = self.watchers.lock().unwrap();
+        let head = self.head.load(Ordering::SeqCst);
+
+        if let Some(w) = watchers.iter().find(|w| w.receiver_index == receiver.index) {
+            let (tk, handle) = &w.select_handle;
+            handle.update(
+                *tk,
+                Self::pending(
+                    head,
+                    receiver.cursor.load(Ordering::Relaxed),
+                    receiver.capacity,
+                ),
+            );
+        }
+    }
+
+    fn ack_close(&self, receiver: &RingReceiver) {
+        if !receiver.is_selected.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let watchers = self.watchers.lock().unwrap();
+        if let Some(w) = watchers.iter().find(|w| w.receiver_index == receiver.index) {
+            let (tk, handle) = &w.select_handle;
+            handle.ack_close(*tk);
+        }
+    }
+
+    pub(super) fn register(
+        &self,
+        receiver: &RingReceiver,
+        token: SelectToken,
+        handle: SelectGroup,
+    ) {
+        let mut watchers = self.watchers.lock().unwrap();
+
+        debug_assert!(!receiver.is_selected.load(Ordering::Relaxed));
+
+        let head = self.head.load(Ordering::SeqCst);
+        handle.update(
+            token,
+            Self::pending(
+                head,
+                receiver.cursor.load(Ordering::Relaxed),
+                receiver.capacity,
+            ),
+        );
+        if self.closed.load(Ordering::SeqCst) {
+            handle.close(token);
+        }
+
+        watchers.push(Watcher {
+            receiver_index: receiver.index,
+            cursor: receiver.cursor.clone(),
+            capacity: receiver.capacity,
+            select_handle: (token, handle),
+        });
+
+        self.num_watchers.fetch_add(1, Ordering::SeqCst);
+        receiver.is_selected.store(true, Ordering::Relaxed);
+    }
+
+    pub(super) fn unregister(&self, receiver: &RingReceiver) {
+        let mut watchers = self.watchers.lock().unwrap();
+
+        debug_assert!(receiver.is_selected.load(Ordering::Relaxed));
+
+        watchers.retain(|w| w.receiver_index != receiver.index);
+
+        self.num_watchers.fetch_sub(1, Ordering::SeqCst);
+        receiver.is_selected.store(false, Ordering::Relaxed);
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        for slot in self.slots.iter_mut() {
+            let node = *slot.node.get_mut();
+            let nodes = slot.retired.get_mut().drain(..).chain(Some(node));
+
+            for node in nodes.filter(|node| !node.is_null()) {
+                // SAFETY: allocated with `Box::into_raw`, and there is nobody left to read it
+                drop(unsafe { Box::from_raw(node) });
+            }
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for Ring<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ring")
+            .field("size", &self.slots.len())
+            .field("head", &self.head)
+            .field("closed", &self.closed)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+    use crate::utils::{
+        capacity::Capacity,
+        ringchannel::{channel_with, Backend, Channel, Receiver, Select, Sender},
+    };
+
+    fn ring<T: Clone + Send + Sync>(size: usize) -> (Sender<T>, Receiver<T>) {
+        channel_with(
+            Backend::Ring(NonZero::new(size).unwrap()),
+            Capacity::Unbounded,
+        )
+    }
+
+    #[test]
+    fn test_simple_channel() {
+        let (s, r_recv) = ring::<f32>(2);
+
+        let r_try = r_recv.clone();
+
+        assert_eq!(r_try.try_recv(), Err(ChannelError::Empty));
+
+        s.send(1.1);
+        assert_eq!(r_recv.recv(), Ok(1.1));
+        assert_eq!(r_try.try_recv(), Ok(1.1));
+
+        s.send(1.2);
+        assert_eq!(r_recv.recv(), Ok(1.2));
+        assert_eq!(r_try.try_recv(), Ok(1.2));
+
+        assert_eq!(r_try.try_recv(), Err(ChannelError::Empty));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let (s, r) = ring::<f32>(2);
+        s.send(1.1);
+        s.send(1.2);
+
+        assert_eq!(r.recv(), Ok(1.1));
+        assert_eq!(r.recv(), Ok(1.2));
+
+        assert_eq!(r.try_recv(), Err(ChannelError::Empty));
+
+        s.send(1.1);
+        s.send(1.2);
+        s.send(1.3);
+
+        assert_eq!(r.recv(), Ok(1.2));
+        assert_eq!(r.recv(), Ok(1.3));
+        assert_eq!(r.try_recv(), Err(ChannelError::Empty));
+    }
+
+    #[test]
+    fn test_receiver_capacity() {
+        let (s, r) = ring::<String>(4);
+        let r1 = r.clone_with_capacity(Capacity::Bounded(NonZero::new(1).unwrap()));
+
+        // Limited by the size of the ring
+        assert_eq!(r.capacity(), Capacity::Bounded(NonZero::new(4).unwrap()));
+
+        for i in 0..6 {
+            s.send(i.to_string());
+        }
+
+        let all: Vec<_> = std::iter::from_fn(|| r.try_recv().ok()).collect();
+        assert_eq!(all, ["2", "3", "4", "5"]);
+
+        assert_eq!(r1.try_recv(), Ok("5".to_string()));
+        assert_eq!(r1.try_recv(), Err(ChannelError::Empty));
+    }
+
+    #[test]
+    fn test_multiple_receiver() {
+        let (s, r) = ring::<f32>(2);
+
+        s.send(1.1);
+        assert_eq!(r.recv(), Ok(1.1));
+
+        let r2 = r.clone();
+
+        s.send(1.2);
+        s.send(1.3);
+
+        assert_eq!(r.recv(), Ok(1.2));
+        assert_eq!(r.recv(), Ok(1.3));
+
+        assert_eq!(r2.recv(), Ok(1.2));
+        assert_eq!(r2.recv(), Ok(1.3));
+
+        drop(s);
+        assert_eq!(r.recv(), Err(ChannelError::Closed));
+        assert_eq!(r2.recv(), Err(ChannelError::Closed));
+    }
+
+    #[test]
+    fn test_drain_after_close() {
+        let (s, r) = ring::<i32>(4);
+
+        s.send(1);
+        s.send(2);
+        drop(s);
+
+        assert_eq!(r.try_recv(), Ok(1));
+        assert_eq!(r.recv(), Ok(2));
+        assert_eq!(r.try_recv(), Err(ChannelError::Closed));
+    }
+
+    #[test]
+    fn test_latch() {
+        let (s, r) = ring::<f32>(2);
+
+        s.send(1.1);
+        assert_eq!(r.clone().try_recv(), Err(ChannelError::Empty));
+
+        s.latch();
+        assert_eq!(r.clone().try_recv(), Err(ChannelError::Empty));
+
+        s.send(1.2);
+        s.send(1.3);
+
+        let r2 = r.clone();
+        assert_eq!(r2.try_recv(), Ok(1.3));
+        assert_eq!(r2.try_recv(), Err(ChannelError::Empty));
+
+        let channel = s.get_channel();
+        drop(s);
+        let r3 = Channel::add_receiver(Capacity::Unbounded, &channel);
+        assert_eq!(r3.try_recv(), Ok(1.3));
+        assert_eq!(r3.try_recv(), Err(ChannelError::Closed));
+    }
+
+    #[test]
+    fn test_thread_send() {
+        let (s, r) = ring::<f32>(2);
+
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            s.send(1.1);
+        });
+
+        assert_eq!(r.recv(), Ok(1.1));
+
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_thread_drop() {
+        let (s, r) = ring::<f32>(2);
+
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(s);
+        });
+
+        assert_eq!(r.recv(), Err(ChannelError::Closed));
+
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_in_order() {
+        const N: u64 = 100000;
+
+        // Heap allocated messages, so that a message freed too early would be noticed
+        let (s, r) = ring::<Vec<u64>>(16);
+        let receivers: Vec<_> = (0..3).map(|_| r.clone()).collect();
+
+        let handles: Vec<_> = receivers
+            .into_iter()
+            .map(|r| {
+                thread::spawn(move || {
+                    let mut last = None;
+                    while let Ok(v) = r.recv() {
+                        // Values are never torn and never go backwards, but may be skipped
+                        assert!(v.iter().all(|x| *x == v[0]));
+                        assert!(last.is_none_or(|l| v[0] > l));
+                        last = Some(v[0]);
+                    }
+                    last
+                })
+            })
+            .collect();
+
+        for i in 0..N {
+            s.send(vec![i; 4]);
+        }
+        drop(s);
+
+        for h in handles {
+            assert_eq!(h.join().unwrap(), Some(N - 1));
+        }
+    }
+
+    /// Counts the live instances, and is slow to clone
+    struct Slow;
+
+    static LIVE_SLOW: AtomicUsize = AtomicUsize::new(0);
+
+    impl Slow {
+        fn new() -> Self {
+            LIVE_SLOW.fetch_add(1, Ordering::SeqCst);
+            Self
+        }
+    }
+
+    impl Clone for Slow {
+        fn clone(&self) -> Self {
+            thread::sleep(Duration::from_micros(50));
+            Self::new()
+        }
+    }
+
+    impl Drop for Slow {
+        fn drop(&mut self) {
+            LIVE_SLOW.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_retired_bounded() {
+        const READERS: usize = 4;
+
+        // With a single slot, the slow receivers are almost always cloning its node when the
+        // sender replaces it
+        let (s, r) = ring::<Slow>(1);
+        let handles: Vec<_> = (0..READERS)
+            .map(|_| {
+                let r = r.clone();
+                thread::spawn(move || while r.recv().is_ok() {})
+            })
+            .collect();
+
+        let mut max_live = 0;
+        for _ in 0..2000 {
+            s.send(Slow::new());
+            max_live = max_live.max(LIVE_SLOW.load(Ordering::SeqCst));
+        }
+
+        // The node in the ring, the retired ones, and at most two clones per receiver
+        assert!(max_live <= 1 + MAX_RETIRED + 2 * READERS, "{max_live}");
+
+        drop((s, r));
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(LIVE_SLOW.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_select() {
+        let (s1, r1) = ring::<i32>(1);
+        let (s2, r2) = ring::<i32>(1);
+
+        let mut select = Select::default();
+        select.add(&r1);
+        select.add(&r2);
+
+        s2.send(1);
+        assert_eq!(select.ready(), 1);
+        assert_eq!(r2.recv(), Ok(1));
+        assert_eq!(select.try_ready(), Err(ChannelError::Empty));
+
+        s1.send(1);
+        assert_eq!(select.ready(), 0);
+        assert_eq!(r1.recv(), Ok(1));
+
+        drop(s1);
+        assert_eq!(select.ready(), 0);
+        assert_eq!(r1.recv(), Err(ChannelError::Closed));
+        assert_eq!(select.try_ready(), Err(ChannelError::Empty));
+    }
+}